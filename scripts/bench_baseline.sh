@@ -0,0 +1,44 @@
+#!/bin/sh
+
+# Runs the vault bench in src/rust/bench.rs on a baseline commit and on the
+# working tree, one after the other on the same machine. The baseline is
+# exported to a temporary directory and built there, nothing is added to the
+# repository or its target directory, and the directory is removed on exit.
+# usage: scripts/bench_baseline.sh [baseline commit, the first by default]
+
+set -e
+
+root=$(git rev-parse --show-toplevel)
+baseline=$(git rev-parse --short "${1:-$(git rev-list --max-parents=0 HEAD)}")
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT INT TERM
+
+mkdir "$tmp/baseline"
+git -C "$root" archive "$baseline" | tar -x -C "$tmp/baseline"
+
+# the baseline has neither the bench nor the feature it counts allocations with
+cp "$root/src/rust/bench.rs" "$tmp/baseline/src/rust/bench.rs"
+cat >> "$tmp/baseline/src/rust/lib.rs" <<'RUST'
+
+#[cfg(test)]
+mod bench;
+RUST
+cat >> "$tmp/baseline/Cargo.toml" <<'TOML'
+
+[features]
+count-allocations = []
+TOML
+if [ -f "$root/Cargo.lock" ]; then
+  cp "$root/Cargo.lock" "$tmp/baseline/Cargo.lock"
+fi
+
+bench() {
+  (cd "$1" && CARGO_TARGET_DIR="$2" cargo test --release --features count-allocations \
+    vault_bench -- --ignored --nocapture 2>/dev/null \
+    | sed -n -e 's/^test bench::vault_bench \.\.\. //p' -e '/^vault:/p' -e '/^heap use/p')
+}
+
+echo "baseline $baseline"
+bench "$tmp/baseline" "$tmp/target"
+echo "working tree $(git -C "$root" rev-parse --short HEAD)"
+bench "$root" "${CARGO_TARGET_DIR:-$root/target}"
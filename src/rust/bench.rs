@@ -0,0 +1,143 @@
+// Parse time and heap use of a synthetic vault. The file only uses what the
+// baseline commit has as well, scripts/bench_baseline.sh runs it on both.
+// Run it alone with
+// cargo test --release --features count-allocations vault_bench -- --ignored --nocapture
+// BENCH_NOTES sets the number of notes, 10000 by default.
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::prelude::*;
+use crate::VaultWrapper;
+
+// Counts the bytes held on the heap. Only built with the count-allocations
+// feature, it replaces the allocator of the whole test binary.
+#[cfg(feature = "count-allocations")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    pub static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+    pub struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr: *mut u8 = System.alloc(layout);
+            if !ptr.is_null() {
+                LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+            }
+            ptr
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            let ptr: *mut u8 = System.alloc_zeroed(layout);
+            if !ptr.is_null() {
+                LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new_ptr: *mut u8 = System.realloc(ptr, layout, new_size);
+            if !new_ptr.is_null() {
+                LIVE.fetch_add(new_size, Ordering::Relaxed);
+                LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+            }
+            new_ptr
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+}
+
+// bytes held on the heap, None when they are not counted
+fn live() -> Option<usize> {
+    #[cfg(feature = "count-allocations")]
+    return Some(counting::LIVE.load(std::sync::atomic::Ordering::Relaxed));
+    #[cfg(not(feature = "count-allocations"))]
+    None
+}
+
+// about 900 bytes of the markdown a vault is made of, linking to other notes
+fn synthetic_note(i: usize, notes: usize) -> String {
+    let (a, b): (usize, usize) = ((i * 7 + 1) % notes, (i * 13 + 5) % notes);
+    f!(
+        "---\naliases: [Topic {i}, T{i}]\ntags: [generated, batch-{batch}]\n---\n\
+         # Topic {i}\n\n\
+         Topic {i} builds on [[note {a}]] and is **closely related** to [[note {b}|its neighbour]].\n\
+         Some *emphasised* text, `inline code`, $x^2$ and a #tag/nested with a url https://example.com/{i}.\n\n\
+         ## Details\n\n\
+         - first point about topic {i}\n\
+         - second point with ==highlighted== words\n\
+         \x20 - a nested point, see [[note {b}#Details]]\n\
+         1. numbered step\n\
+         - [ ] an open task ^task-{i}\n\
+         - [x] a finished task\n\n\
+         > a quote about topic {a}\n\
+         > > nested inside it\n\n\
+         > [!note] Callout for {i}\n\
+         > with a second line\n\n\
+         | Column | Value |\n\
+         | :--- | ---: |\n\
+         | topic | {i} |\n\
+         | link | [[note {a}]] |\n\n\
+         ```rust\nfn topic_{i}() -> usize {{\n    {i}\n}}\n```\n\n\
+         $$\n\\sum_{{k=0}}^{{{i}}} k\n$$\n\n\
+         A closing paragraph[^{i}] that mentions note {a} and note {b} by name, \
+         with enough plain prose to look like a real note rather than a test fixture.\n\n\
+         [^{i}]: a footnote for topic {i}\n",
+        batch = i % 10,
+    )
+}
+
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+#[test]
+#[ignore]
+fn vault_bench() {
+    let notes: usize = std::env::var("BENCH_NOTES")
+        .ok()
+        .and_then(|notes| notes.parse().ok())
+        .unwrap_or(10_000);
+    let paths: Vec<PathBuf> = (0..notes)
+        .map(|i| PathBuf::from(f!("note {}.md", i)))
+        .collect();
+    let contents: Vec<String> = (0..notes).map(|i| synthetic_note(i, notes)).collect();
+    let source: usize = contents.iter().map(String::len).sum();
+
+    // the fastest of a few runs, the vault of the last is measured
+    let mut parse_time: Duration = Duration::MAX;
+    let mut held: Option<usize> = None;
+    for _ in 0..3 {
+        let before: Option<usize> = live();
+        let start: Instant = Instant::now();
+        let vault: VaultWrapper = VaultWrapper::new(paths.clone(), contents.clone());
+        parse_time = parse_time.min(start.elapsed());
+        held = live().zip(before).map(|(after, before)| after - before);
+        assert_eq!(vault.valid_files.len(), notes);
+        assert!(vault.invalid_files.is_empty());
+    }
+
+    println!(
+        "{} notes, {:.1} MiB of markdown, parsed in {:.2?}",
+        notes,
+        mib(source),
+        parse_time
+    );
+    match held {
+        Some(held) => println!(
+            "vault: {:.1} MiB held, {:.1}x the markdown",
+            mib(held),
+            held as f64 / source as f64
+        ),
+        None => println!("heap use is counted with --features count-allocations"),
+    }
+}
@@ -2,7 +2,10 @@
 
 use std::path::PathBuf;
 
+use crate::parser::ParseDiagnostic;
+
 #[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("Generic {0}")]
     Generic(String),
@@ -15,4 +18,16 @@ pub enum Error {
 
     #[error("{}", .1)]
     ParseError(PathBuf, String),
+
+    #[error("{}", .1)]
+    Diagnostic(PathBuf, Box<ParseDiagnostic>),
+}
+
+impl Error {
+    pub fn diagnostic(&self) -> Option<&ParseDiagnostic> {
+        match self {
+            Error::Diagnostic(_, diagnostic) => Some(diagnostic),
+            _ => None,
+        }
+    }
 }
@@ -3,6 +3,7 @@
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
+    rc::Rc,
     vec,
 };
 
@@ -13,6 +14,8 @@ use wasm_bindgen::prelude::*;
 
 use crate::prelude::*;
 
+#[cfg(test)]
+mod bench;
 mod error;
 mod link_finder;
 mod obsidian;
@@ -50,6 +53,7 @@ impl JsVault {
     }
 
     #[wasm_bindgen]
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Self {
         JsVault {
             files: VaultWrapper::default(),
@@ -63,15 +67,70 @@ impl JsVault {
         self.files.add_file(file_path, file_content);
     }
 
+    // replaces start..end of a file already in the vault, parsing again only around the change
+    #[wasm_bindgen]
+    pub fn edit_file(
+        &mut self,
+        file_path: JsString,
+        start: usize,
+        end: usize,
+        replacement: JsString,
+    ) -> bool {
+        let file_path = PathBuf::from(f!("{}", file_path));
+        let edit = crate::utils::TextEdit::new(start, end, f!("{}", replacement));
+        self.files.edit_file(file_path, &edit).is_ok()
+    }
+
+    // sets the text of a file, parsing again only around what changed when the vault has it
+    #[wasm_bindgen]
+    pub fn update_file(&mut self, file_path: JsString, file_content: JsString) {
+        let file_path = PathBuf::from(f!("{}", file_path));
+        let file_content = f!("{}", file_content);
+        self.files.update_file(file_path, file_content);
+    }
+
+    // the edit that wraps the text of a found link in a wikilink, without making it. shift
+    // is how many bytes the links added to the file before this one moved its text
+    #[wasm_bindgen]
+    pub fn preview_link(&self, link: &JsLink, shift: isize) -> Option<JsTextEdit> {
+        let file: &crate::vault::File = self.files.get_file(link.link.source.clone())?;
+        let (edit, _) = self.files.link_edit(&link.link, shift).ok()?;
+        Some(JsTextEdit::new(edit, &file.contents))
+    }
+
+    // makes the edit preview_link returns in the vault's copy of the file, get_contents
+    // then gives the text to write
+    #[wasm_bindgen]
+    pub fn add_link(&mut self, link: &JsLink, shift: isize) -> Option<JsTextEdit> {
+        let file: &crate::vault::File = self.files.get_file(link.link.source.clone())?;
+        let (edit, contents) = self.files.link_edit(&link.link, shift).ok()?;
+        let edit: JsTextEdit = JsTextEdit::new(edit, &file.contents);
+        self.files
+            .set_contents(link.link.source.clone(), contents)
+            .ok()?;
+        Some(edit)
+    }
+
+    // the text of a file as the vault has it, with the edits made to it
+    #[wasm_bindgen]
+    pub fn get_contents(&self, file_path: JsString) -> JsString {
+        let file_path = PathBuf::from(f!("{}", file_path));
+        match self.files.get_file(file_path) {
+            Some(file) => JsString::from(file.original()),
+            None => JsString::from(""),
+        }
+    }
+
     #[wasm_bindgen]
     pub fn get_file(&self, file_path: JsString) -> JsFile {
         let file_path = PathBuf::from(f!("{}", file_path));
-        let file_opt: Option<&crate::vault::File> = self.files.get_file(file_path);
+        // shared with the vault, not copied
+        let file_opt: Option<&Rc<crate::vault::File>> = self.files.get_file(file_path);
         let valid: bool = file_opt.is_some();
-        let mut file: crate::vault::File = crate::vault::File::default();
-        if valid {
-            file = file_opt.unwrap().clone();
-        }
+        let file: Rc<crate::vault::File> = match file_opt {
+            Some(file) => Rc::clone(file),
+            None => Rc::default(),
+        };
 
         JsFile { valid, file }
     }
@@ -89,11 +148,152 @@ impl JsVault {
         self.files
             .invalid_files
             .iter()
-            .map(|(path, error)| {
-                (JsFileError::new(
-                    JsString::from(format!("{}", path.display())),
-                    JsString::from(format!("{}", error)),
-                ))
+            .map(|(path, error)| JsFileError::from_error(path, error, None))
+            .collect::<Vec<JsFileError>>()
+    }
+    #[wasm_bindgen]
+    pub fn get_embeds(&self, file_path: JsString) -> Vec<JsString> {
+        let file_path = PathBuf::from(f!("{}", file_path));
+        self.files
+            .get_embeds(file_path)
+            .iter()
+            .map(|embed| JsString::from(embed.as_str()))
+            .collect()
+    }
+
+    // frontmatter properties and dataview inline fields as a json object
+    #[wasm_bindgen]
+    pub fn get_properties(&self, file_path: JsString) -> JsString {
+        let file_path = PathBuf::from(f!("{}", file_path));
+        let properties: serde_yaml::Mapping = self.files.get_properties(file_path);
+        JsString::from(serde_json::to_string(&properties).unwrap_or_else(|_| "{}".to_string()))
+    }
+
+    // the values of a frontmatter property that may be a single value or a list
+    #[wasm_bindgen]
+    pub fn get_frontmatter_list(&self, file_path: JsString, key: JsString) -> Vec<JsString> {
+        let file_path = PathBuf::from(f!("{}", file_path));
+        self.files
+            .get_frontmatter_list(file_path, &f!("{}", key))
+            .iter()
+            .map(|value| JsString::from(value.as_str()))
+            .collect()
+    }
+
+    // every external url in the vault, bare, autolinked or written as [text](url)
+    #[wasm_bindgen]
+    pub fn get_outbound_links(&self) -> Vec<JsOutboundLink> {
+        self.files
+            .get_outbound_links()
+            .into_iter()
+            .map(|(path, url)| JsOutboundLink {
+                path: path.clone(),
+                url,
+            })
+            .collect()
+    }
+
+    // paths of notes tagged with tag or one of its nested tags
+    #[wasm_bindgen]
+    pub fn get_files_with_tag(&self, tag: JsString) -> Vec<JsString> {
+        self.files
+            .get_files_with_tag(&f!("{}", tag))
+            .iter()
+            .map(|path| JsString::from(f!("{}", path.display())))
+            .collect()
+    }
+
+    // link is the inside of [[note#^id]], source is the note it appears in
+    #[wasm_bindgen]
+    pub fn resolve_block_reference(
+        &self,
+        source_path: JsString,
+        link: JsString,
+    ) -> Option<JsBlockReference> {
+        let source_path = PathBuf::from(f!("{}", source_path));
+        self.files
+            .resolve_block_reference(&source_path, &f!("{}", link))
+            .map(|(path, block_id)| JsBlockReference {
+                path: path.clone(),
+                start: block_id.start,
+                end: block_id.end,
+            })
+    }
+    #[wasm_bindgen]
+    pub fn get_fixes(&self, file_path: JsString) -> Vec<JsFix> {
+        let file_path = PathBuf::from(f!("{}", file_path));
+        let file: &crate::vault::File = match self.files.get_file(file_path.clone()) {
+            Some(file) => file,
+            None => return Vec::new(),
+        };
+        self.files
+            .get_fixes(file_path)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|fix| JsFix::new(fix, &file.contents))
+            .collect()
+    }
+
+    // contents with the fixes from get_fixes applied, so the search for them runs once
+    #[wasm_bindgen]
+    pub fn fix_file(&self, file_path: JsString, fixes: Vec<JsFix>) -> JsString {
+        let file_path = PathBuf::from(f!("{}", file_path));
+        let original: String = match self.files.get_file(file_path) {
+            Some(file) => file.original().to_string(),
+            None => return JsString::from(""),
+        };
+        let edits: Vec<crate::utils::TextEdit> =
+            fixes.into_iter().map(|fix| fix.fix.edit).collect();
+        let fixed: String = crate::utils::apply_edits(&original, &edits).unwrap_or(original);
+        JsString::from(fixed)
+    }
+
+    // edits that add value to the frontmatter list under key, creating the frontmatter if needed
+    #[wasm_bindgen]
+    pub fn add_frontmatter_item(
+        &self,
+        file_path: JsString,
+        key: JsString,
+        value: JsString,
+    ) -> Vec<JsTextEdit> {
+        self.frontmatter_edits(file_path, |file| {
+            file.add_frontmatter_item(&f!("{}", key), &f!("{}", value))
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_frontmatter_item(
+        &self,
+        file_path: JsString,
+        key: JsString,
+        value: JsString,
+    ) -> Vec<JsTextEdit> {
+        self.frontmatter_edits(file_path, |file| {
+            file.remove_frontmatter_item(&f!("{}", key), &f!("{}", value))
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn set_frontmatter_value(
+        &self,
+        file_path: JsString,
+        key: JsString,
+        value: JsString,
+    ) -> Vec<JsTextEdit> {
+        self.frontmatter_edits(file_path, |file| {
+            file.set_frontmatter_value(&f!("{}", key), &f!("{}", value))
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn get_warnings(&self) -> Vec<JsFileError> {
+        self.files
+            .warnings
+            .iter()
+            .map(|(path, warning)| {
+                let file: Option<&crate::vault::File> =
+                    self.files.get_file(path.clone()).map(|file| file.as_ref());
+                JsFileError::from_error(path, warning, file)
             })
             .collect::<Vec<JsFileError>>()
     }
@@ -103,6 +303,24 @@ impl JsVault {
 pub struct JsFileError {
     path: JsString,
     error: JsString,
+    diagnostic: Option<parser::ParseDiagnostic>,
+    fixable: bool,
+}
+
+impl JsFileError {
+    // file is the vault's copy of the note, only notes in the vault can be fixed
+    fn from_error(path: &Path, error: &Error, file: Option<&crate::vault::File>) -> JsFileError {
+        let fixable: bool = match (error.diagnostic(), file) {
+            (Some(diagnostic), Some(file)) => parser::can_fix(file.original(), diagnostic),
+            _ => false,
+        };
+        JsFileError {
+            path: JsString::from(format!("{}", path.display())),
+            error: JsString::from(format!("{}", error)),
+            diagnostic: error.diagnostic().cloned(),
+            fixable,
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -111,6 +329,8 @@ impl JsFileError {
         JsFileError {
             path: path.clone(),
             error: error.clone(),
+            diagnostic: None,
+            fixable: false,
         }
     }
     #[wasm_bindgen]
@@ -122,12 +342,246 @@ impl JsFileError {
     pub fn get_error(&self) -> JsString {
         self.error.clone()
     }
+
+    #[wasm_bindgen]
+    pub fn has_diagnostic(&self) -> bool {
+        self.diagnostic.is_some()
+    }
+
+    // whether get_fixes has something to try, frontmatter yaml and lines without
+    // anything to escape are never fixable
+    #[wasm_bindgen]
+    pub fn is_fixable(&self) -> bool {
+        self.fixable
+    }
+
+    // "error" or "warning", errors without a diagnostic are always "error"
+    #[wasm_bindgen]
+    pub fn get_severity(&self) -> JsString {
+        match &self.diagnostic {
+            Some(diagnostic) => JsString::from(format!("{}", diagnostic.severity)),
+            None => JsString::from("error"),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_line(&self) -> JsValue {
+        match &self.diagnostic {
+            Some(diagnostic) => diagnostic.line.into(),
+            None => JsValue::UNDEFINED,
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_column(&self) -> JsValue {
+        match &self.diagnostic {
+            Some(diagnostic) => diagnostic.column.into(),
+            None => JsValue::UNDEFINED,
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_byte_offset(&self) -> JsValue {
+        match &self.diagnostic {
+            Some(diagnostic) => diagnostic.byte_offset.into(),
+            None => JsValue::UNDEFINED,
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_expected(&self) -> Vec<JsString> {
+        match &self.diagnostic {
+            Some(diagnostic) => diagnostic
+                .expected
+                .iter()
+                .map(|rule| JsString::from(rule.as_str()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_excerpt(&self) -> JsString {
+        match &self.diagnostic {
+            Some(diagnostic) => JsString::from(diagnostic.excerpt.as_str()),
+            None => JsString::from(""),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_category(&self) -> JsString {
+        match &self.diagnostic {
+            Some(diagnostic) => JsString::from(diagnostic.category()),
+            None => JsString::from("custom"),
+        }
+    }
+}
+
+// byte offsets into the file contents the vault was given
+#[wasm_bindgen]
+pub struct JsFix {
+    fix: parser::Fix,
+    // the same range in UTF-16 code units, for slicing JavaScript strings
+    utf16_start: usize,
+    utf16_end: usize,
+}
+
+impl JsFix {
+    fn new(fix: parser::Fix, file: &parser::ParsedMDFile) -> Self {
+        JsFix {
+            utf16_start: file.position(fix.edit.start).utf16,
+            utf16_end: file.position(fix.edit.end).utf16,
+            fix,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl JsFix {
+    #[wasm_bindgen]
+    pub fn get_start(&self) -> JsValue {
+        self.fix.edit.start.into()
+    }
+    #[wasm_bindgen]
+    pub fn get_end(&self) -> JsValue {
+        self.fix.edit.end.into()
+    }
+    #[wasm_bindgen]
+    pub fn get_utf16_start(&self) -> JsValue {
+        self.utf16_start.into()
+    }
+    #[wasm_bindgen]
+    pub fn get_utf16_end(&self) -> JsValue {
+        self.utf16_end.into()
+    }
+    #[wasm_bindgen]
+    pub fn get_replacement(&self) -> JsString {
+        JsString::from(self.fix.edit.replacement.as_str())
+    }
+    #[wasm_bindgen]
+    pub fn get_explanation(&self) -> JsString {
+        JsString::from(self.fix.explanation.as_str())
+    }
+}
+
+impl JsVault {
+    // no edits when the file is unknown or its frontmatter cannot be edited safely
+    fn frontmatter_edits(
+        &self,
+        file_path: JsString,
+        edit: impl Fn(&crate::vault::File) -> Result<Vec<crate::utils::TextEdit>>,
+    ) -> Vec<JsTextEdit> {
+        let file_path = PathBuf::from(f!("{}", file_path));
+        self.files
+            .get_file(file_path)
+            .and_then(|file| {
+                let edits: Vec<crate::utils::TextEdit> = edit(file).ok()?;
+                Some(
+                    edits
+                        .into_iter()
+                        .map(|edit| JsTextEdit::new(edit, &file.contents))
+                        .collect(),
+                )
+            })
+            .unwrap_or_default()
+    }
+}
+
+// byte offsets into the file contents the vault was given
+#[wasm_bindgen]
+pub struct JsTextEdit {
+    edit: crate::utils::TextEdit,
+    // the same range in UTF-16 code units, for slicing JavaScript strings
+    utf16_start: usize,
+    utf16_end: usize,
+}
+
+impl JsTextEdit {
+    fn new(edit: crate::utils::TextEdit, file: &parser::ParsedMDFile) -> Self {
+        JsTextEdit {
+            utf16_start: file.position(edit.start).utf16,
+            utf16_end: file.position(edit.end).utf16,
+            edit,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl JsTextEdit {
+    #[wasm_bindgen]
+    pub fn get_start(&self) -> JsValue {
+        self.edit.start.into()
+    }
+    #[wasm_bindgen]
+    pub fn get_end(&self) -> JsValue {
+        self.edit.end.into()
+    }
+    #[wasm_bindgen]
+    pub fn get_utf16_start(&self) -> JsValue {
+        self.utf16_start.into()
+    }
+    #[wasm_bindgen]
+    pub fn get_utf16_end(&self) -> JsValue {
+        self.utf16_end.into()
+    }
+    #[wasm_bindgen]
+    pub fn get_replacement(&self) -> JsString {
+        JsString::from(self.edit.replacement.as_str())
+    }
+    // how many bytes longer the file gets, added to the shift of the links after it
+    #[wasm_bindgen]
+    pub fn get_delta(&self) -> isize {
+        self.edit.delta()
+    }
+}
+
+// a url found in a note, for reporting outbound links
+#[wasm_bindgen]
+pub struct JsOutboundLink {
+    path: PathBuf,
+    url: String,
+}
+
+#[wasm_bindgen]
+impl JsOutboundLink {
+    #[wasm_bindgen]
+    pub fn get_path(&self) -> JsString {
+        JsString::from(f!("{}", self.path.display()))
+    }
+    #[wasm_bindgen]
+    pub fn get_url(&self) -> JsString {
+        JsString::from(self.url.as_str())
+    }
+}
+
+// the note and byte range of the ^id marker a block reference points at
+#[wasm_bindgen]
+pub struct JsBlockReference {
+    path: PathBuf,
+    start: usize,
+    end: usize,
+}
+
+#[wasm_bindgen]
+impl JsBlockReference {
+    #[wasm_bindgen]
+    pub fn get_path(&self) -> JsString {
+        JsString::from(f!("{}", self.path.display()))
+    }
+    #[wasm_bindgen]
+    pub fn get_start(&self) -> JsValue {
+        self.start.into()
+    }
+    #[wasm_bindgen]
+    pub fn get_end(&self) -> JsValue {
+        self.end.into()
+    }
 }
 
 #[wasm_bindgen]
 pub struct JsFile {
     valid: bool,
-    file: crate::vault::File,
+    file: Rc<crate::vault::File>,
 }
 
 #[wasm_bindgen]
@@ -144,17 +598,34 @@ impl JsLinkFinder {
         case_insensitive: JsValue,
     ) -> JsLinkFinder {
         let file_paths: Vec<String> = file_paths.iter().map(|file| f!("{}", file)).collect();
-        let files: Vec<&crate::vault::File> = files.iter().map(|file| &file.file).collect();
+        let files: Vec<&crate::vault::File> = files.iter().map(|file| file.file.as_ref()).collect();
         let case_insensitive = case_insensitive.as_bool().unwrap_or(true);
 
         let link_finder = LinkFinderWrapper::new(file_paths, files, case_insensitive);
 
         JsLinkFinder { link_finder }
     }
+    // callout kinds like "quote" or "cite" whose contents should never be linked
+    #[wasm_bindgen]
+    pub fn set_excluded_callouts(&mut self, kinds: Vec<JsString>) {
+        let kinds: Vec<String> = kinds.iter().map(|kind| f!("{}", kind)).collect();
+        self.link_finder.set_excluded_callouts(kinds);
+    }
+    // text between html tags is only linked when enabled, tags and attributes never are
     #[wasm_bindgen]
-    pub fn find_links(&self, file: JsFile) -> Vec<JsLink> {
-        let file = file.file;
-        let links: Vec<crate::link_finder::Link> = self.link_finder.find_links(file);
+    pub fn set_link_html_text(&mut self, link_html_text: bool) {
+        self.link_finder.set_link_html_text(link_html_text);
+    }
+    // dataview field keys are never linked when set, only their values
+    #[wasm_bindgen]
+    pub fn set_link_only_field_values(&mut self, link_only_field_values: bool) {
+        self.link_finder
+            .set_link_only_field_values(link_only_field_values);
+    }
+
+    #[wasm_bindgen]
+    pub fn find_links(&self, file: &JsFile) -> Vec<JsLink> {
+        let links: Vec<crate::link_finder::Link> = self.link_finder.find_links(&file.file);
         links
             .iter()
             .map(|link| JsLink { link: link.clone() })
@@ -196,12 +667,36 @@ impl JsLink {
     pub fn get_end(&self) -> JsValue {
         self.link.byte_end.into()
     }
+    // offsets into the file as a JavaScript string, undefined for links cached without them
+    #[wasm_bindgen]
+    pub fn get_utf16_start(&self) -> JsValue {
+        match self.link.utf16_start {
+            Some(start) => start.into(),
+            None => JsValue::UNDEFINED,
+        }
+    }
+    #[wasm_bindgen]
+    pub fn get_utf16_end(&self) -> JsValue {
+        match self.link.utf16_end {
+            Some(end) => end.into(),
+            None => JsValue::UNDEFINED,
+        }
+    }
+    // link_text is the text at get_start..get_end
+    #[wasm_bindgen]
+    pub fn get_replacement(&self, link_text: JsString) -> JsString {
+        let link_text: String = f!("{}", link_text);
+        JsString::from(self.link.replacement(&link_text))
+    }
 }
 
 // Interface Types
+#[derive(Default)]
 pub struct VaultWrapper {
-    pub valid_files: HashMap<PathBuf, crate::vault::File>,
+    // files are handed to JavaScript by reference count, an edit copies one still held there
+    pub valid_files: HashMap<PathBuf, Rc<crate::vault::File>>,
     pub invalid_files: Vec<(PathBuf, Error)>,
+    pub warnings: Vec<(PathBuf, Error)>,
 }
 
 impl VaultWrapper {
@@ -215,10 +710,17 @@ impl VaultWrapper {
         vault_wrapper
     }
 
+    // re-adding a path replaces whatever was parsed for it before
     fn add_file(&mut self, file_path: PathBuf, file_content: String) {
+        self.valid_files.remove(&file_path);
+        self.invalid_files.retain(|(path, _)| path != &file_path);
+        self.warnings.retain(|(path, _)| path != &file_path);
         match crate::vault::File::new(file_path.clone(), file_content.clone()) {
             Ok(file) => {
-                self.valid_files.insert(file_path.clone(), file);
+                for warning in file.get_warnings() {
+                    self.warnings.push((file_path.clone(), warning));
+                }
+                self.valid_files.insert(file_path.clone(), Rc::new(file));
             }
             Err(e) => {
                 self.invalid_files.push((file_path.clone(), e));
@@ -226,21 +728,202 @@ impl VaultWrapper {
         }
     }
 
-    fn get_file(&self, file_path: PathBuf) -> Option<&crate::vault::File> {
+    fn edit_file(&mut self, file_path: PathBuf, edit: &crate::utils::TextEdit) -> Result<()> {
+        let contents: parser::ParsedMDFile = match self.get_file(file_path.clone()) {
+            Some(file) => file.contents.reparse(edit)?,
+            None => {
+                return Err(Error::Generic(f!(
+                    "File not found in vault: {}",
+                    file_path.display()
+                )))
+            }
+        };
+        self.set_contents(file_path, contents)
+    }
+
+    // a file that was not parsed before, or fails to parse again, is added in full
+    fn update_file(&mut self, file_path: PathBuf, file_content: String) {
+        let edit: Option<crate::utils::TextEdit> = match self.get_file(file_path.clone()) {
+            Some(file) => match crate::utils::TextEdit::between(file.original(), &file_content) {
+                Some(edit) => Some(edit),
+                None => return,
+            },
+            None => None,
+        };
+        match edit {
+            Some(edit) if self.edit_file(file_path.clone(), &edit).is_ok() => {}
+            _ => self.add_file(file_path, file_content),
+        }
+    }
+
+    // the edit that wraps the text of a link in [[target|text]] and the file with it made
+    fn link_edit(
+        &self,
+        link: &link_finder::Link,
+        shift: isize,
+    ) -> Result<(crate::utils::TextEdit, parser::ParsedMDFile)> {
+        let file: &crate::vault::File = self.get_file(link.source.clone()).ok_or_else(|| {
+            Error::Generic(f!("File not found in vault: {}", link.source.display()))
+        })?;
+        let (start, end): (usize, usize) = match (
+            link.byte_start.checked_add_signed(shift),
+            link.byte_end.checked_add_signed(shift),
+        ) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Err(Error::Generic(f!("Invalid shift {} for {:?}", shift, link))),
+        };
+        let mut contents: parser::ParsedMDFile = file.contents.clone();
+        let target: String = f!("{}", link.target.display());
+        let mut edits: Vec<crate::utils::TextEdit> = contents.wrap_in_link(start, end, &target)?;
+        match edits.pop() {
+            Some(edit) if edits.is_empty() => Ok((edit, contents)),
+            _ => Err(Error::Generic(f!("Expected one edit for {:?}", link))),
+        }
+    }
+
+    fn set_contents(&mut self, file_path: PathBuf, contents: parser::ParsedMDFile) -> Result<()> {
+        let file: &mut crate::vault::File = match self.valid_files.get_mut(&file_path) {
+            Some(file) => Rc::make_mut(file),
+            None => {
+                return Err(Error::Generic(f!(
+                    "File not found in vault: {}",
+                    file_path.display()
+                )))
+            }
+        };
+        file.contents = contents;
+        let warnings: Vec<Error> = file.get_warnings();
+        self.warnings.retain(|(path, _)| path != &file_path);
+        for warning in warnings {
+            self.warnings.push((file_path.clone(), warning));
+        }
+        Ok(())
+    }
+
+    fn get_file(&self, file_path: PathBuf) -> Option<&Rc<crate::vault::File>> {
         self.valid_files.get(&file_path)
     }
 
-    fn get_valid_file_paths(&self) -> Vec<&PathBuf> {
-        self.valid_files.keys().collect()
+    fn get_fixes(&self, file_path: PathBuf) -> Result<Vec<parser::Fix>> {
+        match self.valid_files.get(&file_path) {
+            Some(file) => parser::fix_md_file(file.original(), &file_path),
+            None => Err(Error::Generic(f!(
+                "File not found in vault: {}",
+                file_path.display()
+            ))),
+        }
     }
-}
 
-impl Default for VaultWrapper {
-    fn default() -> Self {
-        VaultWrapper {
-            valid_files: HashMap::new(),
-            invalid_files: Vec::new(),
+    // resolves a link target the way Obsidian does, by vault path first and then by file name
+    fn resolve_link(&self, target: &str) -> Option<&PathBuf> {
+        let target: &str = target.split('#').next().unwrap_or(target).trim();
+        if target.is_empty() {
+            return None;
         }
+        let target: PathBuf = if target.ends_with(".md") {
+            PathBuf::from(target)
+        } else {
+            PathBuf::from(f!("{}.md", target))
+        };
+        if let Some((path, _)) = self.valid_files.get_key_value(&target) {
+            return Some(path);
+        }
+        let mut matches: Vec<&PathBuf> = self
+            .valid_files
+            .keys()
+            .filter(|path| path.ends_with(&target))
+            .collect();
+        matches.sort();
+        matches.into_iter().next()
+    }
+
+    // [[note#^id]] and [[#^id]], the latter pointing into the source note
+    fn resolve_block_reference(
+        &self,
+        source_path: &Path,
+        link: &str,
+    ) -> Option<(&PathBuf, &parser::LinkPart)> {
+        let link: &str = link.split('|').next().unwrap_or(link);
+        let (target, id) = link.split_once("#^")?;
+        let path: &PathBuf = if target.trim().is_empty() {
+            self.valid_files.get_key_value(source_path)?.0
+        } else {
+            self.resolve_link(target)?
+        };
+        let line: &parser::Line = self.valid_files.get(path)?.contents.get_block(id.trim())?;
+        Some((path, line.get_block_id()?))
+    }
+
+    // targets embedded by a file, resolved to vault paths where possible
+    fn get_embeds(&self, file_path: PathBuf) -> Vec<String> {
+        let file: &crate::vault::File = match self.valid_files.get(&file_path) {
+            Some(file) => file,
+            None => return Vec::new(),
+        };
+        let source: &str = &file.contents.source;
+        file.contents
+            .get_embeds()
+            .iter()
+            .map(|embed| {
+                let target: &str = embed.target.text(source);
+                match self.resolve_link(target) {
+                    Some(path) => f!("{}", path.display()),
+                    None => target.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    fn get_frontmatter_list(&self, file_path: PathBuf, key: &str) -> Vec<String> {
+        self.valid_files
+            .get(&file_path)
+            .and_then(|file| file.contents.get_frontmatter())
+            .map(|frontmatter| frontmatter.get_list(key))
+            .unwrap_or_default()
+    }
+
+    fn get_properties(&self, file_path: PathBuf) -> serde_yaml::Mapping {
+        self.valid_files
+            .get(&file_path)
+            .map(|file| file.contents.get_properties())
+            .unwrap_or_default()
+    }
+
+    fn get_outbound_links(&self) -> Vec<(&PathBuf, String)> {
+        let mut paths: Vec<&PathBuf> = self.valid_files.keys().collect();
+        paths.sort();
+        let mut links: Vec<(&PathBuf, String)> = Vec::new();
+        for path in paths {
+            let contents: &parser::ParsedMDFile = &self.valid_files[path].contents;
+            for node in contents.get_nodes() {
+                match &node.node {
+                    parser::NodeEnum::Url(url) => links.push((path, url.href(&contents.source))),
+                    parser::NodeEnum::WebLink(web_link) => {
+                        let link: &str = web_link.link.text(&contents.source);
+                        if link.contains("://") {
+                            links.push((path, link.to_string()));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        links
+    }
+
+    fn get_files_with_tag(&self, tag: &str) -> Vec<&PathBuf> {
+        let mut paths: Vec<&PathBuf> = self
+            .valid_files
+            .iter()
+            .filter(|(_, file)| file.contents.get_tags().iter().any(|t| t.matches(tag)))
+            .map(|(path, _)| path)
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn get_valid_file_paths(&self) -> Vec<&PathBuf> {
+        self.valid_files.keys().collect()
     }
 }
 
@@ -256,22 +939,29 @@ impl LinkFinderWrapper {
         files: Vec<&crate::vault::File>,
         case_insensitive: bool,
     ) -> LinkFinderWrapper {
-        let file_paths: Vec<PathBuf> = file_paths
-            .iter()
-            .map(|file_path| PathBuf::from(file_path))
-            .collect();
+        let file_paths: Vec<PathBuf> = file_paths.iter().map(PathBuf::from).collect();
 
-        let file_refs: Vec<&crate::vault::File> = files
-            .iter()
-            .map(|file: &&crate::vault::File| *file)
-            .collect();
+        let file_refs: Vec<&crate::vault::File> = files.to_vec();
 
         let link_finder = link_finder::LinkFinder::new(file_refs, case_insensitive);
         LinkFinderWrapper { link_finder }
     }
 
-    pub fn find_links(&self, file: crate::vault::File) -> Vec<link_finder::Link> {
-        self.link_finder.get_links(&file)
+    pub fn set_excluded_callouts(&mut self, kinds: Vec<String>) {
+        self.link_finder.set_excluded_callouts(kinds);
+    }
+
+    pub fn set_link_html_text(&mut self, link_html_text: bool) {
+        self.link_finder.set_link_html_text(link_html_text);
+    }
+
+    pub fn set_link_only_field_values(&mut self, link_only_field_values: bool) {
+        self.link_finder
+            .set_link_only_field_values(link_only_field_values);
+    }
+
+    pub fn find_links(&self, file: &crate::vault::File) -> Vec<link_finder::Link> {
+        self.link_finder.get_links(file)
     }
 }
 
@@ -310,6 +1000,310 @@ pub mod wasm_test {
 
     use super::*;
 
+    #[test]
+    fn vault_embeds_test() {
+        let vault = VaultWrapper::new(
+            vec![
+                PathBuf::from(FILE_1_PATH),
+                PathBuf::from("notes/turing machine.md"),
+                PathBuf::from("index.md"),
+            ],
+            vec![
+                FILE_1_CONT.to_string(),
+                FILE_2_CONT.to_string(),
+                "![[alan turing]] and ![[turing machine#Heading|300]] ![[diagram.png|200x100]]"
+                    .to_string(),
+            ],
+        );
+        let embeds: Vec<String> = vault.get_embeds(PathBuf::from("index.md"));
+        assert_eq!(
+            embeds,
+            vec!["alan turing.md", "notes/turing machine.md", "diagram.png"]
+        );
+    }
+
+    #[test]
+    fn vault_tags_test() {
+        let vault = VaultWrapper::new(
+            vec![
+                PathBuf::from("a.md"),
+                PathBuf::from("b.md"),
+                PathBuf::from("c.md"),
+            ],
+            vec![
+                "working on #project/alpha\n".to_string(),
+                "---\ntags: Project\n---\nbody\n".to_string(),
+                "#projects are different\n".to_string(),
+            ],
+        );
+        assert_eq!(
+            vault.get_files_with_tag("#project"),
+            vec![&PathBuf::from("a.md"), &PathBuf::from("b.md")]
+        );
+        assert_eq!(
+            vault.get_files_with_tag("project/alpha"),
+            vec![&PathBuf::from("a.md")]
+        );
+    }
+
+    #[test]
+    fn vault_frontmatter_test() {
+        let vault = VaultWrapper::new(
+            vec![
+                PathBuf::from("Turing Machine.md"),
+                PathBuf::from("broken.md"),
+            ],
+            vec![
+                "---\naliases: turing\n---\nbody\n".to_string(),
+                "---\naliases: [\n---\nbody\n".to_string(),
+            ],
+        );
+        let file: &crate::vault::File = vault.get_file(PathBuf::from("Turing Machine.md")).unwrap();
+        assert_eq!(file.get_aliases(), vec!["Turing Machine", "turing"]);
+        assert_eq!(
+            vault.get_frontmatter_list(PathBuf::from("Turing Machine.md"), "aliases"),
+            vec!["turing"]
+        );
+        assert!(vault
+            .get_frontmatter_list(PathBuf::from("broken.md"), "aliases")
+            .is_empty());
+        assert!(vault.get_file(PathBuf::from("broken.md")).is_some());
+        assert_eq!(vault.warnings.len(), 1);
+        assert_eq!(vault.warnings[0].0, PathBuf::from("broken.md"));
+    }
+
+    #[test]
+    fn frontmatter_edit_test() {
+        let edit = |contents: &str, change: &dyn Fn(&crate::vault::File) -> Result<Vec<crate::utils::TextEdit>>| {
+            let file = crate::vault::File::new(PathBuf::from("note.md"), contents.to_string()).unwrap();
+            let edits = change(&file).unwrap();
+            crate::utils::apply_edits(contents, &edits).unwrap()
+        };
+        let contents: &str = "---\ntitle: 'Draft' # working title\nbad_links:\n    - \"Aliasing.md\"\n# keep me\naliases: turing\ntags: [a, b]\n---\nbody\n";
+
+        assert_eq!(
+            edit(contents, &|file| file
+                .add_frontmatter_item("bad_links", "Relation.md")),
+            contents.replace(
+                "\"Aliasing.md\"\n",
+                "\"Aliasing.md\"\n    - \"Relation.md\"\n"
+            )
+        );
+        assert_eq!(
+            edit(contents, &|file| file
+                .remove_frontmatter_item("bad_links", "Aliasing.md")),
+            contents.replace("    - \"Aliasing.md\"\n", "")
+        );
+        assert_eq!(
+            edit(contents, &|file| file
+                .add_frontmatter_item("aliases", "Alan")),
+            contents.replace("aliases: turing", "aliases: [turing, Alan]")
+        );
+        assert_eq!(
+            edit(contents, &|file| file.add_frontmatter_item("tags", "c, d")),
+            contents.replace("[a, b]", "[a, b, \"c, d\"]")
+        );
+        assert_eq!(
+            edit(contents, &|file| file.remove_frontmatter_item("tags", "a")),
+            contents.replace("[a, b]", "[b]")
+        );
+        assert_eq!(
+            edit(contents, &|file| file
+                .set_frontmatter_value("title", "It's done")),
+            contents.replace("'Draft'", "'It''s done'")
+        );
+        assert_eq!(
+            edit(contents, &|file| file
+                .set_frontmatter_value("status", "true")),
+            contents.replace("---\nbody", "status: \"true\"\n---\nbody")
+        );
+        let file = crate::vault::File::new(PathBuf::from("note.md"), contents.to_string()).unwrap();
+        assert!(file
+            .add_frontmatter_item("aliases", "turing")
+            .unwrap()
+            .is_empty());
+        assert!(file
+            .remove_frontmatter_item("missing", "x")
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            edit("body\r\n", &|file| file
+                .add_frontmatter_item("bad_links", "a.md")),
+            "---\r\nbad_links:\r\n  - a.md\r\n---\r\nbody\r\n"
+        );
+        let file = crate::vault::File::new(PathBuf::from("bad.md"), "---\na: [\n---\n".to_string())
+            .unwrap();
+        assert!(file.set_frontmatter_value("a", "b").is_err());
+    }
+
+    #[test]
+    fn vault_edit_file_test() {
+        let mut vault = VaultWrapper::new(
+            vec![PathBuf::from("a.md")],
+            vec!["first #one\nsecond\n".to_string()],
+        );
+        let edit = crate::utils::TextEdit::insert(11, "#two ");
+        vault.edit_file(PathBuf::from("a.md"), &edit).unwrap();
+        let file = vault.get_file(PathBuf::from("a.md")).unwrap();
+        assert_eq!(file.original(), "first #one\n#two second\n");
+        assert_eq!(
+            vault.get_files_with_tag("two"),
+            vec![&PathBuf::from("a.md")]
+        );
+
+        let edit = crate::utils::TextEdit::insert(0, "`");
+        vault.edit_file(PathBuf::from("a.md"), &edit).unwrap();
+        assert_eq!(vault.warnings.len(), 1);
+        assert!(vault.edit_file(PathBuf::from("b.md"), &edit).is_err());
+
+        vault.update_file(PathBuf::from("a.md"), "first #one\nsecond é\n".to_string());
+        let file = vault.get_file(PathBuf::from("a.md")).unwrap();
+        assert_eq!(file.original(), "first #one\nsecond é\n");
+        assert!(vault.get_files_with_tag("two").is_empty());
+        assert!(vault.warnings.is_empty());
+        vault.update_file(PathBuf::from("b.md"), "#two\n".to_string());
+        assert_eq!(
+            vault.get_files_with_tag("two"),
+            vec![&PathBuf::from("b.md")]
+        );
+        assert_eq!(
+            crate::utils::TextEdit::between("aéb", "aèb"),
+            Some(crate::utils::TextEdit::new(1, 3, "è".to_string()))
+        );
+        assert_eq!(crate::utils::TextEdit::between("ab", "ab"), None);
+    }
+
+    #[test]
+    fn vault_add_link_test() {
+        let contents: &str = "Alan Turing met Alan Turing\n| Alan Turing |\n| --- |\n";
+        let mut vault = VaultWrapper::new(
+            vec![PathBuf::from("a.md"), PathBuf::from("Alan Turing.md")],
+            vec![contents.to_string(), "a person\n".to_string()],
+        );
+        let link_finder = LinkFinderWrapper::new(
+            vec!["a.md".to_string(), "Alan Turing.md".to_string()],
+            vec![vault.get_file(PathBuf::from("Alan Turing.md")).unwrap()],
+            true,
+        );
+        let links = link_finder.find_links(vault.get_file(PathBuf::from("a.md")).unwrap());
+        assert_eq!(links.len(), 3);
+
+        // the links were found in the file before any was added
+        let mut shift: isize = 0;
+        for link in links.iter().filter(|link| link.byte_start != 16) {
+            let (edit, contents) = vault.link_edit(link, shift).unwrap();
+            shift += edit.delta();
+            vault.set_contents(PathBuf::from("a.md"), contents).unwrap();
+        }
+        let file = vault.get_file(PathBuf::from("a.md")).unwrap();
+        assert_eq!(
+            file.original(),
+            "[[Alan Turing.md|Alan Turing]] met Alan Turing\n| [[Alan Turing.md\\|Alan Turing]] |\n| --- |\n"
+        );
+        assert_eq!(file.contents.get_wikilinks().len(), 2);
+        assert!(vault.link_edit(&links[0], shift).is_err());
+    }
+
+    #[test]
+    fn vault_block_reference_test() {
+        let vault = VaultWrapper::new(
+            vec![PathBuf::from("notes/source.md"), PathBuf::from("index.md")],
+            vec![
+                "a quotable line ^quote\n".to_string(),
+                "see [[source#^quote]] ^local\n".to_string(),
+            ],
+        );
+        let (path, block_id) = vault
+            .resolve_block_reference(Path::new("index.md"), "source#^quote|shown")
+            .unwrap();
+        assert_eq!(path, &PathBuf::from("notes/source.md"));
+        assert_eq!((block_id.start, block_id.end), (17, 22));
+
+        let (path, _) = vault
+            .resolve_block_reference(Path::new("index.md"), "#^local")
+            .unwrap();
+        assert_eq!(path, &PathBuf::from("index.md"));
+        assert!(vault
+            .resolve_block_reference(Path::new("index.md"), "source#^missing")
+            .is_none());
+    }
+
+    #[test]
+    fn vault_outbound_links_test() {
+        let vault = VaultWrapper::new(
+            vec![PathBuf::from("b.md"), PathBuf::from("a.md")],
+            vec![
+                "mail <alan@example.com> or www.example.com\n".to_string(),
+                "see https://en.wikipedia.org/wiki/Turing_machine. and [docs](https://docs.rs)\n"
+                    .to_string(),
+            ],
+        );
+        let links: Vec<(String, String)> = vault
+            .get_outbound_links()
+            .into_iter()
+            .map(|(path, url)| (f!("{}", path.display()), url))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                (
+                    "a.md".to_string(),
+                    "https://en.wikipedia.org/wiki/Turing_machine".to_string()
+                ),
+                ("a.md".to_string(), "https://docs.rs".to_string()),
+                ("b.md".to_string(), "mailto:alan@example.com".to_string()),
+                ("b.md".to_string(), "http://www.example.com".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn vault_fixes_test() {
+        let vault = VaultWrapper::new(
+            vec![
+                PathBuf::from("a.md"),
+                PathBuf::from("b.md"),
+                PathBuf::from("c.md"),
+            ],
+            vec![
+                "Grüße 🎉\nstray * here\n".to_string(),
+                "---\naliases: [a\n---\nbody\n".to_string(),
+                "a ```x\n".to_string(),
+            ],
+        );
+        let fixable: Vec<(String, bool)> = vault
+            .warnings
+            .iter()
+            .map(|(path, warning)| {
+                let file: &crate::vault::File = vault.get_file(path.clone()).unwrap();
+                (
+                    f!("{}", path.display()),
+                    parser::can_fix(file.original(), warning.diagnostic().unwrap()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            fixable,
+            vec![
+                ("a.md".to_string(), true),
+                ("b.md".to_string(), false),
+                ("c.md".to_string(), false),
+            ]
+        );
+
+        let file: &crate::vault::File = vault.get_file(PathBuf::from("a.md")).unwrap();
+        let fixes: Vec<JsFix> = vault
+            .get_fixes(PathBuf::from("a.md"))
+            .unwrap()
+            .into_iter()
+            .map(|fix| JsFix::new(fix, &file.contents))
+            .collect();
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[0].fix.edit.start, 19);
+        assert_eq!((fixes[0].utf16_start, fixes[0].utf16_end), (15, 15));
+    }
+
     #[test]
     fn wasm_alan_turing_test() {
         // let settings = crate::settings::Settings::new(true, "red".to_string());
@@ -323,12 +1317,15 @@ pub mod wasm_test {
             files,
             true,
         );
-        let links = link_finder.find_links(file1);
+        let links = link_finder.find_links(&file1);
         let links_expected: Vec<crate::link_finder::Link> = vec![crate::link_finder::Link {
             source: PathBuf::from(FILE_1_PATH),
             target: PathBuf::from(FILE_2_PATH),
             byte_start: 189,
             byte_end: 203,
+            in_table: false,
+            utf16_start: Some(189),
+            utf16_end: Some(203),
         }];
         assert_eq!(links, links_expected);
     }
@@ -346,7 +1343,7 @@ pub mod wasm_test {
             files,
             true,
         );
-        let links = link_finder.find_links(file2);
+        let links = link_finder.find_links(&file2);
         /*
            Link { source: "turing machine.md", target: "alan turing.md", byte_start: 149, byte_end: 155 }
            Link { source: "turing machine.md", target: "alan turing.md", byte_start: 167, byte_end: 173 }
@@ -359,24 +1356,36 @@ pub mod wasm_test {
                 target: PathBuf::from(FILE_1_PATH),
                 byte_start: 149,
                 byte_end: 155,
+                in_table: false,
+                utf16_start: Some(149),
+                utf16_end: Some(155),
             },
             crate::link_finder::Link {
                 source: PathBuf::from(FILE_2_PATH),
                 target: PathBuf::from(FILE_1_PATH),
                 byte_start: 167,
                 byte_end: 173,
+                in_table: false,
+                utf16_start: Some(167),
+                utf16_end: Some(173),
             },
             crate::link_finder::Link {
                 source: PathBuf::from(FILE_2_PATH),
                 target: PathBuf::from(FILE_1_PATH),
                 byte_start: 256,
                 byte_end: 267,
+                in_table: false,
+                utf16_start: Some(256),
+                utf16_end: Some(267),
             },
             crate::link_finder::Link {
                 source: PathBuf::from(FILE_2_PATH),
                 target: PathBuf::from(FILE_1_PATH),
                 byte_start: 415,
                 byte_end: 421,
+                in_table: false,
+                utf16_start: Some(415),
+                utf16_end: Some(421),
             },
         ];
         assert_eq!(links, links_expected);
@@ -1,20 +1,32 @@
 use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
+use unicode_normalization::char::canonical_combining_class;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
 
 use crate::parser::ParsedMDFile;
 use crate::prelude::*;
+use crate::settings::Settings;
 
 use std::{
+    borrow::Cow,
     collections::HashMap,
     path::{Path, PathBuf},
 };
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
-pub(crate) struct Link {
+pub struct Link {
     pub source: PathBuf,
     pub target: PathBuf,
     pub byte_start: usize,
     pub byte_end: usize,
+    // a pipe inside a table cell ends the cell, so the alias separator has to be escaped
+    #[serde(default)]
+    pub in_table: bool,
+    // the same range in UTF-16 code units, None for links cached before they were recorded
+    #[serde(default)]
+    pub utf16_start: Option<usize>,
+    #[serde(default)]
+    pub utf16_end: Option<usize>,
 }
 
 impl Link {
@@ -29,8 +41,16 @@ impl Link {
             target,
             byte_start,
             byte_end,
+            in_table: false,
+            utf16_start: None,
+            utf16_end: None,
         }
     }
+    // the text that should replace byte_start..byte_end, link_text is the text being linked
+    pub(crate) fn replacement(&self, link_text: &str) -> String {
+        let separator: &str = if self.in_table { "\\|" } else { "|" };
+        f!("[[{}{}{}]]", self.target.display(), separator, link_text)
+    }
     pub(crate) fn ser(&self) -> String {
         serde_json::to_string(self).unwrap()
     }
@@ -42,17 +62,25 @@ impl Link {
 #[derive(Debug)]
 pub(crate) struct LinkFinder {
     groups: Vec<(PathBuf, String)>,
-    case_insensitive: bool,
+    settings: Settings,
 }
 
 impl LinkFinder {
     pub(crate) fn new(files: Vec<&crate::vault::File>, case_insensitive: bool) -> Self {
+        let settings: Settings = Settings {
+            case_insensitive,
+            ..Settings::default()
+        };
+        LinkFinder::with_settings(files, settings)
+    }
+
+    pub(crate) fn with_settings(files: Vec<&crate::vault::File>, settings: Settings) -> Self {
         let mut file_groups: HashMap<usize, PathBuf> = HashMap::new();
         let mut group_index: usize = 1;
         let mut file_regex_strs: Vec<(PathBuf, String)> = vec![];
 
         for file in files {
-            let aliases: Vec<&str> = file.get_aliases();
+            let aliases: Vec<String> = file.get_aliases();
             // escape all regex special characters
             let cleaned_aliases: Vec<String> =
                 aliases.iter().map(|alias| regex::escape(alias)).collect();
@@ -64,21 +92,31 @@ impl LinkFinder {
             }
         }
 
-        file_regex_strs.sort_by(|a, b| a.1.len().cmp(&b.1.len()));
+        file_regex_strs.sort_by_key(|a| a.1.len());
         file_regex_strs.reverse();
 
         LinkFinder {
             groups: file_regex_strs,
-            case_insensitive,
+            settings,
         }
     }
 
+    pub(crate) fn set_excluded_callouts(&mut self, kinds: Vec<String>) {
+        self.settings.excluded_callouts = kinds;
+    }
+
+    pub(crate) fn set_link_html_text(&mut self, link_html_text: bool) {
+        self.settings.link_html_text = link_html_text;
+    }
+
+    pub(crate) fn set_link_only_field_values(&mut self, link_only_field_values: bool) {
+        self.settings.link_only_field_values = link_only_field_values;
+    }
+
     pub(crate) fn create_regex(&self) -> Result<(Regex, HashMap<usize, PathBuf>)> {
         let mut file_groups: HashMap<usize, PathBuf> = HashMap::new();
-        let mut group_index: usize = 1;
-        for (path, regex_str) in self.groups.iter() {
-            file_groups.insert(group_index, path.clone());
-            group_index += 1;
+        for (group_index, (path, regex_str)) in self.groups.iter().enumerate() {
+            file_groups.insert(group_index + 1, path.clone());
         }
         let regex_strs: Vec<String> = self
             .groups
@@ -87,7 +125,7 @@ impl LinkFinder {
             .collect::<Vec<String>>();
         let regex_str: String = regex_strs.join("|");
         let regex: Regex = RegexBuilder::new(&regex_str)
-            .case_insensitive(self.case_insensitive)
+            .case_insensitive(self.settings.case_insensitive)
             .build()?;
         Ok((regex, file_groups))
     }
@@ -103,10 +141,8 @@ impl LinkFinder {
             .map(|(p, s)| (p.clone(), s.clone()))
             .collect();
         let mut file_groups: HashMap<usize, PathBuf> = HashMap::new();
-        let mut group_index: usize = 1;
-        for (path, regex_str) in groups.iter() {
-            file_groups.insert(group_index, path.clone());
-            group_index += 1;
+        for (group_index, (path, regex_str)) in groups.iter().enumerate() {
+            file_groups.insert(group_index + 1, path.clone());
         }
         let regex_strs: Vec<String> = groups
             .iter()
@@ -114,7 +150,7 @@ impl LinkFinder {
             .collect::<Vec<String>>();
         let regex_str: String = regex_strs.join("|");
         let regex: Regex = RegexBuilder::new(&regex_str)
-            .case_insensitive(self.case_insensitive)
+            .case_insensitive(self.settings.case_insensitive)
             .build()?;
         Ok((regex, file_groups))
     }
@@ -124,40 +160,43 @@ impl LinkFinder {
         let mut links: Vec<Link> = vec![];
         let (regex, group_map) = self.create_regex_exc(&md_file.path).unwrap();
         let num_groups = group_map.len();
-        let string_nodes: Vec<crate::parser::Node> = md_file.get_string_nodes();
+        let string_nodes: Vec<crate::parser::Node> = md_file.get_string_nodes(&self.settings);
+        let table_cells: Vec<&crate::parser::TableCell> = md_file.get_table_cells();
 
         for node in string_nodes {
             let start: usize = node.start;
             let end: usize = node.end;
-            let string: Result<&str> = node.get_inner_string();
-            match string {
-                Ok(string) => {
-                    let caps_iter: regex::CaptureMatches<'_, '_> = regex.captures_iter(string);
-                    // println!("caps_iter:{:?}", caps_iter);
-                    for caps in caps_iter {
-                        let cap_result: Option<(regex::Match, usize)> =
-                            get_first_capture(Some(caps), num_groups);
-                        match cap_result {
-                            Some((capture, group_index)) => {
-                                let capture_str: &str = capture.as_str();
-                                let cap_start = capture.start();
-                                let target: &Path =
-                                    group_map.get(&group_index).expect("expected group");
-                                let source: &Path = &md_file.path;
-                                let link_text: &str = capture_str;
-                                let link: Link = Link {
-                                    source: source.to_path_buf(),
-                                    target: target.to_path_buf(),
-                                    byte_start: start + cap_start,
-                                    byte_end: start + cap_start + link_text.len(),
-                                };
-                                links.push(link);
-                            }
-                            None => (),
-                        }
+            let string: Result<&str> = node.get_inner_string(&md_file.source);
+            if let Ok(string) = string {
+                // aliases are in NFC, so the text is matched in NFC too
+                let string: NfcText = NfcText::new(string);
+                let caps_iter: regex::CaptureMatches<'_, '_> = regex.captures_iter(&string.text);
+                // println!("caps_iter:{:?}", caps_iter);
+                for caps in caps_iter {
+                    let cap_result: Option<(regex::Match, usize)> =
+                        get_first_capture(Some(caps), num_groups);
+                    if let Some((capture, group_index)) = cap_result {
+                        let cap_start = string.start(capture.start());
+                        let cap_end = string.end(capture.end());
+                        let target: &Path = group_map.get(&group_index).expect("expected group");
+                        let source: &Path = &md_file.path;
+                        let byte_start: usize = start + cap_start;
+                        let in_table: bool = table_cells
+                            .iter()
+                            .any(|cell| cell.start <= byte_start && byte_start < cell.end);
+                        let byte_end: usize = start + cap_end;
+                        let link: Link = Link {
+                            source: source.to_path_buf(),
+                            target: target.to_path_buf(),
+                            byte_start,
+                            byte_end,
+                            in_table,
+                            utf16_start: Some(md_file.position(byte_start).utf16),
+                            utf16_end: Some(md_file.position(byte_end).utf16),
+                        };
+                        links.push(link);
                     }
                 }
-                Err(_) => (),
             }
         }
         // if !self.settings.link_to_self {
@@ -167,23 +206,76 @@ impl LinkFinder {
     }
 }
 
+// Text in NFC along with where each of its segments starts in the original, so
+// a match maps back onto the bytes as written. A segment is a character that
+// cannot combine with what comes before it and the characters after it that can.
+struct NfcText<'a> {
+    text: Cow<'a, str>,
+    // (normalized, original) offsets of the segment starts and of the end, empty
+    // when the text was already in NFC
+    segments: Vec<(usize, usize)>,
+}
+
+impl<'a> NfcText<'a> {
+    fn new(text: &'a str) -> Self {
+        if is_nfc_quick(text.chars()) == IsNormalized::Yes {
+            return NfcText {
+                text: Cow::Borrowed(text),
+                segments: Vec::new(),
+            };
+        }
+        let mut normalized: String = String::with_capacity(text.len());
+        let mut segments: Vec<(usize, usize)> = Vec::new();
+        let mut segment_start: usize = 0;
+        for (i, c) in text.char_indices() {
+            if i > segment_start && starts_segment(c) {
+                segments.push((normalized.len(), segment_start));
+                normalized.extend(text[segment_start..i].nfc());
+                segment_start = i;
+            }
+        }
+        segments.push((normalized.len(), segment_start));
+        normalized.extend(text[segment_start..].nfc());
+        segments.push((normalized.len(), text.len()));
+        NfcText {
+            text: Cow::Owned(normalized),
+            segments,
+        }
+    }
+
+    // a match starting inside a segment starts with the segment
+    fn start(&self, pos: usize) -> usize {
+        if self.segments.is_empty() {
+            return pos;
+        }
+        let index: usize = self.segments.partition_point(|(start, _)| *start <= pos);
+        self.segments[index - 1].1
+    }
+
+    // a match ending inside a segment ends with the segment
+    fn end(&self, pos: usize) -> usize {
+        if self.segments.is_empty() {
+            return pos;
+        }
+        let index: usize = self.segments.partition_point(|(start, _)| *start < pos);
+        self.segments[index.min(self.segments.len() - 1)].1
+    }
+}
+
+fn starts_segment(c: char) -> bool {
+    canonical_combining_class(c) == 0 && is_nfc_quick(std::iter::once(c)) == IsNormalized::Yes
+}
+
 fn get_first_capture(
     caps: Option<regex::Captures>,
     caps_len: usize,
 ) -> Option<(regex::Match, usize)> {
-    match caps {
-        Some(captures) => {
-            for i in 1..caps_len + 1 {
-                let i: usize = i as usize;
-                if captures.get(i).is_some() {
-                    return Some((
-                        captures.get(i).expect("Expected capture to exist."),
-                        i as usize,
-                    ));
-                }
+    if let Some(captures) = caps {
+        for i in 1..caps_len + 1 {
+            if captures.get(i).is_some() {
+                return Some((captures.get(i).expect("Expected capture to exist."), i));
             }
         }
-        None => (),
     }
     None
 }
@@ -224,6 +316,22 @@ pub mod link_finder_test {
 
     use super::*;
 
+    // the links to a note named project in a note holding contents
+    fn project_links(contents: &str, configure: impl FnOnce(&mut LinkFinder)) -> Vec<Link> {
+        let project =
+            crate::vault::File::new(PathBuf::from("project.md"), "a project\n".to_string())
+                .unwrap();
+        let notes =
+            crate::vault::File::new(PathBuf::from("notes.md"), contents.to_string()).unwrap();
+        let mut link_finder = LinkFinder::new(vec![&project], true);
+        configure(&mut link_finder);
+        link_finder.get_links(&notes)
+    }
+
+    fn link_starts(links: &[Link]) -> Vec<usize> {
+        links.iter().map(|link| link.byte_start).collect()
+    }
+
     #[test]
     fn regex_construct_test() {
         // let settings = crate::settings::Settings::new(true, "red".to_string());
@@ -269,6 +377,9 @@ pub mod link_finder_test {
             target: PathBuf::from(FILE_2_PATH),
             byte_start: 189,
             byte_end: 203,
+            in_table: false,
+            utf16_start: Some(189),
+            utf16_end: Some(203),
         }];
         assert_eq!(links, links_expected);
     }
@@ -304,28 +415,196 @@ pub mod link_finder_test {
                 target: PathBuf::from(FILE_1_PATH),
                 byte_start: 149,
                 byte_end: 155,
+                in_table: false,
+                utf16_start: Some(149),
+                utf16_end: Some(155),
             },
             Link {
                 source: PathBuf::from(FILE_2_PATH),
                 target: PathBuf::from(FILE_1_PATH),
                 byte_start: 167,
                 byte_end: 173,
+                in_table: false,
+                utf16_start: Some(167),
+                utf16_end: Some(173),
             },
             Link {
                 source: PathBuf::from(FILE_2_PATH),
                 target: PathBuf::from(FILE_1_PATH),
                 byte_start: 256,
                 byte_end: 267,
+                in_table: false,
+                utf16_start: Some(256),
+                utf16_end: Some(267),
             },
             Link {
                 source: PathBuf::from(FILE_2_PATH),
                 target: PathBuf::from(FILE_1_PATH),
                 byte_start: 415,
                 byte_end: 421,
+                in_table: false,
+                utf16_start: Some(415),
+                utf16_end: Some(421),
             },
         ];
         assert_eq!(links, links_expected);
     }
+    #[test]
+    fn link_block_quote_test() {
+        let file_1_path: PathBuf = PathBuf::from(FILE_1_PATH);
+        let file_2_path: PathBuf = PathBuf::from(FILE_2_PATH);
+        let file1 = crate::vault::File::new(file_1_path, FILE_1_CONT.to_string()).unwrap();
+        let file2 = crate::vault::File::new(file_2_path, FILE_2_CONT.to_string()).unwrap();
+        let quote_contents: &str = "> Quoting Alan Turing\n> > about the Turing Machine\n";
+        let quote =
+            crate::vault::File::new(PathBuf::from("quote.md"), quote_contents.to_string()).unwrap();
+        let link_finder = LinkFinder::new(vec![&file1, &file2], true);
+
+        let links: Vec<Link> = link_finder.get_links(&quote);
+        let link_texts: Vec<&str> = links
+            .iter()
+            .map(|link| &quote_contents[link.byte_start..link.byte_end])
+            .collect();
+        assert_eq!(link_texts, vec!["Alan Turing", "Turing Machine"]);
+        assert_eq!(links[1].target, PathBuf::from(FILE_2_PATH));
+    }
+
+    #[test]
+    fn link_table_test() {
+        let file_1_path: PathBuf = PathBuf::from(FILE_1_PATH);
+        let file_2_path: PathBuf = PathBuf::from(FILE_2_PATH);
+        let file1 = crate::vault::File::new(file_1_path, FILE_1_CONT.to_string()).unwrap();
+        let file2 = crate::vault::File::new(file_2_path, FILE_2_CONT.to_string()).unwrap();
+        let table_contents: &str =
+            "| Person | Idea |\n| :--- | ---: |\n| Alan Turing | the Turing Machine |\nAlan Turing\n";
+        let table =
+            crate::vault::File::new(PathBuf::from("table.md"), table_contents.to_string()).unwrap();
+        let link_finder = LinkFinder::new(vec![&file1, &file2], true);
+
+        let links: Vec<Link> = link_finder.get_links(&table);
+        let link_texts: Vec<&str> = links
+            .iter()
+            .map(|link| &table_contents[link.byte_start..link.byte_end])
+            .collect();
+        assert_eq!(
+            link_texts,
+            vec!["Alan Turing", "Turing Machine", "Alan Turing"]
+        );
+        assert!(links[0].in_table && links[1].in_table && !links[2].in_table);
+        assert_eq!(
+            links[0].replacement("Alan Turing"),
+            "[[alan turing.md\\|Alan Turing]]"
+        );
+        assert_eq!(
+            links[2].replacement("Alan Turing"),
+            "[[alan turing.md|Alan Turing]]"
+        );
+    }
+
+    #[test]
+    fn link_html_test() {
+        let contents: &str = "%% project %% <!-- project --> <span title=\"project\">project</span>\n\n<div class=\"project\">\nproject\n</div>\n";
+        assert!(project_links(contents, |_| {}).is_empty());
+
+        let links: Vec<Link> =
+            project_links(contents, |link_finder| link_finder.set_link_html_text(true));
+        assert_eq!(link_starts(&links), vec![53, 91]);
+    }
+
+    #[test]
+    fn link_emphasis_test() {
+        let contents: &str = "==project== and ~~a project~~ and _project_\n";
+
+        let edits: Vec<crate::utils::TextEdit> = project_links(contents, |_| {})
+            .iter()
+            .map(|link| {
+                let text: &str = &contents[link.byte_start..link.byte_end];
+                crate::utils::TextEdit::new(link.byte_start, link.byte_end, link.replacement(text))
+            })
+            .collect();
+        assert_eq!(
+            crate::utils::apply_edits(contents, &edits).unwrap(),
+            "==[[project.md|project]]== and ~~a [[project.md|project]]~~ and _[[project.md|project]]_\n"
+        );
+    }
+
+    #[test]
+    fn link_inline_field_test() {
+        let contents: &str = "project:: the project\nsee [project:: project]\n";
+        let links: Vec<Link> = project_links(contents, |_| {});
+        assert_eq!(link_starts(&links), vec![14, 37]);
+
+        let links: Vec<Link> = project_links(contents, |link_finder| {
+            link_finder.set_link_only_field_values(false)
+        });
+        assert_eq!(link_starts(&links), vec![0, 14, 27, 37]);
+    }
+
+    #[test]
+    fn link_unicode_normalization_test() {
+        // a decomposed file name, as macOS writes it, and a composed alias
+        let cafe = crate::vault::File::new(
+            PathBuf::from("Cafe\u{301}.md"),
+            "---\naliases: [Crème]\n---\n".to_string(),
+        )
+        .unwrap();
+        let contents: &str = "the Caf\u{e9} and the Cre\u{300}me, decomposed Cafe\u{301}\n";
+        let notes =
+            crate::vault::File::new(PathBuf::from("notes.md"), contents.to_string()).unwrap();
+        let link_finder = LinkFinder::new(vec![&cafe], true);
+
+        let linked: Vec<&str> = link_finder
+            .get_links(&notes)
+            .iter()
+            .map(|link| &contents[link.byte_start..link.byte_end])
+            .collect();
+        assert_eq!(linked, vec!["Caf\u{e9}", "Cre\u{300}me", "Cafe\u{301}"]);
+    }
+
+    #[test]
+    fn link_utf16_test() {
+        let contents: &str = "# Grüße 🎉\r\n\r\nthe 📚 project\n";
+        let links: Vec<Link> = project_links(contents, |_| {});
+        assert_eq!(links.len(), 1);
+        // what JavaScript would slice out of the same text
+        let utf16: Vec<u16> = contents.encode_utf16().collect();
+        let (start, end): (usize, usize) =
+            (links[0].utf16_start.unwrap(), links[0].utf16_end.unwrap());
+        assert_eq!(String::from_utf16(&utf16[start..end]).unwrap(), "project");
+        assert_eq!(
+            links[0].byte_start - start,
+            "üß🎉📚".len() - "üß🎉📚".encode_utf16().count()
+        );
+
+        // links cached before the offsets were recorded still deserialize
+        let cached: Link = Link::deser(
+            r#"{"source":"notes.md","target":"project.md","byte_start":1,"byte_end":2}"#,
+        );
+        assert_eq!(cached.utf16_start, None);
+    }
+
+    #[test]
+    fn link_url_test() {
+        let wikipedia = crate::vault::File::new(
+            PathBuf::from("wikipedia.md"),
+            "an encyclopedia\n".to_string(),
+        )
+        .unwrap();
+        let url_contents: &str = "https://en.wikipedia.org/wiki/Turing_machine <https://wikipedia.org> wikipedia@example.com (see www.wikipedia.org/wiki/A_(b)) on wikipedia\n";
+        let urls =
+            crate::vault::File::new(PathBuf::from("urls.md"), url_contents.to_string()).unwrap();
+        let link_finder = LinkFinder::new(vec![&wikipedia], true);
+
+        let links: Vec<Link> = link_finder.get_links(&urls);
+        assert_eq!(link_starts(&links), vec![url_contents.len() - 10]);
+    }
+
+    #[test]
+    fn link_tag_test() {
+        let links: Vec<Link> = project_links("#project/alpha is a project\n", |_| {});
+        assert_eq!(link_starts(&links), vec![20]);
+    }
+
     // #[test]
     // fn link_finder_coverage_test() {
     //     static PROJECT_DIR: Dir<'_> = include_dir!("test");
@@ -0,0 +1,134 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::Rule;
+use crate::prelude::*;
+
+// how many characters of context to keep on each side of the error in the excerpt
+const EXCERPT_RADIUS: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// line and column are 1 based, column counts characters not bytes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParseDiagnostic {
+    pub severity: Severity,
+    pub line: usize,
+    pub column: usize,
+    pub byte_offset: usize,
+    pub expected: Vec<String>,
+    pub excerpt: String,
+    pub message: String,
+}
+
+impl ParseDiagnostic {
+    // offset is where the parsed slice starts in source, pest positions are relative to the slice
+    pub(crate) fn from_pest(
+        error: &pest::error::Error<Rule>,
+        source: &str,
+        offset: usize,
+        severity: Severity,
+    ) -> Self {
+        let position: usize = match error.location {
+            pest::error::InputLocation::Pos(pos) => pos,
+            pest::error::InputLocation::Span((start, _)) => start,
+        };
+        let expected: Vec<String> = match &error.variant {
+            pest::error::ErrorVariant::ParsingError { positives, .. } => {
+                positives.iter().map(|rule| f!("{:?}", rule)).collect()
+            }
+            pest::error::ErrorVariant::CustomError { .. } => Vec::new(),
+        };
+        ParseDiagnostic::new(
+            source,
+            offset + position,
+            expected,
+            error.variant.message().to_string(),
+            severity,
+        )
+    }
+
+    pub(crate) fn new(
+        source: &str,
+        byte_offset: usize,
+        expected: Vec<String>,
+        message: String,
+        severity: Severity,
+    ) -> Self {
+        let byte_offset: usize = floor_char_boundary(source, byte_offset);
+        // a byte order mark is not part of the first line
+        let line_start: usize = source[..byte_offset]
+            .rfind('\n')
+            .map_or(
+                source.len() - source.trim_start_matches('\u{feff}').len(),
+                |i| i + 1,
+            )
+            .min(byte_offset);
+        let line_end: usize = source[byte_offset..]
+            .find('\n')
+            .map_or(source.len(), |i| byte_offset + i);
+        let line: usize = source[..byte_offset].matches('\n').count() + 1;
+        let column: usize = source[line_start..byte_offset].chars().count() + 1;
+
+        let before: String = source[line_start..byte_offset]
+            .chars()
+            .rev()
+            .take(EXCERPT_RADIUS)
+            .collect::<Vec<char>>()
+            .into_iter()
+            .rev()
+            .collect();
+        let after: String = source[byte_offset..line_end]
+            .chars()
+            .take(EXCERPT_RADIUS)
+            .collect();
+        let excerpt: String = f!("{}{}", before, after).trim_end_matches('\r').to_string();
+
+        ParseDiagnostic {
+            severity,
+            line,
+            column,
+            byte_offset,
+            expected,
+            excerpt,
+            message,
+        }
+    }
+
+    // the first expected rule, stable enough to group failures by
+    pub fn category(&self) -> &str {
+        self.expected.first().map_or("custom", |rule| rule.as_str())
+    }
+}
+
+impl fmt::Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}: {}\n    {}",
+            self.severity, self.line, self.column, self.message, self.excerpt
+        )
+    }
+}
+
+fn floor_char_boundary(source: &str, index: usize) -> usize {
+    let mut index: usize = index.min(source.len());
+    while !source.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
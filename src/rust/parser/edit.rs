@@ -0,0 +1,88 @@
+use super::{NodeEnum, ParsedMDFile};
+use crate::prelude::*;
+use crate::utils::TextEdit;
+
+// characters that would end or split the link a target is written into
+const TARGET_FORBIDDEN: [char; 6] = ['|', '[', ']', '#', '^', '\n'];
+
+impl ParsedMDFile {
+    // Turns start..end of a single text node into [[target|text]]. The link is
+    // written into the source and the blocks around it parsed again, which has
+    // to read it back as that link. Returns the edit made, in offsets of the
+    // source before the call.
+    pub fn wrap_in_link(
+        &mut self,
+        start: usize,
+        end: usize,
+        target: &str,
+    ) -> Result<Vec<TextEdit>> {
+        if target.is_empty() || target.contains(TARGET_FORBIDDEN) {
+            return Err(Error::Generic(f!("Invalid link target: {:?}", target)));
+        }
+        let text: &str = self
+            .get_nodes()
+            .into_iter()
+            .find_map(|node| match &node.node {
+                NodeEnum::Text if node.start <= start && end <= node.end => {
+                    Some(node.text(&self.source))
+                }
+                _ => None,
+            })
+            .ok_or_else(|| {
+                Error::Generic(f!(
+                    "No text node covers {}..{} in {}",
+                    start,
+                    end,
+                    self.path.display()
+                ))
+            })?;
+        let linked: &str = match self.source.get(start..end) {
+            Some(linked) if start < end && !text.is_empty() => linked,
+            _ => {
+                return Err(Error::Generic(f!(
+                    "Invalid range {}..{} for {:?}",
+                    start,
+                    end,
+                    text
+                )))
+            }
+        };
+
+        // inside a table the alias pipe has to be escaped
+        let pipe: &str = if self
+            .get_table_cells()
+            .iter()
+            .any(|cell| cell.start <= start && end <= cell.end)
+        {
+            "\\|"
+        } else {
+            "|"
+        };
+        let edit: TextEdit = TextEdit::new(start, end, f!("[[{}{}{}]]", target, pipe, linked));
+        let linked_file: ParsedMDFile = self.reparse(&edit)?;
+
+        // text around the range can make the new source read differently, such
+        // as a \ right before it, the file is only changed if the link is there
+        let link_end: usize = start + edit.replacement.len();
+        let source: &str = &linked_file.source;
+        let found: bool = linked_file.get_nodes().into_iter().any(|node| {
+            node.start == start
+                && node.end == link_end
+                && matches!(&node.node, NodeEnum::NamedMDLink(link)
+                    if link.target.text(source) == target
+                        && link.heading_path.is_empty()
+                        && link.block_id.is_none()
+                        && link.display.is_some_and(|display| display.text(source) == linked))
+        });
+        if !found {
+            return Err(Error::Generic(f!(
+                "Linking {}..{} does not read back as the link in {}",
+                start,
+                end,
+                self.path.display()
+            )));
+        }
+        *self = linked_file;
+        Ok(vec![edit])
+    }
+}
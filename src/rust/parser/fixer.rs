@@ -0,0 +1,229 @@
+use std::path::Path;
+
+use pest::Parser;
+use serde::{Deserialize, Serialize};
+
+use super::{parse_md_file_wrapper, MDParser, ParseDiagnostic, Rule};
+use crate::prelude::*;
+use crate::utils::{apply_edits, TextEdit};
+
+// characters the grammar only accepts in text when escaped, as listed in the README
+const ESCAPABLE: [char; 5] = ['*', '$', '>', '[', ']'];
+// largest number of edits tried together on one line
+const MAX_EDITS_PER_LINE: usize = 3;
+// candidates past this on a single line are not tried, keeps the search bounded
+const MAX_CANDIDATES_PER_LINE: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fix {
+    pub edit: TextEdit,
+    pub explanation: String,
+}
+
+// Finds escaping and whitespace edits that make contents parse, edits are in
+// offsets of contents. Each failing line gets the fewest edits that let the
+// parser move past it.
+pub fn fix_md_file(contents: &str, path: &Path) -> Result<Vec<Fix>> {
+    let mut working: String = contents.to_string();
+    if !working.ends_with('\n') {
+        working.push('\n');
+    }
+
+    let mut fixes: Vec<Fix> = Vec::new();
+    // working offset minus original offset, every edit so far is before the current line
+    let mut delta: isize = 0;
+    let mut pos: usize = yaml_end(&working);
+
+    while let Some(line_start) = first_unparsable(&working, pos) {
+        let line_end: usize = working[line_start..]
+            .find('\n')
+            .map_or(working.len(), |i| line_start + i);
+        let original_start: usize = (line_start as isize - delta) as usize;
+        let line: usize = contents[..original_start].matches('\n').count() + 1;
+
+        let candidates: Vec<Fix> = line_candidates(&working, line_start, line_end, line);
+        let chosen: Vec<Fix> = match search(&working, line_start, line_end, &candidates)? {
+            Some(chosen) => chosen,
+            None => {
+                return Err(Error::Generic(f!(
+                    "Could not find a fix for line {} of {}",
+                    line,
+                    path.display()
+                )))
+            }
+        };
+
+        let edits: Vec<TextEdit> = chosen.iter().map(|fix| fix.edit.clone()).collect();
+        working = apply_edits(&working, &edits)?;
+        let mut line_delta: isize = 0;
+        for fix in chosen {
+            line_delta += fix.edit.delta();
+            fixes.push(Fix {
+                edit: TextEdit::new(
+                    (fix.edit.start as isize - delta) as usize,
+                    (fix.edit.end as isize - delta) as usize,
+                    fix.edit.replacement,
+                ),
+                explanation: fix.explanation,
+            });
+        }
+        delta += line_delta;
+        // line_start is a block boundary, a fixed fence can open a block that runs past line_end
+        pos = line_start;
+    }
+
+    // the block by block search should agree with the full grammar, make sure it does
+    let fixed: String = apply_edits(
+        contents,
+        &fixes
+            .iter()
+            .map(|fix| fix.edit.clone())
+            .collect::<Vec<TextEdit>>(),
+    )?;
+    parse_md_file_wrapper(fixed, path.to_path_buf())?;
+
+    Ok(fixes)
+}
+
+// Whether fix_md_file has anything to try for a diagnostic, without searching.
+// Only failures of the markdown grammar qualify, and only when their line has a
+// character the fixer escapes.
+pub fn can_fix(contents: &str, diagnostic: &ParseDiagnostic) -> bool {
+    if diagnostic.expected.is_empty() || diagnostic.category() == f!("{:?}", Rule::yaml_inner) {
+        return false;
+    }
+    let offset: usize = diagnostic.byte_offset.min(contents.len());
+    let line_start: usize = contents[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end: usize = contents[offset..]
+        .find('\n')
+        .map_or(contents.len(), |i| offset + i);
+    !line_candidates(contents, line_start, line_end, diagnostic.line).is_empty()
+}
+
+fn yaml_end(contents: &str) -> usize {
+    match MDParser::parse(Rule::yaml, contents) {
+        Ok(mut pairs) => pairs.next().map_or(0, |pair| pair.as_span().end()),
+        Err(_) => 0,
+    }
+}
+
+// start of the first place no block can be parsed from, searching from pos
+fn first_unparsable(contents: &str, pos: usize) -> Option<usize> {
+    let mut pos: usize = pos;
+    while pos < contents.len() {
+        let consumed: usize = match MDParser::parse(Rule::block, &contents[pos..]) {
+            Ok(mut pairs) => pairs.next().map_or(0, |pair| pair.as_span().end()),
+            Err(_) => 0,
+        };
+        if consumed == 0 {
+            return Some(pos);
+        }
+        pos += consumed;
+    }
+    None
+}
+
+fn line_candidates(contents: &str, line_start: usize, line_end: usize, line: usize) -> Vec<Fix> {
+    let text: &str = &contents[line_start..line_end];
+    let mut candidates: Vec<Fix> = Vec::new();
+
+    let mut escaped: bool = false;
+    for (index, c) in text.char_indices() {
+        if !escaped && ESCAPABLE.contains(&c) {
+            candidates.push(Fix {
+                edit: TextEdit::insert(line_start + index, "\\"),
+                explanation: f!("Escape {} on line {} so it is read as text", c, line),
+            });
+        }
+        escaped = !escaped && c == '\\';
+    }
+
+    candidates.truncate(MAX_CANDIDATES_PER_LINE);
+    candidates
+}
+
+// tries every combination of candidates, smallest first, until the parser gets past the line
+fn search(
+    contents: &str,
+    line_start: usize,
+    line_end: usize,
+    candidates: &[Fix],
+) -> Result<Option<Vec<Fix>>> {
+    for size in 1..=MAX_EDITS_PER_LINE.min(candidates.len()) {
+        let mut indices: Vec<usize> = (0..size).collect();
+        loop {
+            let chosen: Vec<Fix> = indices.iter().map(|i| candidates[*i].clone()).collect();
+            let edits: Vec<TextEdit> = chosen.iter().map(|fix| fix.edit.clone()).collect();
+            let line_delta: isize = edits.iter().map(|edit| edit.delta()).sum();
+            let fixed: String = apply_edits(contents, &edits)?;
+            let fixed_line_end: usize = (line_end as isize + line_delta) as usize;
+            match first_unparsable(&fixed, line_start) {
+                Some(next) if next <= fixed_line_end => {}
+                _ => return Ok(Some(chosen)),
+            }
+            if !next_combination(&mut indices, candidates.len()) {
+                break;
+            }
+        }
+    }
+    Ok(None)
+}
+
+// advances indices to the next increasing combination out of n, false when done
+fn next_combination(indices: &mut [usize], n: usize) -> bool {
+    let k: usize = indices.len();
+    for i in (0..k).rev() {
+        if indices[i] < n - k + i {
+            indices[i] += 1;
+            for j in i + 1..k {
+                indices[j] = indices[j - 1] + 1;
+            }
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+pub mod fixer_test {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fixed(contents: &str) -> (String, Vec<Fix>) {
+        let fixes: Vec<Fix> = fix_md_file(contents, &PathBuf::from("fix.md")).unwrap();
+        let edits: Vec<TextEdit> = fixes.iter().map(|fix| fix.edit.clone()).collect();
+        (apply_edits(contents, &edits).unwrap(), fixes)
+    }
+
+    #[test]
+    fn fix_valid_file_test() {
+        let (text, fixes) = fixed("Nothing to fix here\n");
+        assert!(fixes.is_empty());
+        assert_eq!(text, "Nothing to fix here\n");
+    }
+
+    #[test]
+    fn fix_escapes_test() {
+        let (text, fixes) = fixed("# Title\nThis is **Important text\n1 > 0\nfine");
+        assert_eq!(text, "# Title\nThis is \\*\\*Important text\n1 \\> 0\nfine");
+        assert_eq!(fixes.len(), 3);
+        assert_eq!(fixes[0].edit, TextEdit::insert(16, "\\"));
+        assert_eq!(
+            fixes[2].explanation,
+            "Escape > on line 3 so it is read as text"
+        );
+    }
+
+    #[test]
+    fn fix_indented_fence_test() {
+        // indented fences parse as they are
+        let (text, fixes) = fixed("text\n   ```c\nint x = 1;\n```\n  $$\nx\n$$\n");
+        assert!(fixes.is_empty());
+        assert_eq!(text, "text\n   ```c\nint x = 1;\n```\n  $$\nx\n$$\n");
+
+        let (text, fixes) = fixed("   ```c\nint *x;\n```\n$x\n");
+        assert_eq!(text, "   ```c\nint *x;\n```\n\\$x\n");
+        assert_eq!(fixes.len(), 1);
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+use super::{ParseDiagnostic, Rule, Severity, Tag};
+use crate::prelude::*;
+
+// The properties between the --- lines. Obsidian accepts both the singular and
+// plural keys and a single string where a list is expected, so the well known
+// keys are read leniently. YAML that does not parse leaves every field empty
+// and keeps a diagnostic instead of failing the note.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Frontmatter {
+    pub aliases: Vec<String>,
+    pub tags: Vec<Tag>,
+    pub cssclasses: Vec<String>,
+    // every key as written, including the ones above
+    pub properties: serde_yaml::Mapping,
+    // the text between the --- lines
+    pub start: usize,
+    pub end: usize,
+    pub diagnostic: Option<ParseDiagnostic>,
+}
+
+impl Frontmatter {
+    // text is source[start..end]
+    pub(crate) fn parse(source: &str, start: usize, end: usize) -> Self {
+        let mut frontmatter: Frontmatter = Frontmatter {
+            start,
+            end,
+            ..Default::default()
+        };
+        let properties: serde_yaml::Mapping = match serde_yaml::from_str(&source[start..end]) {
+            Ok(serde_yaml::Value::Mapping(properties)) => properties,
+            Ok(serde_yaml::Value::Null) => return frontmatter,
+            Ok(_) => {
+                frontmatter.diagnostic = Some(yaml_diagnostic(
+                    source,
+                    start,
+                    "frontmatter is not a set of properties".to_string(),
+                ));
+                return frontmatter;
+            }
+            Err(error) => {
+                let offset: usize = start + error.location().map_or(0, |location| location.index());
+                frontmatter.diagnostic = Some(yaml_diagnostic(source, offset, error.to_string()));
+                return frontmatter;
+            }
+        };
+
+        for key in ["alias", "aliases"] {
+            frontmatter.aliases.extend(
+                list(properties.get(key))
+                    .into_iter()
+                    .map(|alias| alias.trim().to_string())
+                    .filter(|alias| !alias.is_empty()),
+            );
+        }
+        // a string of tags or classes may be split by commas or spaces
+        for key in ["tag", "tags"] {
+            frontmatter.tags.extend(
+                split_list(properties.get(key))
+                    .iter()
+                    .filter_map(|tag| Tag::parse(tag)),
+            );
+        }
+        for key in ["cssclass", "cssclasses"] {
+            frontmatter
+                .cssclasses
+                .extend(split_list(properties.get(key)));
+        }
+        frontmatter.properties = properties;
+        frontmatter
+    }
+
+    pub fn get(&self, key: &str) -> Option<&serde_yaml::Value> {
+        self.properties.get(key)
+    }
+
+    // the values of a property that may be a single value or a list
+    pub fn get_list(&self, key: &str) -> Vec<String> {
+        list(self.get(key))
+    }
+}
+
+fn yaml_diagnostic(source: &str, offset: usize, message: String) -> ParseDiagnostic {
+    ParseDiagnostic::new(
+        source,
+        offset,
+        vec![f!("{:?}", Rule::yaml_inner)],
+        message,
+        Severity::Warning,
+    )
+}
+
+// numbers and booleans count as strings, nested values are skipped
+fn scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(value) => Some(value.clone()),
+        serde_yaml::Value::Number(value) => Some(value.to_string()),
+        serde_yaml::Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn list(value: Option<&serde_yaml::Value>) -> Vec<String> {
+    match value {
+        Some(serde_yaml::Value::Sequence(values)) => values.iter().filter_map(scalar).collect(),
+        Some(value) => scalar(value).into_iter().collect(),
+        None => Vec::new(),
+    }
+}
+
+fn split_list(value: Option<&serde_yaml::Value>) -> Vec<String> {
+    match value {
+        Some(serde_yaml::Value::Sequence(_)) => list(value),
+        _ => list(value)
+            .iter()
+            .flat_map(|value| value.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string())
+            .collect(),
+    }
+}
@@ -0,0 +1,446 @@
+use pest::Parser;
+
+use super::normalize::needs_normalizing;
+use super::{
+    parse_blocks_recovering, parse_line, parse_md_file_recovering, Block, Line, LineIndex,
+    MDParser, ParseDiagnostic, ParsedMDFile, Rule, Shift, StringBlock,
+};
+use crate::prelude::*;
+use crate::utils::{apply_edits, TextEdit};
+
+// Reparses previous after edit, giving the same result as parse_md_file_recovering
+// on the edited text. Only the blocks around the edit are parsed again, inside a
+// string block only the lines around it. Everything after is reused with its
+// offsets moved.
+//
+// A block can depend on text after it in two ways: a block ends where the next
+// one starts, which the grammar decides by looking at most two lines ahead, and
+// an inline opener like ` or $ without a closer on its line scans ahead for one.
+// Parsing restarts one line before the edit, or earlier at any line with such an
+// opener when the edit adds or removes one of its closers, or at any unparsed
+// block whose failed parse looked past the restart. Looking for openers stops at
+// the first line above that closes every span that could reach the edit.
+pub fn parse_md_file_incremental(previous: &ParsedMDFile, edit: &TextEdit) -> Result<ParsedMDFile> {
+    let source: String = apply_edits(&previous.source, std::slice::from_ref(edit))?;
+    let body_start: usize = previous
+        .frontmatter
+        .as_ref()
+        .map_or(0, |frontmatter| frontmatter.end + "---".len());
+    let creates_frontmatter: bool =
+        previous.frontmatter.is_none() && source.starts_with("---") && source[3..].contains("---");
+    // text with \r endings or a byte order mark is parsed in full, its offsets are moved after
+    if edit.start < body_start
+        || creates_frontmatter
+        || needs_normalizing(&previous.source)
+        || needs_normalizing(&source)
+        || previous.block_spans.len() != previous.blocks.len()
+    {
+        return parse_md_file_recovering(source, previous.path.clone());
+    }
+
+    let mut old: String = previous.source.clone();
+    if !old.ends_with('\n') {
+        old.push('\n');
+    }
+    let mut contents: String = source.clone();
+    if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    let reparse: Reparse = Reparse {
+        old: &old,
+        spans: &previous.block_spans,
+        edit,
+        edit_end: edit.start + edit.replacement.len(),
+        // the newline appended to a file without one is not part of the text after the edit
+        reuse: edit.end < previous.source.len(),
+        shift: Shift::at(edit.end, edit.delta()),
+    };
+
+    // the line before the edit, or the first line whose openers the edit may close
+    let edit_line: usize = line_start(&old, edit.start);
+    let mut target: usize = if edit_line > body_start {
+        line_start(&old, edit_line - 1)
+    } else {
+        body_start
+    };
+    // whether a closer matches also depends on the rest of its line, and a line
+    // that is parsed again may hold the closer for an opener further up. The
+    // lines are kept apart by newlines so a closer is only found as written.
+    let edit_line_end: usize = old[edit.end..]
+        .find('\n')
+        .map_or(old.len(), |i| edit.end + i);
+    let mut touched: String = f!(
+        "{}\n{}{}{}",
+        &old[edit_line..edit_line_end],
+        &old[edit_line..edit.start],
+        edit.replacement,
+        &old[edit.end..edit_line_end]
+    );
+    // \[ and \( stop at a blank line, an edit adding or removing one next to
+    // it moves where they may close
+    let next_line_end: usize = match old.get(edit_line_end + 1..) {
+        Some(rest) if !rest.is_empty() => {
+            rest.find('\n').map_or(old.len(), |i| edit_line_end + 1 + i)
+        }
+        _ => edit_line_end,
+    };
+    let new_line_end: usize = next_line_end
+        .saturating_add_signed(edit.delta())
+        .min(contents.len());
+    if has_blank_line(&old[edit_line..next_line_end])
+        || contents
+            .get(edit_line..new_line_end)
+            .is_none_or(has_blank_line)
+    {
+        touched.push_str("\n\\]\n\\)");
+    }
+    // for each closer, whether the nearest line holding it below the one looked
+    // at ends every span opened above it, an opener further up cannot reach the
+    // edit past it and the scan stops once this holds for every touched closer
+    let mut closed: [bool; CLOSERS.len()] = [false; CLOSERS.len()];
+    let mut line: usize = target;
+    loop {
+        let line_end: usize = old[line..].find('\n').map_or(old.len(), |i| line + i);
+        let text: &str = &old[line..line_end];
+        // a block may also start inside the line, after a latex or code block
+        let first: usize = previous
+            .block_spans
+            .partition_point(|(start, _)| *start <= line);
+        let inside = previous.block_spans[first..]
+            .iter()
+            .map(|(start, _)| *start)
+            .take_while(|start| *start < line_end);
+        let scans: bool = std::iter::once(line)
+            .chain(inside)
+            .any(|start| scans_ahead(&old[start..line_end], &touched));
+        if scans {
+            touched.push('\n');
+            touched.push_str(text);
+            target = line;
+        }
+        for (closed, closer) in closed.iter_mut().zip(CLOSERS) {
+            if text.contains(closer) {
+                *closed = !scans && ends_spans(text, closer, &touched);
+            } else if has_blank_line(text) && (closer == "\\]" || closer == "\\)") {
+                // these spans end at a blank line
+                *closed = true;
+            }
+        }
+        let done: bool = CLOSERS
+            .iter()
+            .zip(&closed)
+            .all(|(closer, closed)| *closed || !touched.contains(closer));
+        if done || line <= body_start {
+            break;
+        }
+        line = line_start(&old, line - 1);
+    }
+    // a diagnostic points at the last opener the failed parse tried and quotes its line
+    let failed_across: Option<usize> = previous
+        .blocks
+        .iter()
+        .zip(&previous.block_spans)
+        .take_while(|(_, (start, _))| *start < target)
+        .find_map(|(block, (start, _))| match block {
+            Block::Unparsed(unparsed) if unparsed.diagnostic.byte_offset >= target => Some(*start),
+            _ => None,
+        });
+    let target: usize = failed_across.unwrap_or(target).min(target).max(body_start);
+
+    let mut result: ParsedMDFile = ParsedMDFile {
+        frontmatter: previous.frontmatter.clone(),
+        blocks: Vec::new(),
+        block_spans: Vec::new(),
+        line_index: LineIndex::new(&source),
+        source,
+        path: previous.path.clone(),
+    };
+    let block: usize = match previous
+        .block_spans
+        .iter()
+        .position(|(_, end)| *end > target)
+    {
+        Some(block) => block,
+        None => previous.blocks.len(),
+    };
+    let line: usize = match previous.block_spans.get(block) {
+        Some((start, _)) => old[*start..target].matches('\n').count(),
+        None => 0,
+    };
+
+    // block may only be kept in part when its first lines are untouched
+    let mut pos: usize;
+    match previous.blocks.get(block) {
+        Some(Block::String(string_block))
+            if line >= 1 && reparse.one_line_each(block, string_block) =>
+        {
+            result
+                .blocks
+                .extend(previous.blocks[..block].iter().cloned());
+            result
+                .block_spans
+                .extend(previous.block_spans[..block].iter().cloned());
+            pos = target;
+            let kept: Vec<Line> = string_block.lines[..line].to_vec();
+            if reparse.continue_string_block(
+                &contents,
+                &mut pos,
+                &mut result,
+                kept,
+                block,
+                previous,
+            )? {
+                reparse.append_tail(&contents, &mut result, previous, block + 1);
+                result.shrink_to_fit();
+                return Ok(result);
+            }
+        }
+        _ => {
+            let restart: usize = if line >= 1 {
+                block
+            } else {
+                block.saturating_sub(1)
+            };
+            result
+                .blocks
+                .extend(previous.blocks[..restart].iter().cloned());
+            result
+                .block_spans
+                .extend(previous.block_spans[..restart].iter().cloned());
+            pos = match previous.block_spans.get(restart) {
+                Some((start, _)) => *start,
+                None => previous
+                    .block_spans
+                    .last()
+                    .map_or(body_start, |(_, end)| *end),
+            };
+        }
+    }
+
+    pos = parse_blocks_recovering(&contents, pos, &previous.path, &mut result, |pos| {
+        reparse.old_block_at(pos).is_some()
+    })?;
+    if let Some(index) = reparse.old_block_at(pos) {
+        reparse.append_tail(&contents, &mut result, previous, index);
+    }
+    result.shrink_to_fit();
+    Ok(result)
+}
+
+struct Reparse<'a> {
+    old: &'a str,
+    spans: &'a [(usize, usize)],
+    edit: &'a TextEdit,
+    // end of the replacement in the new text
+    edit_end: usize,
+    reuse: bool,
+    // moves offsets after the edit from the old text to the new one
+    shift: Shift<'static>,
+}
+
+impl Reparse<'_> {
+    // the old position of pos when it lies after the edit in the new text
+    fn old_pos(&self, pos: usize) -> Option<usize> {
+        if pos < self.edit_end || !self.reuse {
+            return None;
+        }
+        let old_pos: usize = (pos as isize - self.edit.delta()) as usize;
+        Some(old_pos).filter(|old_pos| *old_pos >= self.edit.end)
+    }
+
+    // index of the old block starting at pos, parsing can stop there
+    fn old_block_at(&self, pos: usize) -> Option<usize> {
+        let old_pos: usize = self.old_pos(pos)?;
+        self.spans
+            .binary_search_by_key(&old_pos, |(start, _)| *start)
+            .ok()
+    }
+
+    // a line holding a multi line code span has no line break of its own
+    fn one_line_each(&self, block: usize, string_block: &StringBlock) -> bool {
+        let (start, end): (usize, usize) = self.spans[block];
+        self.old[start..end].matches('\n').count() == string_block.lines.len()
+    }
+
+    // Parses lines of the string block at index block from pos until one fails
+    // or an old line after the edit is reached. Returns true when the rest of
+    // the old block was reused, so the next block starts where it did before.
+    fn continue_string_block(
+        &self,
+        contents: &str,
+        pos: &mut usize,
+        result: &mut ParsedMDFile,
+        mut lines: Vec<Line>,
+        block: usize,
+        previous: &ParsedMDFile,
+    ) -> Result<bool> {
+        let (start, end): (usize, usize) = self.spans[block];
+        let old_lines: &[Line] = match &previous.blocks[block] {
+            Block::String(string_block) => &string_block.lines,
+            _ => &[],
+        };
+        let old_line_starts: Vec<usize> = std::iter::once(start)
+            .chain(
+                self.old[start..end]
+                    .match_indices('\n')
+                    .map(|(i, _)| start + i + 1),
+            )
+            .collect();
+
+        loop {
+            let old_line: Option<usize> = self
+                .old_pos(*pos)
+                .and_then(|old_pos| old_line_starts.binary_search(&old_pos).ok());
+            if let Some(index) = old_line.filter(|index| *index < old_lines.len()) {
+                lines.extend(old_lines[index..].iter().cloned().map(|mut line| {
+                    line.shift(self.shift);
+                    line
+                }));
+                let mut end: usize = end;
+                self.shift.end(&mut end);
+                result
+                    .blocks
+                    .push(Block::String(StringBlock { lines, start, end }));
+                result.block_spans.push((start, end));
+                *pos = end;
+                return Ok(true);
+            }
+            let pair = match MDParser::parse(Rule::string_block_line, &contents[*pos..]) {
+                Ok(mut pairs) => match pairs.next() {
+                    Some(pair) => pair,
+                    None => break,
+                },
+                Err(_) => break,
+            };
+            let line_end: usize = *pos + pair.as_span().end();
+            let mut line: Line = parse_line(pair, &previous.path)?;
+            line.shift(Shift::by(*pos));
+            lines.push(line);
+            *pos = line_end
+                + if contents[line_end..].starts_with("\r\n") {
+                    2
+                } else {
+                    1
+                };
+        }
+        result.blocks.push(Block::String(StringBlock {
+            lines,
+            start,
+            end: *pos,
+        }));
+        result.block_spans.push((start, *pos));
+        Ok(false)
+    }
+
+    // reuses the old blocks from index on, they only move by the edit's change in length
+    fn append_tail(
+        &self,
+        contents: &str,
+        result: &mut ParsedMDFile,
+        previous: &ParsedMDFile,
+        index: usize,
+    ) {
+        for (block, (start, end)) in previous.blocks[index..]
+            .iter()
+            .zip(&previous.block_spans[index..])
+        {
+            let mut block: Block = block.clone();
+            block.shift(self.shift);
+            let (mut start, mut end): (usize, usize) = (*start, *end);
+            self.shift.start(&mut start);
+            self.shift.end(&mut end);
+            if let Block::Unparsed(unparsed) = &mut block {
+                // line numbers after the edit may have changed
+                let diagnostic: &ParseDiagnostic = &unparsed.diagnostic;
+                let mut offset: usize = diagnostic.byte_offset;
+                self.shift.start(&mut offset);
+                unparsed.diagnostic = ParseDiagnostic::new(
+                    contents,
+                    offset,
+                    diagnostic.expected.clone(),
+                    diagnostic.message.clone(),
+                    diagnostic.severity,
+                );
+                // consecutive unparsed lines form one block
+                if let Some(Block::Unparsed(last)) = result.blocks.last_mut() {
+                    if last.end == unparsed.start {
+                        last.end = unparsed.end;
+                        if let Some(span) = result.block_spans.last_mut() {
+                            span.1 = end;
+                        }
+                        continue;
+                    }
+                }
+            }
+            result.blocks.push(block);
+            result.block_spans.push((start, end));
+        }
+    }
+}
+
+fn line_start(text: &str, pos: usize) -> usize {
+    text[..pos].rfind('\n').map_or(0, |i| i + 1)
+}
+
+// what the openers that may scan past their line close with
+const CLOSERS: [&str; 9] = ["$", "`", "]", ")", "%%", "-->", "\\]", "\\)", "\\end{"];
+
+// whether an opener on this line may scan past its end for a closer on the edited lines
+fn scans_ahead(line: &str, touched: &str) -> bool {
+    // one kind of delimiter can hide another, like a ` inside a comment, so the
+    // counts below only tell when a single kind is on the line
+    let mixed: bool = [
+        line.contains('$'),
+        line.contains('`'),
+        line.contains("%%") || line.contains("<!--"),
+        line.contains(['[', ']', '(', ')']),
+        line.contains('\\'),
+    ]
+    .iter()
+    .filter(|kind| **kind)
+    .count()
+        > 1;
+    let odd = |pattern: &str| mixed || line.matches(pattern).count() % 2 == 1;
+    let unescaped_dollars: usize = line.matches('$').count() - line.matches("\\$").count();
+    let dollar: bool = odd("$") || unescaped_dollars % 2 == 1 || odd("$$");
+    let backtick: bool = odd("`") || odd("```");
+    // an opener after the last closer on the line, None sorts before any position
+    let bracket: bool = mixed || line.rfind('[') > line.rfind(']');
+    let paren: bool = mixed || line.rfind("](") > line.rfind(')');
+    let html_comment: bool = mixed
+        || line
+            .rfind("<!--")
+            .is_some_and(|i| !line[i + "<!--".len()..].contains("-->"));
+
+    (dollar && line.contains('$') && touched.contains('$'))
+        || (backtick && line.contains('`') && touched.contains('`'))
+        || (bracket && line.contains('[') && touched.contains(']'))
+        || (paren && line.contains("](") && touched.contains(')'))
+        || (odd("%%") && line.contains("%%") && touched.contains("%%"))
+        || (html_comment && line.contains("<!--") && touched.contains("-->"))
+        || (line.contains("\\[") && touched.contains("\\]"))
+        || (line.contains("\\(") && touched.contains("\\)"))
+        || (line.contains("\\begin{") && touched.contains("\\end{"))
+}
+
+// Whether a line holding closer ends the span of any opener above it, the
+// openers close at their first closer and the rest of the line must not scan
+// ahead in turn.
+fn ends_spans(line: &str, closer: &str, touched: &str) -> bool {
+    let ends = |closer: &str| {
+        line.find(closer)
+            .is_some_and(|at| !scans_ahead(&line[at + closer.len()..], touched))
+    };
+    match closer {
+        // $ opens inline latex and $$ a latex block, the latter needs $$ to close
+        "$" => ends("$") && ends("$$"),
+        // an environment only closes at its own name
+        "\\end{" => false,
+        _ => ends(closer),
+    }
+}
+
+fn has_blank_line(text: &str) -> bool {
+    text.split('\n')
+        .any(|line| line.trim_matches([' ', '\t']).is_empty())
+}
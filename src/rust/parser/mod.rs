@@ -1,4 +1,3 @@
-use core::panic;
 use std::{
     f32::consts::E,
     path::{Path, PathBuf},
@@ -56,13 +55,109 @@ md_file = { SOI ~ yaml? ~ block+ ~ EOI }
 */
 
 use crate::prelude::*;
+use crate::settings::Settings;
+use crate::utils::TextEdit;
+
+mod diagnostic;
+mod edit;
+mod fixer;
+mod frontmatter;
+mod incremental;
+mod normalize;
+mod outline;
+mod render;
+mod span;
+pub mod visit;
+pub use diagnostic::{ParseDiagnostic, Severity};
+pub use fixer::{can_fix, fix_md_file, Fix};
+pub use frontmatter::Frontmatter;
+pub use incremental::parse_md_file_incremental;
+use normalize::Normalized;
+use outline::indent_width;
+pub use outline::{ListItem, Outline, Section, SectionItem};
+pub use render::render;
+pub use span::{LineIndex, Location, Position, Span};
+use visit::{AllNodes, ShiftOffsets, StringNodes, TableCells};
+pub use visit::{Emphasis, OpenHeading, VisitContext, Visitor, VisitorMut};
 
 #[derive(Parser)]
 #[grammar = "src/rust/parser/md.pest"]
 pub struct MDParser;
 
+// Moves byte offsets after the source changed. Starts at or after from move,
+// ends only when past from, so a span ending where text is inserted keeps its
+// end unless every offset moves.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Shift<'a> {
+    from: usize,
+    delta: isize,
+    all: bool,
+    // offsets before each of which one byte was dropped, see Shift::restore
+    dropped: &'a [usize],
+}
+
+impl Shift<'_> {
+    // every offset moves, used when a block was parsed from a slice
+    pub(crate) fn by(offset: usize) -> Shift<'static> {
+        Shift {
+            from: 0,
+            delta: offset as isize,
+            all: true,
+            dropped: &[],
+        }
+    }
+
+    // text of length delta was inserted at from, or removed when negative
+    pub(crate) fn at(from: usize, delta: isize) -> Shift<'static> {
+        Shift {
+            from,
+            delta,
+            all: false,
+            dropped: &[],
+        }
+    }
+
+    // puts back the bytes dropped when the text was normalized, offset bytes
+    // at the start and one before each of the sorted dropped offsets
+    pub(crate) fn restore(offset: usize, dropped: &[usize]) -> Shift<'_> {
+        Shift {
+            from: 0,
+            delta: offset as isize,
+            all: true,
+            dropped,
+        }
+    }
+
+    pub(crate) fn start(&self, pos: &mut usize) {
+        *pos += self.dropped.partition_point(|dropped| *dropped < *pos);
+        if self.all || *pos >= self.from {
+            *pos = (*pos as isize + self.delta) as usize;
+        }
+    }
+
+    pub(crate) fn end(&self, pos: &mut usize) {
+        *pos += self.dropped.partition_point(|dropped| *dropped < *pos);
+        if self.all || *pos > self.from {
+            *pos = (*pos as isize + self.delta) as usize;
+        }
+    }
+
+    // an empty span, such as a blank line, moves along with its start
+    pub(crate) fn span(&self, start: &mut usize, end: &mut usize) {
+        let empty: bool = *start == *end;
+        self.start(start);
+        if empty {
+            *end = *start;
+        } else {
+            self.end(end);
+        }
+    }
+}
+
 pub fn parse_md_file_wrapper(contents: String, path: PathBuf) -> Result<ParsedMDFile> {
-    let mut contents = contents;
+    let normalized: Normalized = Normalized::new(&contents);
+    let source: String = contents;
+    let mut contents = normalized.text.clone();
     if !&contents.ends_with('\n') {
         contents.push('\n');
     }
@@ -71,10 +166,12 @@ pub fn parse_md_file_wrapper(contents: String, path: PathBuf) -> Result<ParsedMD
     let mut md_file = match parse_result {
         Ok(md_file) => md_file,
         Err(e) => {
-            return Err(Error::ParseError(
+            let diagnostic: ParseDiagnostic =
+                ParseDiagnostic::from_pest(&e, &contents, 0, Severity::Error);
+            return Err(Error::Diagnostic(
                 path.to_path_buf(),
-                format!("Error: {}", e),
-            ))
+                Box::new(normalized.restore_diagnostic(&source, &diagnostic)),
+            ));
         }
     };
     let pairs_result = md_file.next();
@@ -83,20 +180,124 @@ pub fn parse_md_file_wrapper(contents: String, path: PathBuf) -> Result<ParsedMD
         None => {
             return Err(Error::ParseError(
                 path.to_path_buf(),
-                format!("No parse result"),
+                "No parse result".to_string(),
             ))
         }
     };
 
     let mut md_file_struct: ParsedMDFile = parse_md_file(pairs, &path)?;
+    md_file_struct.line_index = LineIndex::new(&source);
+    md_file_struct.source = source;
     md_file_struct.path = path;
+    normalized.restore(&mut md_file_struct);
+    md_file_struct.shrink_to_fit();
     Ok(md_file_struct)
 }
 
+// Parses the file one block at a time, any line that cannot start a block
+// becomes a Block::Unparsed so the rest of the file is still usable.
+pub fn parse_md_file_recovering(contents: String, path: PathBuf) -> Result<ParsedMDFile> {
+    let normalized: Normalized = Normalized::new(&contents);
+    let source: String = contents;
+    let mut contents = normalized.text.clone();
+    if !&contents.ends_with('\n') {
+        contents.push('\n');
+    }
+
+    let mut result: ParsedMDFile = ParsedMDFile {
+        frontmatter: None,
+        blocks: Vec::new(),
+        block_spans: Vec::new(),
+        line_index: LineIndex::new(&source),
+        source,
+        path: Default::default(),
+    };
+
+    let mut pos: usize = 0;
+    if let Ok(mut yaml_pairs) = MDParser::parse(Rule::yaml, &contents) {
+        if let Some(pair) = yaml_pairs.next() {
+            pos = pair.as_span().end();
+            result.frontmatter = Some(parse_yaml(pair, &path)?);
+        }
+    }
+
+    parse_blocks_recovering(&contents, pos, &path, &mut result, |_| false)?;
+    result.path = path;
+    normalized.restore(&mut result);
+    result.shrink_to_fit();
+    Ok(result)
+}
+
+// Appends the blocks from pos onwards to result, stopping early at the first
+// block start where stop returns true. Returns where parsing stopped.
+fn parse_blocks_recovering(
+    contents: &str,
+    mut pos: usize,
+    path: &Path,
+    result: &mut ParsedMDFile,
+    stop: impl Fn(usize) -> bool,
+) -> Result<usize> {
+    while pos < contents.len() && !stop(pos) {
+        let rest: &str = &contents[pos..];
+        match MDParser::parse(Rule::block, rest) {
+            Ok(mut block_pairs) => {
+                let pair = match block_pairs.next() {
+                    Some(pair) => pair,
+                    None => {
+                        return Err(Error::ParseError(
+                            path.to_path_buf(),
+                            "No parse result".to_string(),
+                        ))
+                    }
+                };
+                let consumed: usize = pair.as_span().end();
+                let mut block: Block = parse_block(pair, path)?;
+                block.shift(Shift::by(pos));
+                result.blocks.push(block);
+                result.block_spans.push((pos, pos + consumed));
+                pos += consumed;
+            }
+            Err(e) => {
+                let line_len: usize = rest.find('\n').map(|i| i + 1).unwrap_or(rest.len());
+                let end: usize = pos + line_len;
+                match result.blocks.last_mut() {
+                    Some(Block::Unparsed(unparsed)) if unparsed.end == pos => {
+                        unparsed.end = end;
+                        if let Some(span) = result.block_spans.last_mut() {
+                            span.1 = end;
+                        }
+                    }
+                    _ => {
+                        result.blocks.push(Block::Unparsed(UnparsedBlock {
+                            diagnostic: ParseDiagnostic::from_pest(
+                                &e,
+                                contents,
+                                pos,
+                                Severity::Warning,
+                            ),
+                            start: pos,
+                            end,
+                        }));
+                        result.block_spans.push((pos, end));
+                    }
+                }
+                pos = end;
+            }
+        }
+    }
+    Ok(pos)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParsedMDFile {
-    pub yaml: Option<YAML>,
+    pub frontmatter: Option<Frontmatter>,
     pub blocks: Vec<Block>,
+    // where each top level block starts and ends in the parsed text
+    pub block_spans: Vec<(usize, usize)>,
+    // where the lines of source start, for positions in lines and UTF-16
+    pub line_index: LineIndex,
+    // the text that was parsed, kept in step with the blocks by the edit helpers
+    pub source: String,
 
     pub path: PathBuf, // absolute path to the file
 }
@@ -105,8 +306,27 @@ impl ParsedMDFile {
     pub fn new(path: PathBuf, contents: String) -> Result<Self> {
         parse_md_file_wrapper(contents, path)
     }
-    pub fn get_yaml(&self) -> Option<&serde_yaml::Value> {
-        self.yaml.as_ref().map(|yaml| &yaml.yaml)
+    pub fn new_recovering(path: PathBuf, contents: String) -> Result<Self> {
+        parse_md_file_recovering(contents, path)
+    }
+    // the file after edit, parsing again only around it
+    pub fn reparse(&self, edit: &TextEdit) -> Result<Self> {
+        parse_md_file_incremental(self, edit)
+    }
+    pub fn get_frontmatter(&self) -> Option<&Frontmatter> {
+        self.frontmatter.as_ref()
+    }
+    pub(crate) fn shrink_to_fit(&mut self) {
+        shrink_blocks(&mut self.blocks);
+        self.block_spans.shrink_to_fit();
+        self.source.shrink_to_fit();
+    }
+    // line, column and UTF-16 offset of a byte offset into source
+    pub fn position(&self, byte: usize) -> Position {
+        self.line_index.position(&self.source, byte)
+    }
+    pub fn location(&self, span: Span) -> Location {
+        self.line_index.location(&self.source, span)
     }
 
     // pub fn get_blocks(&self) -> &Vec<Block> {
@@ -118,77 +338,238 @@ impl ParsedMDFile {
         self.path.file_stem().unwrap().to_str().unwrap()
     }
 
-    pub fn get_aliases(&self) -> Result<Vec<&str>> {
-        let yaml: &serde_yaml::Value = match self.yaml.as_ref() {
-            Some(yaml) => &yaml.yaml,
-            None => {
-                return Err(Error::Generic(format!(
-                    "No yaml for file: {}",
-                    self.path.display()
-                )))
-            }
-        };
+    // alias and aliases, each a single string or a list
+    pub fn get_aliases(&self) -> Vec<&str> {
+        self.get_frontmatter()
+            .map(|frontmatter| {
+                frontmatter
+                    .aliases
+                    .iter()
+                    .map(|alias| alias.as_str())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 
-        let aliases: Option<&Vec<serde_yaml::Value>> = yaml["aliases"].as_sequence();
-        let aliases: &Vec<serde_yaml::Value> = match aliases {
-            Some(aliases) => aliases,
-            None => {
-                return Err(Error::Generic(format!(
-                    "No aliases for file: {}",
-                    self.path.display()
-                )))
-            }
-        };
-        for alias in aliases {
-            let alias: &serde_yaml::Value = alias;
-            let alias: &str = match alias.as_str() {
-                Some(alias) => alias,
-                None => {
-                    return Err(Error::Generic(format!(
-                        "Alias: {:?} is not a string for file: {}",
-                        alias,
-                        self.path.display()
-                    )))
-                }
-            };
-        }
-        let aliases: Vec<&str> = aliases
+    pub fn get_nodes(&self) -> Vec<&Node> {
+        let mut visitor: AllNodes = AllNodes { nodes: Vec::new() };
+        visitor.visit_file(self, &mut VisitContext::new());
+        visitor.nodes
+    }
+
+    pub fn get_wikilinks(&self) -> Vec<&WikiLink> {
+        self.get_nodes()
+            .into_iter()
+            .filter_map(|node| match &node.node {
+                NodeEnum::MDLink(link) | NodeEnum::NamedMDLink(link) => Some(link.as_ref()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn get_embeds(&self) -> Vec<&Embed> {
+        self.get_nodes()
+            .into_iter()
+            .filter_map(|node| match &node.node {
+                NodeEnum::Embed(embed) => Some(embed.as_ref()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn get_images(&self) -> Vec<&Image> {
+        self.get_nodes()
+            .into_iter()
+            .filter_map(|node| match &node.node {
+                NodeEnum::Image(image) => Some(image.as_ref()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // sections nested by heading level with their list items nested by indent
+    pub fn get_outline(&self) -> Outline<'_> {
+        Outline::new(self)
+    }
+
+    // the markdown for the current blocks, equal to source until they are edited
+    pub fn render(&self) -> String {
+        render(self)
+    }
+
+    pub fn get_urls(&self) -> Vec<&Url> {
+        self.get_nodes()
+            .into_iter()
+            .filter_map(|node| match &node.node {
+                NodeEnum::Url(url) => Some(url),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn get_footnote_definitions(&self) -> Vec<&FootnoteDefinition> {
+        self.blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::FootnoteDefinition(footnote) => Some(footnote),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn get_block_ids(&self) -> Vec<&LinkPart> {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.get_lines())
+            .filter_map(|line| line.get_block_id())
+            .collect()
+    }
+
+    // the line marked with ^id, what [[note#^id]] points at
+    pub fn get_block(&self, id: &str) -> Option<&Line> {
+        self.blocks
             .iter()
-            .map(|alias| {
-                alias
-                    .as_str()
-                    .expect("Non strings should have been caught above")
+            .flat_map(|block| block.get_lines())
+            .find(|line| {
+                line.get_block_id()
+                    .is_some_and(|block_id| block_id.text(&self.source) == id)
+            })
+    }
+
+    // body tags in document order followed by the frontmatter tags
+    pub fn get_tags(&self) -> Vec<Tag> {
+        let mut tags: Vec<Tag> = self
+            .get_nodes()
+            .into_iter()
+            .filter_map(|node| match &node.node {
+                NodeEnum::Tag => node.tag(&self.source),
+                _ => None,
             })
             .collect();
-        Ok(aliases)
+        tags.extend(self.get_frontmatter_tags());
+        tags
+    }
+
+    // tags: [a, b], tags: a, b or the singular tag key
+    pub fn get_frontmatter_tags(&self) -> Vec<Tag> {
+        self.get_frontmatter()
+            .map(|frontmatter| frontmatter.tags.clone())
+            .unwrap_or_default()
+    }
+
+    // dataview key:: value fields in document order, the value as written
+    pub fn get_inline_fields(&self) -> Vec<(&str, &str)> {
+        self.get_nodes()
+            .into_iter()
+            .filter_map(|node| match &node.node {
+                NodeEnum::InlineField(field) => {
+                    let text: &str = match (field.value.first(), field.value.last()) {
+                        (Some(first), Some(last)) => &self.source[first.start..last.end],
+                        _ => "",
+                    };
+                    Some((field.key.text(&self.source), text.trim()))
+                }
+                _ => None,
+            })
+            .collect()
     }
 
-    pub fn get_string_nodes(&self) -> Vec<Node> {
-        let mut nodes: Vec<Node> = Vec::new();
-        for block in &self.blocks {
-            for node in block.get_string_nodes() {
-                nodes.push(node);
+    // the frontmatter properties with the inline fields added as strings, a key
+    // given more than once holds a list of its values like dataview
+    pub fn get_properties(&self) -> serde_yaml::Mapping {
+        let mut properties: serde_yaml::Mapping = self
+            .get_frontmatter()
+            .map(|frontmatter| frontmatter.properties.clone())
+            .unwrap_or_default();
+        for (key, value) in self.get_inline_fields() {
+            let key: serde_yaml::Value = serde_yaml::Value::String(key.to_string());
+            let value: serde_yaml::Value = serde_yaml::Value::String(value.to_string());
+            match properties.get_mut(&key) {
+                Some(serde_yaml::Value::Sequence(values)) => values.push(value),
+                Some(existing) => {
+                    let first: serde_yaml::Value = existing.clone();
+                    *existing = serde_yaml::Value::Sequence(vec![first, value]);
+                }
+                None => {
+                    properties.insert(key, value);
+                }
             }
         }
-        nodes
+        properties
+    }
+
+    // ```dataview and ```dataviewjs blocks, quotes and callouts hold no code blocks
+    pub fn get_dataview_queries(&self) -> Vec<&CodeBlock> {
+        self.blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Code(code_block) if code_block.is_dataview_query(&self.source) => {
+                    Some(code_block)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn get_tasks(&self) -> Vec<&Task> {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.get_lines())
+            .filter_map(|line| match line {
+                Line::Task(task) => Some(task),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // including the tables inside quotes and callouts
+    pub fn get_table_cells(&self) -> Vec<&TableCell> {
+        let mut visitor: TableCells = TableCells { cells: Vec::new() };
+        visitor.visit_file(self, &mut VisitContext::new());
+        visitor.cells
+    }
+
+    pub fn get_unparsed_blocks(&self) -> Vec<&UnparsedBlock> {
+        self.blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Unparsed(unparsed) => Some(unparsed),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn get_string_nodes(&self, settings: &Settings) -> Vec<Node> {
+        let mut visitor: StringNodes = StringNodes {
+            settings,
+            source: &self.source,
+            nodes: Vec::new(),
+        };
+        visitor.visit_file(self, &mut VisitContext::new());
+        visitor.nodes
     }
 }
 
 fn parse_md_file(pairs: pest::iterators::Pair<Rule>, path: &Path) -> Result<ParsedMDFile> {
     debug_assert!(pairs.as_rule() == Rule::md_file);
     let mut result: ParsedMDFile = ParsedMDFile {
-        yaml: None,
+        frontmatter: None,
         blocks: Vec::new(),
+        block_spans: Vec::new(),
+        line_index: LineIndex::new(""),
+        source: String::new(),
         path: Default::default(),
     };
 
     for pair in pairs.into_inner() {
         match pair.as_rule() {
             Rule::yaml => {
-                result.yaml = Some(parse_yaml(pair, &path)?);
+                result.frontmatter = Some(parse_yaml(pair, path)?);
             }
             Rule::block => {
-                result.blocks.push(parse_block(pair, &path)?);
+                let span: pest::Span = pair.as_span();
+                result.blocks.push(parse_block(pair, path)?);
+                result.block_spans.push((span.start(), span.end()));
             }
             Rule::EOI => {}
             _ => {
@@ -202,100 +583,197 @@ fn parse_md_file(pairs: pest::iterators::Pair<Rule>, path: &Path) -> Result<Pars
     Ok(result)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-
-pub struct YAML {
-    pub yaml: serde_yaml::Value,
-}
-
-fn parse_yaml(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<YAML> {
+// malformed yaml is kept as a diagnostic on the frontmatter, the rest of the note still parses
+fn parse_yaml(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Frontmatter> {
     debug_assert!(pair.as_rule() == Rule::yaml);
 
-    for pair_inner in pair.into_inner() {
-        match pair_inner.as_rule() {
-            Rule::yaml_inner => {
-                return Ok(YAML {
-                    yaml: serde_yaml::from_str(pair_inner.as_str()).unwrap(),
-                });
-            }
-            _ => {
-                return Err(Error::ParseError(
-                    path.to_path_buf(),
-                    format!("unexpected rule 2: {:?}", pair_inner.as_rule()),
-                ))
-            }
+    match pair.into_inner().next() {
+        Some(pair_inner) if pair_inner.as_rule() == Rule::yaml_inner => {
+            let span: pest::Span = pair_inner.as_span();
+            Ok(Frontmatter::parse(
+                span.get_input(),
+                span.start(),
+                span.end(),
+            ))
         }
+        Some(pair_inner) => Err(Error::ParseError(
+            path.to_path_buf(),
+            format!("unexpected rule 2: {:?}", pair_inner.as_rule()),
+        )),
+        None => Err(Error::ParseError(
+            path.to_path_buf(),
+            "pairs inner is empty".to_string(),
+        )),
     }
-    return Err(Error::ParseError(
-        path.to_path_buf(),
-        format!("pairs inner is empty"),
-    ));
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Block {
+    Callout(Callout),
     BlockQuote(BlockQuote),
     Latex(LatexBlock),
     Code(CodeBlock),
+    Table(Table),
+    FootnoteDefinition(FootnoteDefinition),
+    Html(HtmlBlock),
     String(StringBlock),
+    Unparsed(UnparsedBlock),
 }
 
 impl Block {
-    pub fn get_string_nodes(&self) -> Vec<Node> {
-        let mut nodes: Vec<Node> = Vec::new();
+    pub fn get_string_nodes(&self, settings: &Settings, source: &str) -> Vec<Node> {
+        let mut visitor: StringNodes = StringNodes {
+            settings,
+            source,
+            nodes: Vec::new(),
+        };
+        visitor.visit_block(self, &mut VisitContext::new());
+        visitor.nodes
+    }
+
+    // every node in the block, nested ones included, regardless of whether it can be linked
+    pub fn get_nodes(&self) -> Vec<&Node> {
+        let mut visitor: AllNodes = AllNodes { nodes: Vec::new() };
+        visitor.visit_block(self, &mut VisitContext::new());
+        visitor.nodes
+    }
+
+    // every line in the block, including those inside quotes and callouts
+    pub fn get_lines(&self) -> Vec<&Line> {
         match self {
-            Block::BlockQuote(block_quote) => {
-                for block in &block_quote.inner_blocks {
-                    for node in block.get_string_nodes() {
-                        nodes.push(node);
-                    }
-                }
+            Block::Callout(callout) => callout
+                .inner_blocks
+                .iter()
+                .flat_map(|block| block.get_lines())
+                .collect(),
+            Block::BlockQuote(block_quote) => block_quote
+                .inner_blocks
+                .iter()
+                .flat_map(|block| block.get_lines())
+                .collect(),
+            Block::FootnoteDefinition(footnote) => footnote.lines.iter().collect(),
+            Block::String(string_block) => string_block.lines.iter().collect(),
+            Block::Latex(_)
+            | Block::Code(_)
+            | Block::Table(_)
+            | Block::Html(_)
+            | Block::Unparsed(_) => Vec::new(),
+        }
+    }
+
+    // Where the block is in the source. Blocks that take whole lines end after
+    // their last line break, latex and code blocks where their closing
+    // delimiter does, nested blocks where their last line does.
+    pub fn span(&self) -> Span {
+        match self {
+            Block::Callout(callout) => Span::new(callout.start, callout.end),
+            Block::BlockQuote(block_quote) => Span::new(block_quote.start, block_quote.end),
+            Block::Latex(latex_block) => Span::new(latex_block.start, latex_block.end),
+            Block::Code(code_block) => Span::new(code_block.start, code_block.end),
+            Block::Table(table) => Span::new(table.start, table.end),
+            Block::FootnoteDefinition(footnote) => Span::new(footnote.start, footnote.end),
+            Block::Html(html_block) => Span::new(html_block.start, html_block.end),
+            Block::String(string_block) => Span::new(string_block.start, string_block.end),
+            Block::Unparsed(unparsed) => Span::new(unparsed.start, unparsed.end),
+        }
+    }
+
+    fn span_mut(&mut self) -> (&mut usize, &mut usize) {
+        match self {
+            Block::Callout(callout) => (&mut callout.start, &mut callout.end),
+            Block::BlockQuote(block_quote) => (&mut block_quote.start, &mut block_quote.end),
+            Block::Latex(latex_block) => (&mut latex_block.start, &mut latex_block.end),
+            Block::Code(code_block) => (&mut code_block.start, &mut code_block.end),
+            Block::Table(table) => (&mut table.start, &mut table.end),
+            Block::FootnoteDefinition(footnote) => (&mut footnote.start, &mut footnote.end),
+            Block::Html(html_block) => (&mut html_block.start, &mut html_block.end),
+            Block::String(string_block) => (&mut string_block.start, &mut string_block.end),
+            Block::Unparsed(unparsed) => (&mut unparsed.start, &mut unparsed.end),
+        }
+    }
+
+    // moves the byte offsets in the block, after parsing from a slice or editing the source
+    pub(crate) fn shift(&mut self, shift: Shift) {
+        ShiftOffsets { shift }.visit_block_mut(self, &mut VisitContext::new());
+    }
+
+    // drops the room the vecs grew while parsing, a vault keeps every note parsed
+    pub(crate) fn shrink_to_fit(&mut self) {
+        match self {
+            Block::Callout(callout) => {
+                shrink_nodes(&mut callout.title);
+                shrink_blocks(&mut callout.inner_blocks);
             }
-            Block::Latex(latex_block) => {}
-            Block::Code(code_block) => {}
-            Block::String(string_block) => {
-                for line in &string_block.lines {
-                    for node in &line.get_string_nodes() {
-                        nodes.push(node.clone());
+            Block::BlockQuote(block_quote) => shrink_blocks(&mut block_quote.inner_blocks),
+            Block::Latex(_) | Block::Code(_) | Block::Unparsed(_) => {}
+            Block::Table(table) => {
+                table.alignments.shrink_to_fit();
+                table.rows.shrink_to_fit();
+                for row in std::iter::once(&mut table.header).chain(table.rows.iter_mut()) {
+                    row.shrink_to_fit();
+                    for cell in row {
+                        shrink_nodes(&mut cell.nodes);
                     }
                 }
             }
+            Block::FootnoteDefinition(footnote) => shrink_lines(&mut footnote.lines),
+            Block::Html(html_block) => shrink_nodes(&mut html_block.nodes),
+            Block::String(string_block) => shrink_lines(&mut string_block.lines),
         }
-        nodes
     }
 }
 
 fn parse_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Block> {
     debug_assert!(pair.as_rule() == Rule::block || pair.as_rule() == Rule::yaml);
 
-    for pair_inner in pair.into_inner() {
-        match pair_inner.as_rule() {
-            Rule::block_quote_block => {
-                return Ok(Block::BlockQuote(parse_block_quote_block(
-                    pair_inner, &path,
-                )?));
-            }
-            Rule::latex_block => {
-                return Ok(Block::Latex(parse_latex_block(pair_inner, &path)?));
-            }
-            Rule::code_block => {
-                return Ok(Block::Code(parse_code_block(pair_inner, &path)?));
-            }
-            Rule::string_block => {
-                return Ok(Block::String(parse_string_block(pair_inner, &path)?));
-            }
-            _ => {
-                return Err(Error::ParseError(
-                    path.to_path_buf(),
-                    format!("unexpected rule 3: {:?}", pair_inner.as_rule()),
-                ))
-            }
+    let pair_inner = match pair.into_inner().next() {
+        Some(pair_inner) => pair_inner,
+        None => {
+            return Err(Error::ParseError(
+                path.to_path_buf(),
+                "pairs inner is empty".to_string(),
+            ))
         }
+    };
+    match pair_inner.as_rule() {
+        Rule::callout_block => Ok(Block::Callout(parse_callout_block(pair_inner, path)?)),
+        Rule::block_quote_block => Ok(Block::BlockQuote(parse_block_quote_block(
+            pair_inner, path,
+        )?)),
+        Rule::latex_block => Ok(Block::Latex(parse_latex_block(pair_inner, path)?)),
+        Rule::code_block => Ok(Block::Code(parse_code_block(pair_inner, path)?)),
+        Rule::table_block => Ok(Block::Table(parse_table_block(pair_inner, path)?)),
+        Rule::footnote_definition_block => Ok(Block::FootnoteDefinition(
+            parse_footnote_definition_block(pair_inner, path)?,
+        )),
+        Rule::html_block => Ok(Block::Html(parse_html_block(pair_inner, path)?)),
+        Rule::string_block => Ok(Block::String(parse_string_block(pair_inner, path)?)),
+        _ => Err(Error::ParseError(
+            path.to_path_buf(),
+            format!("unexpected rule 3: {:?}", pair_inner.as_rule()),
+        )),
+    }
+}
+
+fn shrink_blocks(blocks: &mut Vec<Block>) {
+    blocks.shrink_to_fit();
+    for block in blocks {
+        block.shrink_to_fit();
+    }
+}
+
+fn shrink_lines(lines: &mut Vec<Line>) {
+    lines.shrink_to_fit();
+    for line in lines {
+        line.shrink_to_fit();
+    }
+}
+
+fn shrink_nodes(nodes: &mut Vec<Node>) {
+    nodes.shrink_to_fit();
+    for node in nodes {
+        node.shrink_to_fit();
     }
-    return Err(Error::ParseError(
-        path.to_path_buf(),
-        format!("pairs inner is empty"),
-    ));
 }
 
 fn parse_vec_line_into_block(
@@ -305,11 +783,12 @@ fn parse_vec_line_into_block(
     for pair in &pairs {
         debug_assert!(pair.as_rule() == Rule::block || pair.as_rule() == Rule::yaml);
     }
+    let (start, end): (usize, usize) = pairs_span(&pairs);
     let mut lines: Vec<Line> = Vec::new();
     for pair in pairs {
         match pair.as_rule() {
             Rule::line => {
-                lines.push(parse_line(pair, &path)?);
+                lines.push(parse_line(pair, path)?);
             }
             _ => {
                 return Err(Error::ParseError(
@@ -319,23 +798,115 @@ fn parse_vec_line_into_block(
             }
         }
     }
-    Ok(StringBlock { lines })
+    Ok(StringBlock { lines, start, end })
+}
+
+fn pair_span(pair: &pest::iterators::Pair<Rule>) -> Span {
+    Span::new(pair.as_span().start(), pair.as_span().end())
+}
+
+// the pair without the whitespace around it
+fn trimmed_span(pair: &pest::iterators::Pair<Rule>) -> Span {
+    let text: &str = pair.as_str();
+    let start: usize = pair.as_span().start() + text.len() - text.trim_start().len();
+    Span::new(start, start + text.trim().len())
+}
+
+// from the start of the first pair to the end of the last
+fn pairs_span(pairs: &[pest::iterators::Pair<Rule>]) -> (usize, usize) {
+    match (pairs.first(), pairs.last()) {
+        (Some(first), Some(last)) => (first.as_span().start(), last.as_span().end()),
+        _ => (0, 0),
+    }
+}
+
+// > [!kind]- title, foldable is set by a trailing + or -, collapsed only by -
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Callout {
+    pub kind: Span,
+    pub title: Vec<Node>,
+    pub foldable: bool,
+    pub collapsed: bool,
+    pub inner_blocks: Vec<Block>,
+    pub start: usize,
+    pub end: usize,
+}
+
+fn parse_callout_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Callout> {
+    debug_assert!(pair.as_rule() == Rule::callout_block);
+
+    let mut callout: Callout = Callout {
+        kind: Span::new(0, 0),
+        title: Vec::new(),
+        foldable: false,
+        collapsed: false,
+        inner_blocks: Vec::new(),
+        start: pair.as_span().start(),
+        end: pair.as_span().end(),
+    };
+    let mut lines: Vec<pest::iterators::Pair<Rule>> = Vec::new();
+
+    for pair_inner in pair.into_inner() {
+        match pair_inner.as_rule() {
+            Rule::callout_line => {
+                for header_pair in pair_inner.into_inner() {
+                    match header_pair.as_rule() {
+                        Rule::callout_kind => {
+                            callout.kind = pair_span(&header_pair);
+                        }
+                        Rule::callout_fold => {
+                            callout.foldable = true;
+                            callout.collapsed = header_pair.as_str() == "-";
+                        }
+                        Rule::string_line => {
+                            callout.title = parse_string_line(header_pair, path)?.nodes;
+                        }
+                        _ => {
+                            return Err(Error::ParseError(
+                                path.to_path_buf(),
+                                format!("unexpected rule 17: {:?}", header_pair.as_rule()),
+                            ))
+                        }
+                    }
+                }
+            }
+            Rule::block_quote_line => {
+                lines.push(pair_inner);
+            }
+            _ => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 18: {:?}", pair_inner.as_rule()),
+                ))
+            }
+        }
+    }
+
+    if !lines.is_empty() {
+        callout.inner_blocks = parse_block_quote_lines(lines, path)?.inner_blocks;
+    }
+    Ok(callout)
 }
 
 // not including >
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockQuote {
     pub inner_blocks: Vec<Block>,
+    pub start: usize,
+    pub end: usize,
 }
 
 fn parse_block_quote_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<BlockQuote> {
     debug_assert!(pair.as_rule() == Rule::block_quote_block);
 
-    let mut inner_blocks: Vec<Block> = Vec::new();
+    let span: pest::Span = pair.as_span();
     let lines: Vec<pest::iterators::Pair<Rule>> = pair.into_inner().collect();
-    parse_block_quote_lines(lines, &path);
-
-    Ok(BlockQuote { inner_blocks })
+    // the last line break belongs to the block, not to its last line
+    Ok(BlockQuote {
+        start: span.start(),
+        end: span.end(),
+        ..parse_block_quote_lines(lines, path)?
+    })
 }
 
 #[derive(Debug)]
@@ -353,6 +924,7 @@ fn parse_block_quote_lines(
     for pair in &pairs {
         debug_assert!(pair.as_rule() == Rule::block_quote_line);
     }
+    let (start, end): (usize, usize) = pairs_span(&pairs);
     let mut inner_blocks: Vec<Block> = Vec::new();
     let mut state = BlockQuoteLineState::Start;
 
@@ -379,9 +951,8 @@ fn parse_block_quote_lines(
                 },
                 BlockQuoteLineState::Line => match pair_inner.as_rule() {
                     Rule::block_quote_line => {
-                        inner_blocks.push(Block::String(parse_vec_line(current_block, &path)?));
-                        current_block = Vec::new();
-                        current_block.push(pair_inner);
+                        inner_blocks.extend(parse_quoted_lines(current_block, path)?);
+                        current_block = vec![pair_inner];
                         state = BlockQuoteLineState::BlockQuote;
                     }
                     Rule::line => {
@@ -399,10 +970,9 @@ fn parse_block_quote_lines(
                         current_block.push(pair_inner);
                     }
                     Rule::line => {
-                        let block_quote = parse_block_quote_lines(current_block, &path)?;
+                        let block_quote = parse_block_quote_lines(current_block, path)?;
                         inner_blocks.push(Block::BlockQuote(block_quote));
-                        current_block = Vec::new();
-                        current_block.push(pair_inner);
+                        current_block = vec![pair_inner];
                         state = BlockQuoteLineState::Line;
                     }
                     _ => {
@@ -419,67 +989,225 @@ fn parse_block_quote_lines(
         BlockQuoteLineState::Start => {}
         BlockQuoteLineState::Line => {
             if !current_block.is_empty() {
-                inner_blocks.push(Block::String(parse_vec_line(current_block, &path)?));
+                inner_blocks.extend(parse_quoted_lines(current_block, path)?);
             }
         }
         BlockQuoteLineState::BlockQuote => {
             if !current_block.is_empty() {
-                let block_quote = parse_block_quote_lines(current_block, &path)?;
+                let block_quote = parse_block_quote_lines(current_block, path)?;
                 inner_blocks.push(Block::BlockQuote(block_quote));
             }
         }
     }
 
-    Ok(BlockQuote { inner_blocks })
+    Ok(BlockQuote {
+        inner_blocks,
+        start,
+        end,
+    })
 }
 
 fn parse_vec_line(pairs: Vec<pest::iterators::Pair<Rule>>, path: &Path) -> Result<StringBlock> {
     for pair in &pairs {
         debug_assert!(pair.as_rule() == Rule::line);
     }
+    let (start, end): (usize, usize) = pairs_span(&pairs);
     let mut lines: Vec<Line> = Vec::new();
     for pair in pairs {
-        lines.push(parse_line(pair, &path)?);
+        lines.push(parse_line(pair, path)?);
     }
-    Ok(StringBlock { lines })
+    Ok(StringBlock { lines, start, end })
 }
 
-// not including $$
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LatexBlock {
-    pub latex: String,
+// The lines of a quote as string blocks, with the runs that form a table
+// parsed as one. A quoted line is parsed on its own, the table rows are found
+// by parsing each line again as a row.
+fn parse_quoted_lines(pairs: Vec<pest::iterators::Pair<Rule>>, path: &Path) -> Result<Vec<Block>> {
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut lines: Vec<pest::iterators::Pair<Rule>> = Vec::new();
+    let mut index: usize = 0;
+    while index < pairs.len() {
+        let rows: usize = quoted_table_rows(&pairs[index..]);
+        if rows == 0 {
+            lines.push(pairs[index].clone());
+            index += 1;
+            continue;
+        }
+        if !lines.is_empty() {
+            blocks.push(Block::String(parse_vec_line(
+                std::mem::take(&mut lines),
+                path,
+            )?));
+        }
+        blocks.push(Block::Table(parse_quoted_table(
+            &pairs[index..index + rows],
+            path,
+        )?));
+        index += rows;
+    }
+    if !lines.is_empty() {
+        blocks.push(Block::String(parse_vec_line(lines, path)?));
+    }
+    Ok(blocks)
 }
 
-fn parse_latex_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<LatexBlock> {
-    debug_assert!(pair.as_rule() == Rule::latex_block);
+// the whole line parsed again as rule, offsets are from the start of the line
+fn parse_whole_line<'i>(
+    pair: &pest::iterators::Pair<'i, Rule>,
+    rule: Rule,
+) -> Option<pest::iterators::Pair<'i, Rule>> {
+    let text: &'i str = pair.as_str();
+    MDParser::parse(rule, text)
+        .ok()?
+        .next()
+        .filter(|row| row.as_span().end() == text.len())
+}
 
-    let latex = pair.as_str();
+// how many lines from the first form a table, 0 when they do not start one
+fn quoted_table_rows(pairs: &[pest::iterators::Pair<Rule>]) -> usize {
+    if pairs.len() < 2
+        || parse_whole_line(&pairs[0], Rule::table_row).is_none()
+        || parse_whole_line(&pairs[1], Rule::table_delimiter_row).is_none()
+    {
+        return 0;
+    }
+    2 + pairs[2..]
+        .iter()
+        .take_while(|pair| parse_whole_line(pair, Rule::table_row).is_some())
+        .count()
+}
 
-    Ok(LatexBlock {
-        latex: latex.to_string(),
-    })
+fn parse_quoted_table(pairs: &[pest::iterators::Pair<Rule>], path: &Path) -> Result<Table> {
+    let (start, end): (usize, usize) = pairs_span(pairs);
+    let mut table: Table = Table {
+        header: Vec::new(),
+        alignments: Vec::new(),
+        delimiter_row: Span::new(0, 0),
+        pipes: Vec::new(),
+        rows: Vec::new(),
+        start,
+        end,
+    };
+    for (index, pair) in pairs.iter().enumerate() {
+        let rule: Rule = if index == 1 {
+            Rule::table_delimiter_row
+        } else {
+            Rule::table_row
+        };
+        let row = match parse_whole_line(pair, rule) {
+            Some(row) => row,
+            None => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 29: {:?}", pair.as_rule()),
+                ))
+            }
+        };
+        let shift: Shift = Shift::by(pair.as_span().start());
+        if index == 1 {
+            table.delimiter_row = trimmed_span(&row);
+            table.delimiter_row.shift(shift);
+            table.alignments = parse_table_alignments(row);
+            continue;
+        }
+        table.pipes.push(table_row_pipes(&row));
+        let mut cells: Vec<TableCell> = parse_table_row(row, path)?;
+        for cell in &mut cells {
+            cell.shift(shift);
+        }
+        if index == 0 {
+            table.header = cells;
+        } else {
+            table.rows.push(cells);
+        }
+    }
+    Ok(table)
 }
 
-// not including ```
+// text that could not be parsed, kept whole so it is never linked
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodeBlock {
-    pub code_type: Option<String>,
-    pub code: String,
+pub struct UnparsedBlock {
+    pub diagnostic: ParseDiagnostic,
+    pub start: usize,
+    pub end: usize,
 }
 
-fn parse_code_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<CodeBlock> {
+impl UnparsedBlock {
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        Span::new(self.start, self.end).text(source)
+    }
+}
+
+// the whole block, $$ and any indentation before them included
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatexBlock {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LatexBlock {
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        Span::new(self.start, self.end).text(source)
+    }
+}
+
+fn parse_latex_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<LatexBlock> {
+    debug_assert!(pair.as_rule() == Rule::latex_block);
+
+    Ok(LatexBlock {
+        start: pair.as_span().start(),
+        end: pair.as_span().end(),
+    })
+}
+
+// not including the fences, start is where the opening fence starts after any indentation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeBlock {
+    pub code_type: Option<Span>,
+    pub code: Span,
+    // ``` or ~~~, or longer to wrap shorter fences
+    pub fence: Span,
+    // the closing line, empty when the block runs to the end of the file
+    pub closing_fence: Span,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl CodeBlock {
+    pub fn is_dataview_query(&self, source: &str) -> bool {
+        self.code_type.is_some_and(|code_type| {
+            matches!(
+                code_type.text(source).to_lowercase().as_str(),
+                "dataview" | "dataviewjs"
+            )
+        })
+    }
+}
+
+fn parse_code_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<CodeBlock> {
     debug_assert!(pair.as_rule() == Rule::code_block);
 
-    let mut code_type: Option<String> = None;
-    let mut code: String = String::new();
+    let mut start: usize = pair.as_span().start();
+    let end: usize = pair.as_span().end();
+    let mut code_type: Option<Span> = None;
+    let mut code: Span = Span::new(end, end);
+    let mut fence: Span = Span::new(start, start);
+    let mut closing_fence: Span = Span::new(end, end);
 
     for pair_inner in pair.into_inner() {
         match pair_inner.as_rule() {
+            Rule::code_fence => {
+                fence = pair_span(&pair_inner);
+                start = fence.start;
+            }
+            Rule::code_fence_close => {
+                closing_fence = pair_span(&pair_inner);
+            }
             Rule::code_type => {
-                code_type = Some(pair_inner.as_str().to_string());
+                code_type = Some(pair_span(&pair_inner));
             }
             Rule::code_block_inner => {
-                code = pair_inner.as_str().to_string();
+                code = pair_span(&pair_inner);
             }
             _ => {
                 return Err(Error::ParseError(
@@ -490,23 +1218,254 @@ fn parse_code_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Co
         }
     }
 
-    Ok(CodeBlock { code_type, code })
+    Ok(CodeBlock {
+        code_type,
+        code,
+        fence,
+        closing_fence,
+        start,
+        end,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Alignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+// start and end cover the cell between its pipes, padding included
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableCell {
+    pub nodes: Vec<Node>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl TableCell {
+    pub(crate) fn shift(&mut self, shift: Shift) {
+        ShiftOffsets { shift }.visit_table_cell_mut(self, &mut VisitContext::new());
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Table {
+    pub header: Vec<TableCell>,
+    pub alignments: Vec<Alignment>,
+    // the line under the header as written, without surrounding whitespace
+    pub delimiter_row: Span,
+    // whether the header and each row start and end with a pipe
+    pub pipes: Vec<(bool, bool)>,
+    pub rows: Vec<Vec<TableCell>>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Table {
+    pub fn get_cells(&self) -> Vec<&TableCell> {
+        self.header
+            .iter()
+            .chain(self.rows.iter().flatten())
+            .collect()
+    }
+}
+
+fn parse_table_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Table> {
+    debug_assert!(pair.as_rule() == Rule::table_block);
+
+    let mut table: Table = Table {
+        header: Vec::new(),
+        alignments: Vec::new(),
+        delimiter_row: Span::new(0, 0),
+        pipes: Vec::new(),
+        rows: Vec::new(),
+        start: pair.as_span().start(),
+        end: pair.as_span().end(),
+    };
+    let mut seen_header: bool = false;
+
+    for pair_inner in pair.into_inner() {
+        match pair_inner.as_rule() {
+            Rule::table_row => {
+                table.pipes.push(table_row_pipes(&pair_inner));
+                let row: Vec<TableCell> = parse_table_row(pair_inner, path)?;
+                if seen_header {
+                    table.rows.push(row);
+                } else {
+                    table.header = row;
+                    seen_header = true;
+                }
+            }
+            Rule::table_delimiter_row => {
+                table.delimiter_row = trimmed_span(&pair_inner);
+                table.alignments = parse_table_alignments(pair_inner);
+            }
+            _ => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 19: {:?}", pair_inner.as_rule()),
+                ))
+            }
+        }
+    }
+
+    Ok(table)
+}
+
+fn parse_table_alignments(pair: pest::iterators::Pair<Rule>) -> Vec<Alignment> {
+    debug_assert!(pair.as_rule() == Rule::table_delimiter_row);
+
+    pair.into_inner()
+        .map(|alignment| {
+            let alignment: &str = alignment.as_str().trim();
+            match (alignment.starts_with(':'), alignment.ends_with(':')) {
+                (true, true) => Alignment::Center,
+                (true, false) => Alignment::Left,
+                (false, true) => Alignment::Right,
+                (false, false) => Alignment::None,
+            }
+        })
+        .collect()
+}
+
+// whether a pipe comes before the first cell and after the last
+fn table_row_pipes(pair: &pest::iterators::Pair<Rule>) -> (bool, bool) {
+    let text: &str = pair.as_str();
+    let start: usize = pair.as_span().start();
+    let cells: Vec<pest::Span> = pair
+        .clone()
+        .into_inner()
+        .map(|cell| cell.as_span())
+        .collect();
+    match (cells.first(), cells.last()) {
+        (Some(first), Some(last)) => (
+            text[..first.start() - start].contains('|'),
+            text[last.end() - start..].contains('|'),
+        ),
+        _ => (false, false),
+    }
+}
+
+fn parse_table_row(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Vec<TableCell>> {
+    debug_assert!(pair.as_rule() == Rule::table_row);
+
+    let mut cells: Vec<TableCell> = Vec::new();
+    for pair_inner in pair.into_inner() {
+        let start: usize = pair_inner.as_span().start();
+        let end: usize = pair_inner.as_span().end();
+        let nodes: Vec<Node> = parse_string_line(pair_inner, path)?.nodes;
+        cells.push(TableCell { nodes, start, end });
+    }
+    Ok(cells)
+}
+
+// starts with a block level tag such as <div> and runs until the next blank line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HtmlBlock {
+    pub nodes: Vec<Node>,
+    pub start: usize,
+    pub end: usize,
+}
+
+fn parse_html_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<HtmlBlock> {
+    debug_assert!(pair.as_rule() == Rule::html_block);
+
+    let block_start: usize = pair.as_span().start();
+    let block_end: usize = pair.as_span().end();
+    let mut nodes: Vec<Node> = Vec::new();
+
+    for pair_inner in pair.into_inner() {
+        let start: usize = pair_inner.as_span().start();
+        let end: usize = pair_inner.as_span().end();
+        let node_enum: NodeEnum = match pair_inner.as_rule() {
+            Rule::comment_node => NodeEnum::Comment,
+            Rule::html_tag_node => NodeEnum::Html(Box::new(parse_html_tag_node(pair_inner, path)?)),
+            // a < or % that starts no tag or comment is text as well
+            Rule::html_block_text | Rule::html_block_char => NodeEnum::Text,
+            _ => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 26: {:?}", pair_inner),
+                ))
+            }
+        };
+        nodes.push(Node {
+            node: node_enum,
+            start,
+            end,
+        });
+    }
+
+    Ok(HtmlBlock {
+        nodes,
+        start: block_start,
+        end: block_end,
+    })
+}
+
+// [^label]: text, continued by lines indented with four spaces or a tab
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FootnoteDefinition {
+    pub label: Span,
+    pub lines: Vec<Line>,
+    pub start: usize,
+    pub end: usize,
+}
+
+fn parse_footnote_definition_block(
+    pair: pest::iterators::Pair<Rule>,
+    path: &Path,
+) -> Result<FootnoteDefinition> {
+    debug_assert!(pair.as_rule() == Rule::footnote_definition_block);
+
+    let mut footnote: FootnoteDefinition = FootnoteDefinition {
+        label: Span::new(0, 0),
+        lines: Vec::new(),
+        start: pair.as_span().start(),
+        end: pair.as_span().end(),
+    };
+
+    for pair_inner in pair.into_inner() {
+        match pair_inner.as_rule() {
+            Rule::footnote_label => {
+                footnote.label = pair_span(&pair_inner);
+            }
+            Rule::line => {
+                footnote.lines.push(parse_line(pair_inner, path)?);
+            }
+            _ => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 24: {:?}", pair_inner),
+                ))
+            }
+        }
+    }
+
+    Ok(footnote)
 }
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 
 pub struct StringBlock {
     pub lines: Vec<Line>,
+    pub start: usize,
+    pub end: usize,
 }
 
 fn parse_string_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<StringBlock> {
     debug_assert!(pair.as_rule() == Rule::string_block);
 
+    let start: usize = pair.as_span().start();
+    let end: usize = pair.as_span().end();
     let mut lines: Vec<Line> = Vec::new();
 
     for pair_inner in pair.into_inner() {
         match pair_inner.as_rule() {
             Rule::line => {
-                lines.push(parse_line(pair_inner, &path)?);
+                lines.push(parse_line(pair_inner, path)?);
             }
             _ => {
                 return Err(Error::ParseError(
@@ -517,11 +1476,12 @@ fn parse_string_block(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<
         }
     }
 
-    Ok(StringBlock { lines })
+    Ok(StringBlock { lines, start, end })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Line {
+    Task(Task),
     NumberedList(NumberedList),
     BulletedList(BulletedList),
     Heading(Heading),
@@ -529,70 +1489,138 @@ pub enum Line {
 }
 
 impl Line {
-    pub fn get_string_nodes(&self) -> Vec<Node> {
+    pub fn get_string_nodes(&self, settings: &Settings, source: &str) -> Vec<Node> {
+        let mut visitor: StringNodes = StringNodes {
+            settings,
+            source,
+            nodes: Vec::new(),
+        };
+        visitor.visit_line(self, &mut VisitContext::new());
+        visitor.nodes
+    }
+
+    pub fn get_nodes(&self) -> Vec<&Node> {
+        let mut visitor: AllNodes = AllNodes { nodes: Vec::new() };
+        visitor.visit_line(self, &mut VisitContext::new());
+        visitor.nodes
+    }
+
+    // leading whitespace of list and task lines, None for other lines
+    pub fn get_indent(&self) -> Option<Indent> {
         match self {
-            Line::NumberedList(numbered_list) => {
-                let mut nodes: Vec<Node> = Vec::new();
-                for node in &numbered_list.nodes {
-                    let inner_nodes = node.get_string_node();
-                    for node in inner_nodes {
-                        nodes.push(node);
-                    }
-                }
-                nodes
-            }
-            Line::BulletedList(bulleted_list) => {
-                let mut nodes: Vec<Node> = Vec::new();
-                for node in &bulleted_list.nodes {
-                    let inner_nodes = node.get_string_node();
-                    for node in inner_nodes {
-                        nodes.push(node);
-                    }
-                }
-                nodes
-            }
-            Line::Heading(heading) => {
-                let mut nodes: Vec<Node> = Vec::new();
-                for node in &heading.nodes {
-                    let inner_nodes = node.get_string_node();
-                    for node in inner_nodes {
-                        nodes.push(node);
-                    }
-                }
-                nodes
-            }
-            Line::StringLine(string_line) => {
-                let mut nodes: Vec<Node> = Vec::new();
-                for node in &string_line.nodes {
-                    let inner_nodes = node.get_string_node();
-                    for node in inner_nodes {
-                        nodes.push(node);
-                    }
-                }
-                nodes
-            }
+            Line::Task(task) => Some(task.indent),
+            Line::NumberedList(numbered_list) => Some(numbered_list.indent),
+            Line::BulletedList(bulleted_list) => Some(bulleted_list.indent),
+            Line::Heading(_) | Line::StringLine(_) => None,
+        }
+    }
+
+    pub fn get_block_id(&self) -> Option<&LinkPart> {
+        match self {
+            Line::Task(task) => task.block_id.as_ref(),
+            Line::NumberedList(numbered_list) => numbered_list.block_id.as_ref(),
+            Line::BulletedList(bulleted_list) => bulleted_list.block_id.as_ref(),
+            Line::Heading(heading) => heading.block_id.as_ref(),
+            Line::StringLine(string_line) => string_line.block_id.as_ref(),
+        }
+    }
+
+    // the whole line, markers and indent included, without its line break
+    pub fn span(&self) -> Span {
+        match self {
+            Line::Task(task) => Span::new(task.start, task.end),
+            Line::NumberedList(numbered_list) => Span::new(numbered_list.start, numbered_list.end),
+            Line::BulletedList(bulleted_list) => Span::new(bulleted_list.start, bulleted_list.end),
+            Line::Heading(heading) => Span::new(heading.start, heading.end),
+            Line::StringLine(string_line) => Span::new(string_line.start, string_line.end),
+        }
+    }
+
+    pub(crate) fn shift(&mut self, shift: Shift) {
+        ShiftOffsets { shift }.visit_line_mut(self, &mut VisitContext::new());
+    }
+
+    // the block id and span, what a line holds besides its nodes
+    fn offsets_mut(&mut self) -> (&mut Option<LinkPart>, &mut usize, &mut usize) {
+        match self {
+            Line::Task(task) => (&mut task.block_id, &mut task.start, &mut task.end),
+            Line::NumberedList(numbered_list) => (
+                &mut numbered_list.block_id,
+                &mut numbered_list.start,
+                &mut numbered_list.end,
+            ),
+            Line::BulletedList(bulleted_list) => (
+                &mut bulleted_list.block_id,
+                &mut bulleted_list.start,
+                &mut bulleted_list.end,
+            ),
+            Line::Heading(heading) => (&mut heading.block_id, &mut heading.start, &mut heading.end),
+            Line::StringLine(string_line) => (
+                &mut string_line.block_id,
+                &mut string_line.start,
+                &mut string_line.end,
+            ),
+        }
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        let nodes: &mut Vec<Node> = match self {
+            Line::Task(task) => &mut task.nodes,
+            Line::NumberedList(numbered_list) => &mut numbered_list.nodes,
+            Line::BulletedList(bulleted_list) => &mut bulleted_list.nodes,
+            Line::Heading(heading) => &mut heading.nodes,
+            Line::StringLine(string_line) => &mut string_line.nodes,
+        };
+        shrink_nodes(nodes);
+    }
+}
+
+// the leading whitespace of a list or task line, the width counts a tab as four columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Indent {
+    pub span: Span,
+    pub width: usize,
+}
+
+impl Indent {
+    fn new(pair: &pest::iterators::Pair<Rule>) -> Self {
+        Indent {
+            span: pair_span(pair),
+            width: indent_width(pair.as_str()),
         }
     }
+
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        self.span.text(source)
+    }
 }
 
 fn parse_line(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Line> {
     debug_assert!(pair.as_rule() == Rule::line);
 
-    let mut result: Line = Line::StringLine(StringLine { nodes: Vec::new() });
+    let mut result: Line = Line::StringLine(StringLine {
+        nodes: Vec::new(),
+        block_id: None,
+        start: pair.as_span().start(),
+        end: pair.as_span().end(),
+    });
 
     for pair_inner in pair.into_inner() {
         match pair_inner.as_rule() {
             Rule::heading_line => {
-                result = Line::Heading(parse_heading_line(pair_inner, &path)?);
+                result = Line::Heading(parse_heading_line(pair_inner, path)?);
+            }
+            Rule::task_line => {
+                result = Line::Task(parse_task_line(pair_inner, path)?);
             }
             Rule::numbered_list_line => {
-                result = Line::NumberedList(parse_numbered_list_line(pair_inner, &path)?);
+                result = Line::NumberedList(parse_numbered_list_line(pair_inner, path)?);
             }
             Rule::list_line => {
-                result = Line::BulletedList(parse_list_line(pair_inner, &path)?);
+                result = Line::BulletedList(parse_list_line(pair_inner, path)?);
             }
             Rule::string_line => {
-                result = Line::StringLine(parse_string_line(pair_inner, &path)?);
+                result = Line::StringLine(parse_string_line(pair_inner, path)?);
             }
             _ => {
                 return Err(Error::ParseError(
@@ -606,12 +1634,78 @@ fn parse_line(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Line> {
     Ok(result)
 }
 
+// - [x] text, checked only for x or X, other statuses such as [-] or [>] are kept in status_char
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    pub indent: Indent,
+    // -, + or a number and a dot
+    pub marker: Span,
+    pub checked: bool,
+    pub status_char: char,
+    pub nodes: Vec<Node>,
+    pub block_id: Option<LinkPart>,
+    pub start: usize,
+    pub end: usize,
+}
+
+fn parse_task_line(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Task> {
+    debug_assert!(pair.as_rule() == Rule::task_line);
+
+    let start: usize = pair.as_span().start();
+    let mut task: Task = Task {
+        indent: Indent {
+            span: Span::new(start, start),
+            width: 0,
+        },
+        marker: Span::new(start, start),
+        checked: false,
+        status_char: ' ',
+        nodes: Vec::new(),
+        block_id: None,
+        start,
+        end: pair.as_span().end(),
+    };
+
+    for pair_inner in pair.into_inner() {
+        match pair_inner.as_rule() {
+            Rule::indent => {
+                task.indent = Indent::new(&pair_inner);
+            }
+            Rule::task_marker => {
+                task.marker = pair_span(&pair_inner);
+            }
+            Rule::task_status => {
+                task.status_char = pair_inner.as_str().chars().next().unwrap_or(' ');
+                task.checked = matches!(task.status_char, 'x' | 'X');
+            }
+            Rule::string_line => {
+                let string_line: StringLine = parse_string_line(pair_inner, path)?;
+                task.nodes = string_line.nodes;
+                task.block_id = string_line.block_id;
+            }
+            _ => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 20: {:?}", pair_inner),
+                ))
+            }
+        }
+    }
+
+    Ok(task)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 
 pub struct NumberedList {
-    pub indent: String,
+    pub indent: Indent,
     pub number: u32,
+    // how many digits the number is written with, 007 has three
+    pub digits: usize,
     pub nodes: Vec<Node>,
+    pub block_id: Option<LinkPart>,
+    pub start: usize,
+    pub end: usize,
 }
 
 fn parse_numbered_list_line(
@@ -620,14 +1714,35 @@ fn parse_numbered_list_line(
 ) -> Result<NumberedList> {
     debug_assert!(pair.as_rule() == Rule::numbered_list_line);
 
-    let mut indent: String = String::new();
+    let start: usize = pair.as_span().start();
+    let end: usize = pair.as_span().end();
+    let mut indent: Indent = Indent {
+        span: Span::new(start, start),
+        width: 0,
+    };
     let mut number: u32 = 0;
+    let mut digits: usize = 0;
     let mut nodes: Vec<Node> = Vec::new();
+    let mut block_id: Option<LinkPart> = None;
 
     for pair_inner in pair.into_inner() {
         match pair_inner.as_rule() {
+            Rule::indent => {
+                indent = Indent::new(&pair_inner);
+            }
+            Rule::list_number => {
+                digits = pair_inner.as_str().len();
+                number = pair_inner.as_str().parse().map_err(|_| {
+                    Error::ParseError(
+                        path.to_path_buf(),
+                        f!("list number out of range: {}", pair_inner.as_str()),
+                    )
+                })?;
+            }
             Rule::string_line => {
-                nodes = parse_string_line(pair_inner, &path)?.nodes;
+                let string_line: StringLine = parse_string_line(pair_inner, path)?;
+                nodes = string_line.nodes;
+                block_id = string_line.block_id;
             }
             _ => {
                 return Err(Error::ParseError(
@@ -641,27 +1756,45 @@ fn parse_numbered_list_line(
     Ok(NumberedList {
         indent,
         number,
+        digits,
         nodes,
+        block_id,
+        start,
+        end,
     })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 
 pub struct BulletedList {
-    pub indent: String,
+    pub indent: Indent,
     pub nodes: Vec<Node>,
+    pub block_id: Option<LinkPart>,
+    pub start: usize,
+    pub end: usize,
 }
 
 fn parse_list_line(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<BulletedList> {
     debug_assert!(pair.as_rule() == Rule::list_line);
 
-    let mut indent: String = String::new();
+    let start: usize = pair.as_span().start();
+    let end: usize = pair.as_span().end();
+    let mut indent: Indent = Indent {
+        span: Span::new(start, start),
+        width: 0,
+    };
     let mut nodes: Vec<Node> = Vec::new();
+    let mut block_id: Option<LinkPart> = None;
 
     for pair_inner in pair.into_inner() {
         match pair_inner.as_rule() {
+            Rule::indent => {
+                indent = Indent::new(&pair_inner);
+            }
             Rule::string_line => {
-                nodes = parse_string_line(pair_inner, &path)?.nodes;
+                let string_line: StringLine = parse_string_line(pair_inner, path)?;
+                nodes = string_line.nodes;
+                block_id = string_line.block_id;
             }
             _ => {
                 return Err(Error::ParseError(
@@ -672,7 +1805,13 @@ fn parse_list_line(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Bul
         }
     }
 
-    Ok(BulletedList { indent, nodes })
+    Ok(BulletedList {
+        indent,
+        nodes,
+        block_id,
+        start,
+        end,
+    })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -680,18 +1819,29 @@ fn parse_list_line(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Bul
 pub struct Heading {
     pub level: u32,
     pub nodes: Vec<Node>,
+    pub block_id: Option<LinkPart>,
+    pub start: usize,
+    pub end: usize,
 }
 
 fn parse_heading_line(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Heading> {
     debug_assert!(pair.as_rule() == Rule::heading_line);
 
+    let start: usize = pair.as_span().start();
+    let end: usize = pair.as_span().end();
     let mut level: u32 = 0;
     let mut nodes: Vec<Node> = Vec::new();
+    let mut block_id: Option<LinkPart> = None;
 
     for pair_inner in pair.into_inner() {
         match pair_inner.as_rule() {
+            Rule::heading_level => {
+                level = pair_inner.as_str().len() as u32;
+            }
             Rule::string_line => {
-                nodes = parse_string_line(pair_inner, &path)?.nodes;
+                let string_line: StringLine = parse_string_line(pair_inner, path)?;
+                nodes = string_line.nodes;
+                block_id = string_line.block_id;
             }
             _ => {
                 return Err(Error::ParseError(
@@ -702,33 +1852,54 @@ fn parse_heading_line(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<
         }
     }
 
-    Ok(Heading { level, nodes })
+    Ok(Heading {
+        level,
+        nodes,
+        block_id,
+        start,
+        end,
+    })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 
 pub struct StringLine {
     pub nodes: Vec<Node>,
+    // the trailing ^id, without the caret
+    pub block_id: Option<LinkPart>,
+    pub start: usize,
+    pub end: usize,
 }
 
 fn parse_string_line(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<StringLine> {
     debug_assert!(
         pair.as_rule() == Rule::string_line
+            || pair.as_rule() == Rule::table_cell
             || pair.as_rule() == Rule::bold_italic_node
             || pair.as_rule() == Rule::bold_node
             || pair.as_rule() == Rule::italic_node
+            || pair.as_rule() == Rule::html_element_node
+            || pair.as_rule() == Rule::highlight_node
+            || pair.as_rule() == Rule::strikethrough_node
+            || pair.as_rule() == Rule::underscore_bold_node
+            || pair.as_rule() == Rule::underscore_italic_node
+            || pair.as_rule() == Rule::inline_field_value
+            || pair.as_rule() == Rule::inline_field_paren_value
     );
 
+    let line_start: usize = pair.as_span().start();
+    let line_end: usize = pair.as_span().end();
     let mut nodes: Vec<Node> = Vec::new();
+    let mut block_id: Option<LinkPart> = None;
+    // key:: opening the line, the rest of the line is its value
+    let mut line_field: Option<(LinkPart, usize)> = None;
     for pair_inner in pair.into_inner() {
         let start_pos = pair_inner.as_span().start();
         let end_pos = pair_inner.as_span().end();
-        let (line, col) = pair_inner.as_span().start_pos().line_col();
 
         match pair_inner.as_rule() {
             Rule::bold_italic_node => {
-                let mut inner_nodes: Vec<Node> = Vec::new();
-                inner_nodes.append(&mut parse_string_line(pair_inner, &path)?.nodes.clone());
+                let inner_nodes: Vec<Node> = parse_string_line(pair_inner, path)?.nodes;
                 let node_enum = NodeEnum::BoldItalic(inner_nodes);
                 nodes.push(Node {
                     node: node_enum,
@@ -736,141 +1907,262 @@ fn parse_string_line(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<S
                     end: end_pos,
                 });
             }
-            Rule::bold_node => {
-                let mut inner_nodes: Vec<Node> = Vec::new();
-                inner_nodes.append(&mut parse_string_line(pair_inner, &path)?.nodes.clone());
-                let node_enum = NodeEnum::Bold(inner_nodes);
+            Rule::bold_node | Rule::underscore_bold_node => {
+                let delimiter: char = if pair_inner.as_rule() == Rule::bold_node {
+                    '*'
+                } else {
+                    '_'
+                };
+                let inner_nodes: Vec<Node> = parse_string_line(pair_inner, path)?.nodes;
+                let node_enum = NodeEnum::Bold(inner_nodes, delimiter);
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            Rule::italic_node => {
-                let mut inner_nodes: Vec<Node> = Vec::new();
-                inner_nodes.append(&mut parse_string_line(pair_inner, &path)?.nodes.clone());
-                let node_enum = NodeEnum::Italic(inner_nodes);
+            Rule::italic_node | Rule::underscore_italic_node => {
+                let delimiter: char = if pair_inner.as_rule() == Rule::italic_node {
+                    '*'
+                } else {
+                    '_'
+                };
+                let inner_nodes: Vec<Node> = parse_string_line(pair_inner, path)?.nodes;
+                let node_enum = NodeEnum::Italic(inner_nodes, delimiter);
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            Rule::named_link_node => {
-                // nodes.push(Node::NamedMDLink(
-                //     parse_named_link_node(pair_inner, &path)?,
-                //     position,
-                // ));
-                let node_enum = NodeEnum::NamedMDLink(parse_named_link_node(pair_inner, &path)?);
+            Rule::highlight_node => {
+                let inner_nodes: Vec<Node> = parse_string_line(pair_inner, path)?.nodes;
+                let node_enum = NodeEnum::Highlight(inner_nodes);
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            Rule::link_node => {
-                // nodes.push(Node::MDLink(pair_inner.as_str().to_string(), position));
-                let node_enum = NodeEnum::MDLink(pair_inner.as_str().to_string());
+            Rule::strikethrough_node => {
+                let inner_nodes: Vec<Node> = parse_string_line(pair_inner, path)?.nodes;
+                let node_enum = NodeEnum::Strikethrough(inner_nodes);
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            Rule::weblink_node => {
-                // nodes.push(Node::WebLink(
-                //     parse_weblink_node(pair_inner, &path)?,
-                //     position,
-                // ));
-                let node_enum = NodeEnum::WebLink(parse_weblink_node(pair_inner, &path)?);
+            Rule::embed_node => {
+                let node_enum = NodeEnum::Embed(Box::new(parse_embed_node(pair_inner, path)?));
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            Rule::square_bracket_node => {
-                // nodes.push(Node::SquareBracket(
-                //     pair_inner.as_str().to_string(),
-                //     position,
-                // ));
-                let node_enum = NodeEnum::SquareBracket(pair_inner.as_str().to_string());
+            Rule::image_node => {
+                let node_enum = NodeEnum::Image(Box::new(parse_image_node(pair_inner, path)?));
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            Rule::latex_block_inline_node => {
-                // nodes.push(Node::InlineLatexBlock(
-                //     pair_inner.as_str().to_string(),
-                //     position,
-                // ));
-                let node_enum = NodeEnum::InlineLatexBlock(pair_inner.as_str().to_string());
+            Rule::bare_url_node | Rule::email_node | Rule::autolink_node => {
+                let node_enum = NodeEnum::Url(parse_url_node(pair_inner, path)?);
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            Rule::code_block_inline_node => {
-                // nodes.push(Node::InlineCodeBlock(
-                //     pair_inner.as_str().to_string(),
-                //     position,
-                // ));
-                let node_enum = NodeEnum::InlineCodeBlock(pair_inner.as_str().to_string());
+            Rule::comment_node => {
+                let node_enum = NodeEnum::Comment;
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            Rule::latex_inline_node => {
-                // nodes.push(Node::InlineLatex(pair_inner.as_str().to_string(), position));
-                let node_enum = NodeEnum::InlineLatex(pair_inner.as_str().to_string());
+            Rule::html_element_node => {
+                let node_enum =
+                    NodeEnum::Html(Box::new(parse_html_element_node(pair_inner, path)?));
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            Rule::code_inline_node => {
-                // nodes.push(Node::InlineCode(pair_inner.as_str().to_string(), position));
-                let node_enum = NodeEnum::InlineCode(pair_inner.as_str().to_string());
+            Rule::html_tag_node => {
+                let node_enum = NodeEnum::Html(Box::new(parse_html_tag_node(pair_inner, path)?));
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            Rule::node => {
-                // nodes.push(Node::Text(pair_inner.as_str().to_string(), position));
-                let node_enum = NodeEnum::Text(pair_inner.as_str().to_string());
+            // the opening tag of an html element, kept on its Html node
+            Rule::html_tag_name | Rule::html_attributes => {}
+            Rule::footnote_ref_node => {
+                let node_enum = NodeEnum::FootnoteRef;
                 nodes.push(Node {
                     node: node_enum,
                     start: start_pos,
                     end: end_pos,
                 });
             }
-            _ => {
-                return Err(Error::ParseError(
-                    path.to_path_buf(),
-                    format!("unexpected rule 14: {:?}", pair_inner.as_rule()),
-                ))
+            Rule::inline_field_line => {
+                for key in pair_inner.into_inner() {
+                    line_field = Some((parse_inline_field_key(key), start_pos));
+                }
             }
-        }
-    }
-
-    Ok(StringLine { nodes })
-}
-
-// #[derive(Debug, Clone, Serialize, Deserialize)]
-// pub struct NodePosition {
-//     // pub line: u32,
-//     // pub column: u32,
-//     // debug only
-//     // pub span_str: String,
-// }
+            Rule::inline_field_node => {
+                let node_enum = parse_inline_field_node(pair_inner, path)?;
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            Rule::block_id => {
+                for id in pair_inner.into_inner() {
+                    block_id = Some(LinkPart::new(id.as_span().start(), id.as_span().end()));
+                }
+            }
+            Rule::tag_node => {
+                let node_enum = NodeEnum::Tag;
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            Rule::named_link_node => {
+                // nodes.push(Node::NamedMDLink(
+                //     parse_named_link_node(pair_inner, path)?,
+                //     position,
+                // ));
+                let node_enum =
+                    NodeEnum::NamedMDLink(Box::new(parse_wikilink_node(pair_inner, path)?));
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            Rule::link_node => {
+                // nodes.push(Node::MDLink(pair_inner.as_str().to_string(), position));
+                let node_enum = NodeEnum::MDLink(Box::new(parse_wikilink_node(pair_inner, path)?));
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            Rule::weblink_node => {
+                // nodes.push(Node::WebLink(
+                //     parse_weblink_node(pair_inner, path)?,
+                //     position,
+                // ));
+                let node_enum = NodeEnum::WebLink(Box::new(parse_weblink_node(pair_inner, path)?));
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            Rule::square_bracket_node => {
+                let node_enum = NodeEnum::SquareBracket;
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            Rule::latex_block_inline_node | Rule::latex_display_node => {
+                let node_enum = NodeEnum::InlineLatexBlock;
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            Rule::code_block_inline_node => {
+                let node_enum = NodeEnum::InlineCodeBlock;
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            Rule::latex_inline_node => {
+                let node_enum = NodeEnum::InlineLatex;
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            Rule::code_inline_node => {
+                let node_enum = NodeEnum::InlineCode;
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            Rule::node
+            | Rule::table_cell_node
+            | Rule::inline_field_text
+            | Rule::html_text
+            | Rule::highlight_text
+            | Rule::strikethrough_text
+            | Rule::underscore_text => {
+                let node_enum = NodeEnum::Text;
+                nodes.push(Node {
+                    node: node_enum,
+                    start: start_pos,
+                    end: end_pos,
+                });
+            }
+            _ => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 14: {:?}", pair_inner.as_rule()),
+                ))
+            }
+        }
+    }
+
+    if let Some((key, start)) = line_field {
+        let end: usize = nodes.last().map_or(key.end + "::".len(), |node| node.end);
+        nodes = vec![Node {
+            node: NodeEnum::InlineField(Box::new(InlineField {
+                key,
+                value: nodes,
+                bracket: None,
+            })),
+            start,
+            end,
+        }];
+    }
+
+    Ok(StringLine {
+        nodes,
+        block_id,
+        start: line_start,
+        end: line_end,
+    })
+}
+
+// #[derive(Debug, Clone, Serialize, Deserialize)]
+// pub struct NodePosition {
+//     // pub line: u32,
+//     // pub column: u32,
+//     // debug only
+//     // pub span_str: String,
+// }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 
@@ -882,96 +2174,140 @@ pub struct Node {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NodeEnum {
-    Text(String),
+    // nodes hold no text of their own, leaves and the parts of links, embeds,
+    // urls and html are spans read from the source, see Node::text
+    Text,
     BoldItalic(Vec<Node>),
-    Bold(Vec<Node>),
-    Italic(Vec<Node>),
-    MDLink(String),
-    NamedMDLink(NamedMDLink),
-    WebLink(WebLink),
-    SquareBracket(String),
-    InlineCode(String),
-    InlineLatex(String),
-    InlineCodeBlock(String),
-    InlineLatexBlock(String),
+    // **text** or __text__, with the character it is written with
+    Bold(Vec<Node>, char),
+    // *text* or _text_
+    Italic(Vec<Node>, char),
+    // ==text==
+    Highlight(Vec<Node>),
+    // ~~text~~
+    Strikethrough(Vec<Node>),
+    // the large variants are boxed, every node is as big as the largest one
+    MDLink(Box<WikiLink>),
+    NamedMDLink(Box<WikiLink>),
+    WebLink(Box<WebLink>),
+    SquareBracket,
+    InlineCode,
+    InlineLatex,
+    InlineCodeBlock,
+    InlineLatexBlock,
+    Embed(Box<Embed>),
+    Image(Box<Image>),
+    // #tag/nested, see Node::tag
+    Tag,
+    // [^label]
+    FootnoteRef,
+    // bare urls, <autolinks> and email addresses
+    Url(Url),
+    // %% text %% or <!-- text -->
+    Comment,
+    Html(Box<Html>),
+    // a dataview key:: value field
+    InlineField(Box<InlineField>),
+}
+
+impl NodeEnum {
+    // the name of the variant, for error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NodeEnum::Text => "Text",
+            NodeEnum::BoldItalic(_) => "BoldItalic",
+            NodeEnum::Bold(..) => "Bold",
+            NodeEnum::Italic(..) => "Italic",
+            NodeEnum::Highlight(_) => "Highlight",
+            NodeEnum::Strikethrough(_) => "Strikethrough",
+            NodeEnum::MDLink(_) => "MDLink",
+            NodeEnum::NamedMDLink(_) => "NamedMDLink",
+            NodeEnum::WebLink(_) => "WebLink",
+            NodeEnum::SquareBracket => "SquareBracket",
+            NodeEnum::InlineCode => "InlineCode",
+            NodeEnum::InlineLatex => "InlineLatex",
+            NodeEnum::InlineCodeBlock => "InlineCodeBlock",
+            NodeEnum::InlineLatexBlock => "InlineLatexBlock",
+            NodeEnum::Embed(_) => "Embed",
+            NodeEnum::Image(_) => "Image",
+            NodeEnum::Tag => "Tag",
+            NodeEnum::FootnoteRef => "FootnoteRef",
+            NodeEnum::Url(_) => "Url",
+            NodeEnum::Comment => "Comment",
+            NodeEnum::Html(_) => "Html",
+            NodeEnum::InlineField(_) => "InlineField",
+        }
+    }
 }
 
 impl Node {
-    pub fn get_string_node(&self) -> Vec<Node> {
-        match &self.node {
-            NodeEnum::Text(node) => {
-                vec![self.clone()]
-            }
-            NodeEnum::BoldItalic(node) => {
-                let nodes: Vec<Node> = node
-                    .iter()
-                    .map(|node| node.get_string_node())
-                    .flatten()
-                    .collect();
-                nodes
-            }
-            NodeEnum::Bold(node) => {
-                let nodes: Vec<Node> = node
-                    .iter()
-                    .map(|node| node.get_string_node())
-                    .flatten()
-                    .collect();
-                nodes
-            }
-            NodeEnum::Italic(node) => {
-                let nodes: Vec<Node> = node
-                    .iter()
-                    .map(|node| node.get_string_node())
-                    .flatten()
-                    .collect();
-                nodes
+    pub fn get_string_node(&self, settings: &Settings, source: &str) -> Vec<Node> {
+        let mut visitor: StringNodes = StringNodes {
+            settings,
+            source,
+            nodes: Vec::new(),
+        };
+        visitor.visit_node(self, &mut VisitContext::new());
+        visitor.nodes
+    }
+
+    // this node followed by every node nested inside it
+    pub fn get_nodes(&self) -> Vec<&Node> {
+        let mut visitor: AllNodes = AllNodes { nodes: Vec::new() };
+        visitor.visit_node(self, &mut VisitContext::new());
+        visitor.nodes
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.start, self.end)
+    }
+
+    // what the node was parsed from, all a leaf such as Text has to it
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        self.span().text(source)
+    }
+
+    // the tag a #tag node is written as
+    pub fn tag(&self, source: &str) -> Option<Tag> {
+        match self.node {
+            NodeEnum::Tag => Tag::parse(self.text(source)),
+            _ => None,
+        }
+    }
+
+    // the label of a [^label] reference
+    pub fn footnote_label<'s>(&self, source: &'s str) -> Option<&'s str> {
+        match self.node {
+            NodeEnum::FootnoteRef => self
+                .text(source)
+                .strip_prefix("[^")
+                .and_then(|text| text.strip_suffix(']')),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        match &mut self.node {
+            NodeEnum::BoldItalic(nodes)
+            | NodeEnum::Bold(nodes, _)
+            | NodeEnum::Italic(nodes, _)
+            | NodeEnum::Highlight(nodes)
+            | NodeEnum::Strikethrough(nodes) => shrink_nodes(nodes),
+            NodeEnum::InlineField(field) => shrink_nodes(&mut field.value),
+            NodeEnum::MDLink(link) | NodeEnum::NamedMDLink(link) => {
+                link.heading_path.shrink_to_fit()
             }
-            NodeEnum::MDLink(_) => vec![],
-            NodeEnum::NamedMDLink(_) => vec![],
-            NodeEnum::WebLink(_) => vec![],
-            NodeEnum::SquareBracket(_) => vec![],
-            NodeEnum::InlineCode(_) => vec![],
-            NodeEnum::InlineLatex(_) => vec![],
-            NodeEnum::InlineCodeBlock(_) => vec![],
-            NodeEnum::InlineLatexBlock(_) => vec![],
+            NodeEnum::Html(html) => shrink_nodes(&mut html.nodes),
+            _ => {}
         }
     }
 
-    pub(crate) fn get_inner_string(&self) -> Result<&str> {
+    pub(crate) fn get_inner_string<'s>(&self, source: &'s str) -> Result<&'s str> {
         match &self.node {
-            NodeEnum::Text(s) => Ok(s.as_str()),
-            NodeEnum::BoldItalic(s) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from BoldItalic"
-            ))),
-            NodeEnum::Bold(s) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from Bold"
-            ))),
-            NodeEnum::Italic(s) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from Italic"
-            ))),
-            NodeEnum::MDLink(_) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from MDLink"
-            ))),
-            NodeEnum::NamedMDLink(_) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from NamedMDLink"
-            ))),
-            NodeEnum::WebLink(_) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from WebLink"
-            ))),
-            NodeEnum::SquareBracket(_) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from SquareBracket"
-            ))),
-            NodeEnum::InlineCode(_) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from InlineCode"
-            ))),
-            NodeEnum::InlineLatex(_) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from InlineLatex"
-            ))),
-            NodeEnum::InlineCodeBlock(_) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from InlineCodeBlock"
-            ))),
-            NodeEnum::InlineLatexBlock(_) => Err(Error::Generic(f!(
-                "Unexpected call to get_inner_string from InlineLatexBlock"
+            NodeEnum::Text => Ok(self.text(source)),
+            _ => Err(Error::Generic(f!(
+                "Unexpected call to get_inner_string from {}",
+                self.node.kind()
             ))),
         }
     }
@@ -995,26 +2331,89 @@ impl Node {
     // }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// a piece of a wikilink and where it sits in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkPart {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LinkPart {
+    fn new(start: usize, end: usize) -> Self {
+        LinkPart { start, end }
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.start, self.end)
+    }
+
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        self.span().text(source)
+    }
+
+    pub(crate) fn shift(&mut self, shift: Shift) {
+        shift.span(&mut self.start, &mut self.end);
+    }
+}
+
+// [[target#heading#subheading#^block_id|display]], target is empty for links within the same note
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WikiLink {
+    pub target: LinkPart,
+    pub heading_path: Vec<LinkPart>,
+    pub block_id: Option<LinkPart>,
+    pub display: Option<LinkPart>,
+    // the display follows \| instead of |, as it has to inside a table
+    pub escaped_pipe: bool,
+}
+
+impl WikiLink {
+    // the heading and block id parts joined the way they are written, without the leading #
+    pub fn get_subpath(&self, source: &str) -> Option<String> {
+        let mut parts: Vec<String> = self
+            .heading_path
+            .iter()
+            .map(|heading| heading.text(source).to_string())
+            .collect();
+        if let Some(block_id) = &self.block_id {
+            parts.push(f!("^{}", block_id.text(source)));
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("#"))
+        }
+    }
 
-pub struct NamedMDLink {
-    pub name: String,
-    pub link: String,
+    pub(crate) fn shift(&mut self, shift: Shift) {
+        self.target.shift(shift);
+        for heading in &mut self.heading_path {
+            heading.shift(shift);
+        }
+        if let Some(block_id) = &mut self.block_id {
+            block_id.shift(shift);
+        }
+        if let Some(display) = &mut self.display {
+            display.shift(shift);
+        }
+    }
 }
 
-fn parse_named_link_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<NamedMDLink> {
-    debug_assert!(pair.as_rule() == Rule::named_link_node);
+fn parse_wikilink_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<WikiLink> {
+    debug_assert!(pair.as_rule() == Rule::named_link_node || pair.as_rule() == Rule::link_node);
 
-    let mut name: String = String::new();
-    let mut link: String = String::new();
+    let mut link: Option<pest::Span> = None;
+    let mut display: Option<LinkPart> = None;
 
-    for pair_inner in pair.into_inner() {
+    for pair_inner in pair.clone().into_inner() {
+        let span = pair_inner.as_span();
         match pair_inner.as_rule() {
             Rule::filepath => {
-                link = pair_inner.as_str().to_string();
+                link = Some(span);
             }
-            Rule::node => {
-                name = pair_inner.as_str().to_string();
+            // the display is kept as written, a #word in it is not a tag
+            Rule::link_display => {
+                display = Some(LinkPart::new(span.start(), span.end()));
             }
             _ => {
                 return Err(Error::ParseError(
@@ -1025,74 +2424,445 @@ fn parse_named_link_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Resu
         }
     }
 
-    Ok(NamedMDLink { name, link })
+    let link: pest::Span = match link {
+        Some(link) => link,
+        None => {
+            return Err(Error::ParseError(
+                path.to_path_buf(),
+                format!("link without target: {:?}", pair),
+            ))
+        }
+    };
+    let mut link_text: &str = link.as_str();
+    // inside tables the alias pipe is written as \|
+    let escaped_pipe: bool = display.is_some() && link_text.ends_with('\\');
+    if escaped_pipe {
+        link_text = &link_text[..link_text.len() - 1];
+    }
+
+    let mut segments = link_text.split('#');
+    let mut offset: usize = link.start();
+    let target_text: &str = segments.next().unwrap_or("");
+    let target: LinkPart = LinkPart::new(offset, offset + target_text.len());
+    offset += target_text.len() + 1;
+
+    let mut heading_path: Vec<LinkPart> = Vec::new();
+    let mut block_id: Option<LinkPart> = None;
+    for segment in segments {
+        match segment.strip_prefix('^') {
+            Some(id) => block_id = Some(LinkPart::new(offset + 1, offset + 1 + id.len())),
+            None if !segment.is_empty() => {
+                heading_path.push(LinkPart::new(offset, offset + segment.len()))
+            }
+            None => {}
+        }
+        offset += segment.len() + 1;
+    }
+
+    Ok(WikiLink {
+        target,
+        heading_path,
+        block_id,
+        display,
+        escaped_pipe,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbedSize {
+    pub width: u32,
+    pub height: Option<u32>,
+}
+
+impl EmbedSize {
+    // 300 or 300x200 written the way it is displayed, 0300 or +300 is not a size
+    fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.splitn(2, 'x');
+        let width: u32 = parts.next()?.parse().ok()?;
+        let height: Option<u32> = match parts.next() {
+            Some(height) => Some(height.parse().ok()?),
+            None => None,
+        };
+        let size: EmbedSize = EmbedSize { width, height };
+        (size.to_string() == text).then_some(size)
+    }
+}
+
+impl std::fmt::Display for EmbedSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.height {
+            Some(height) => write!(f, "{}x{}", self.width, height),
+            None => write!(f, "{}", self.width),
+        }
+    }
 }
 
+// ![[target|option]], the option is a size for media and alt text otherwise
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Embed {
+    pub target: Span,
+    pub size: Option<EmbedSize>,
+    pub alt: Option<Span>,
+}
 
-pub struct WebLink {
-    pub name: String,
-    pub link: String,
+fn parse_embed_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Embed> {
+    debug_assert!(pair.as_rule() == Rule::embed_node);
+
+    let start: usize = pair.as_span().start();
+    let mut embed: Embed = Embed {
+        target: Span::new(start, start),
+        size: None,
+        alt: None,
+    };
+
+    for pair_inner in pair.into_inner() {
+        match pair_inner.as_rule() {
+            Rule::filepath => {
+                embed.target = pair_span(&pair_inner);
+            }
+            Rule::embed_option => match EmbedSize::parse(pair_inner.as_str()) {
+                Some(size) => embed.size = Some(size),
+                None => embed.alt = Some(pair_span(&pair_inner)),
+            },
+            _ => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 21: {:?}", pair_inner),
+                ))
+            }
+        }
+    }
+
+    Ok(embed)
 }
 
-fn parse_weblink_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<WebLink> {
-    debug_assert!(pair.as_rule() == Rule::weblink_node);
+// ![alt|size](target)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Image {
+    pub target: Span,
+    pub size: Option<EmbedSize>,
+    pub alt: Span,
+}
+
+fn parse_image_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Image> {
+    debug_assert!(pair.as_rule() == Rule::image_node);
 
-    let mut name: String = String::new();
-    let mut link: String = String::new();
+    let start: usize = pair.as_span().start();
+    let mut image: Image = Image {
+        target: Span::new(start, start),
+        size: None,
+        alt: Span::new(start, start),
+    };
 
     for pair_inner in pair.into_inner() {
         match pair_inner.as_rule() {
-            Rule::weblink_text => {
-                name = pair_inner.as_str().to_string();
+            Rule::image_alt => {
+                image.alt = pair_span(&pair_inner);
             }
-            Rule::weblink_link => {
-                link = pair_inner.as_str().to_string();
+            // a size that would not be written back the same stays part of the alt text
+            Rule::image_size => match EmbedSize::parse(pair_inner.as_str()) {
+                Some(size) => image.size = Some(size),
+                None => image.alt.end = pair_inner.as_span().end(),
+            },
+            Rule::image_url => {
+                image.target = pair_span(&pair_inner);
             }
             _ => {
                 return Err(Error::ParseError(
                     path.to_path_buf(),
-                    format!("unexpected rule 16: {:?}", pair_inner),
+                    format!("unexpected rule 22: {:?}", pair_inner),
                 ))
             }
         }
     }
 
-    Ok(WebLink { name, link })
+    Ok(image)
 }
 
-// #[derive(Debug, Clone, Serialize, Deserialize)]
-// pub(crate) struct StringPosition<'a> {
-//     pub string_node: &'a Node,
-//     // pub line: u32,
-//     // pub column: u32,
-//     pub start: u32,
-//     pub end: u32,
-// }
+// url is written as in the note, without the angle brackets of an autolink
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Url {
+    pub url: Span,
+    pub autolink: bool,
+    pub email: bool,
+}
 
-// #[cfg(test)]
-// pub mod parser_tests {
-//     use super::*;
-//     use crate::prelude::*;
+impl Url {
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        self.url.text(source)
+    }
 
-//     #[test]
-//     fn test_parse_md_file() {
-//         let path = "test/";
-//         for file in std::fs::read_dir(path).unwrap() {
-//             println!("{:?}", file);
-//             let file = file.unwrap();
-//             let path = file.path();
-//             let path = path.to_str().unwrap();
-//             let contents = std::fs::read_to_string(path).unwrap() + "\n";
-//             let md_file = MDParser::parse(Rule::md_file, &contents);
-//             if md_file.is_err() {
-//                 continue;
-//             }
-//             let md_file = md_file.unwrap();
-//             let flattened = md_file.flatten();
-//             for inner in flattened {
-//                 let start = inner.as_span().start();
-//                 let end = inner.as_span().end();
+    // what the url points at, with the scheme filled in for www. and email addresses
+    pub fn href(&self, source: &str) -> String {
+        let url: &str = self.text(source);
+        if self.email && !url.to_lowercase().starts_with("mailto:") {
+            f!("mailto:{}", url)
+        } else if url.to_lowercase().starts_with("www.") {
+            f!("http://{}", url)
+        } else {
+            url.to_string()
+        }
+    }
+}
+
+fn parse_url_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Url> {
+    match pair.as_rule() {
+        Rule::bare_url_node => Ok(Url {
+            url: pair_span(&pair),
+            autolink: false,
+            email: false,
+        }),
+        Rule::email_node => Ok(Url {
+            url: pair_span(&pair),
+            autolink: false,
+            email: true,
+        }),
+        Rule::autolink_node => {
+            let inner = pair.into_inner().next().ok_or_else(|| {
+                Error::ParseError(path.to_path_buf(), "autolink without url".to_string())
+            })?;
+            let email: bool = inner.clone().into_inner().next().is_some();
+            Ok(Url {
+                url: pair_span(&inner),
+                autolink: true,
+                email,
+            })
+        }
+        _ => Err(Error::ParseError(
+            path.to_path_buf(),
+            format!("unexpected rule 27: {:?}", pair),
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InlineField {
+    pub key: LinkPart,
+    pub value: Vec<Node>,
+    // the [ or ( around the field, None for a field that takes up its line
+    pub bracket: Option<char>,
+}
+
+// [key:: value] or (key:: value), the value may hold links, tags and urls
+fn parse_inline_field_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<NodeEnum> {
+    debug_assert!(pair.as_rule() == Rule::inline_field_node);
+
+    let bracket: Option<char> = pair.as_str().chars().next();
+    let mut key: Option<LinkPart> = None;
+    let mut value: Vec<Node> = Vec::new();
+    for pair_inner in pair.into_inner() {
+        match pair_inner.as_rule() {
+            Rule::inline_field_key => {
+                key = Some(parse_inline_field_key(pair_inner));
+            }
+            Rule::inline_field_value | Rule::inline_field_paren_value => {
+                value = parse_string_line(pair_inner, path)?.nodes;
+            }
+            _ => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 28: {:?}", pair_inner),
+                ))
+            }
+        }
+    }
+
+    let key: LinkPart = key.ok_or_else(|| {
+        Error::ParseError(path.to_path_buf(), "inline field without key".to_string())
+    })?;
+    Ok(NodeEnum::InlineField(Box::new(InlineField {
+        key,
+        value,
+        bracket,
+    })))
+}
+
+// surrounding whitespace is not part of the key
+fn parse_inline_field_key(pair: pest::iterators::Pair<Rule>) -> LinkPart {
+    let key: Span = trimmed_span(&pair);
+    LinkPart::new(key.start, key.end)
+}
+
+// a lone tag such as <br> or </div> has no nodes, an element on one line holds its content
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Html {
+    // the tag name as written, html names are case insensitive
+    pub name: Span,
+    // the whole tag, or the opening tag of an element
+    pub tag: Span,
+    pub nodes: Vec<Node>,
+    // an element closed by its own end tag, <b></b> holds no nodes either
+    pub element: bool,
+}
+
+fn parse_html_tag_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Html> {
+    debug_assert!(pair.as_rule() == Rule::html_tag_node);
+
+    let tag: Span = pair_span(&pair);
+    let mut html: Html = Html {
+        name: Span::new(tag.start, tag.start),
+        tag,
+        nodes: Vec::new(),
+        element: false,
+    };
+
+    for pair_inner in pair.into_inner() {
+        match pair_inner.as_rule() {
+            Rule::html_tag_name => {
+                html.name = pair_span(&pair_inner);
+            }
+            Rule::html_attributes => {}
+            _ => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 25: {:?}", pair_inner),
+                ))
+            }
+        }
+    }
+
+    Ok(html)
+}
+
+fn parse_html_element_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<Html> {
+    debug_assert!(pair.as_rule() == Rule::html_element_node);
+
+    let start: usize = pair.as_span().start();
+    let mut html: Html = Html {
+        name: Span::new(start, start),
+        tag: Span::new(start, start),
+        nodes: Vec::new(),
+        element: true,
+    };
+
+    for pair_inner in pair.clone().into_inner() {
+        match pair_inner.as_rule() {
+            Rule::html_tag_name => {
+                html.name = pair_span(&pair_inner);
+            }
+            Rule::html_attributes => {
+                // the attributes are followed by the closing > of the opening tag
+                html.tag.end = pair_inner.as_span().end() + 1;
+            }
+            _ => {}
+        }
+    }
+    html.nodes = parse_string_line(pair, path)?.nodes;
+
+    Ok(html)
+}
+
+// #project/alpha, one entry per nested level
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tag {
+    pub path: Vec<String>,
+}
+
+impl Tag {
+    // frontmatter tags may be written with or without the leading #
+    pub fn parse(text: &str) -> Option<Self> {
+        let text: &str = text.trim();
+        let text: &str = text.strip_prefix('#').unwrap_or(text);
+        let path: Vec<String> = text
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| segment.to_string())
+            .collect();
+        if path.is_empty()
+            || path
+                .iter()
+                .all(|segment| segment.chars().all(|c| c.is_ascii_digit()))
+        {
+            return None;
+        }
+        Some(Tag { path })
+    }
+
+    pub fn name(&self) -> String {
+        self.path.join("/")
+    }
+
+    // tags are case insensitive and #a matches #a/b
+    pub fn matches(&self, query: &str) -> bool {
+        let query: Tag = match Tag::parse(query) {
+            Some(query) => query,
+            None => return false,
+        };
+        query.path.len() <= self.path.len()
+            && query
+                .path
+                .iter()
+                .zip(self.path.iter())
+                .all(|(query, segment)| query.to_lowercase() == segment.to_lowercase())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+
+pub struct WebLink {
+    pub name: Span,
+    pub link: Span,
+}
+
+fn parse_weblink_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<WebLink> {
+    debug_assert!(pair.as_rule() == Rule::weblink_node);
+
+    let start: usize = pair.as_span().start();
+    let mut name: Span = Span::new(start, start);
+    let mut link: Span = Span::new(start, start);
+
+    for pair_inner in pair.into_inner() {
+        match pair_inner.as_rule() {
+            Rule::weblink_text => {
+                name = pair_span(&pair_inner);
+            }
+            Rule::weblink_link => {
+                link = pair_span(&pair_inner);
+            }
+            _ => {
+                return Err(Error::ParseError(
+                    path.to_path_buf(),
+                    format!("unexpected rule 16: {:?}", pair_inner),
+                ))
+            }
+        }
+    }
+
+    Ok(WebLink { name, link })
+}
+
+// #[derive(Debug, Clone, Serialize, Deserialize)]
+// pub(crate) struct StringPosition<'a> {
+//     pub string_node: &'a Node,
+//     // pub line: u32,
+//     // pub column: u32,
+//     pub start: u32,
+//     pub end: u32,
+// }
+
+// #[cfg(test)]
+// pub mod parser_tests {
+//     use super::*;
+//     use crate::prelude::*;
+
+//     #[test]
+//     fn test_parse_md_file() {
+//         let path = "test/";
+//         for file in std::fs::read_dir(path).unwrap() {
+//             println!("{:?}", file);
+//             let file = file.unwrap();
+//             let path = file.path();
+//             let path = path.to_str().unwrap();
+//             let contents = std::fs::read_to_string(path).unwrap() + "\n";
+//             let md_file = MDParser::parse(Rule::md_file, &contents);
+//             if md_file.is_err() {
+//                 continue;
+//             }
+//             let md_file = md_file.unwrap();
+//             let flattened = md_file.flatten();
+//             for inner in flattened {
+//                 let start = inner.as_span().start();
+//                 let end = inner.as_span().end();
 
 //                 // assert that the contents from start to end are the same as the inner.as_str()
 //                 assert!(&contents[start..end] == inner.as_str());
@@ -1106,3 +2876,1319 @@ fn parse_weblink_node(pair: pest::iterators::Pair<Rule>, path: &Path) -> Result<
 //         //     .unwrap();
 //     }
 // }
+
+#[cfg(test)]
+pub mod parser_tests {
+    use super::*;
+
+    #[test]
+    fn recovering_parse_test() {
+        let contents: &str = "Alan Turing\nstray * here\nTuring Machine\n";
+        let path: PathBuf = PathBuf::from("recover.md");
+
+        assert!(parse_md_file_wrapper(contents.to_string(), path.clone()).is_err());
+
+        let md_file = parse_md_file_recovering(contents.to_string(), path).unwrap();
+        let unparsed: Vec<&UnparsedBlock> = md_file.get_unparsed_blocks();
+        assert_eq!(unparsed.len(), 1);
+        assert_eq!(unparsed[0].text(&md_file.source), "stray * here\n");
+        assert_eq!((unparsed[0].start, unparsed[0].end), (12, 25));
+
+        let texts: Vec<&str> = md_file
+            .get_string_nodes(&Settings::default())
+            .iter()
+            .map(|node| &contents[node.start..node.end])
+            .collect();
+        assert_eq!(texts, vec!["Alan Turing", "Turing Machine"]);
+    }
+
+    #[test]
+    fn recovering_parse_merges_lines_test() {
+        let contents: &str = "---\naliases: []\n---\nok\n$bad\n*bad\nok again";
+        let path: PathBuf = PathBuf::from("recover.md");
+
+        let md_file = parse_md_file_recovering(contents.to_string(), path).unwrap();
+        assert!(md_file.frontmatter.is_some());
+        let unparsed: Vec<&UnparsedBlock> = md_file.get_unparsed_blocks();
+        assert_eq!(unparsed.len(), 1);
+        assert_eq!(unparsed[0].text(contents), "$bad\n*bad\n");
+    }
+
+    #[test]
+    fn diagnostic_test() {
+        let contents: &str = "first line\nsecond é *line\nthird line\n";
+        let path: PathBuf = PathBuf::from("diagnostic.md");
+
+        let error: Error = parse_md_file_wrapper(contents.to_string(), path.clone()).unwrap_err();
+        let diagnostic: &ParseDiagnostic = error.diagnostic().unwrap();
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 15));
+        assert_eq!(diagnostic.byte_offset, 26);
+        assert_eq!(diagnostic.excerpt, "second é *line");
+        assert!(!diagnostic.expected.is_empty());
+
+        let md_file = parse_md_file_recovering(contents.to_string(), path).unwrap();
+        let diagnostic: &ParseDiagnostic = &md_file.get_unparsed_blocks()[0].diagnostic;
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 15));
+    }
+
+    #[test]
+    fn line_endings_test() {
+        let contents: &str = "\u{feff}---\r\naliases: [Turing]\r\n---\r\n# Alan Turing\r\n- a [[link]]\r\n```py\r\nx = 1\r\n```\r\nsecond é *line\r\nlast";
+        let path: PathBuf = PathBuf::from("windows.md");
+
+        let error: Error = parse_md_file_wrapper(contents.to_string(), path.clone()).unwrap_err();
+        let diagnostic: &ParseDiagnostic = error.diagnostic().unwrap();
+        assert_eq!((diagnostic.line, diagnostic.column), (9, 15));
+        assert_eq!(diagnostic.byte_offset, contents.find("\r\nlast").unwrap());
+
+        let md_file = parse_md_file_recovering(contents.to_string(), path).unwrap();
+        assert_eq!(md_file.render(), contents);
+        assert_eq!(md_file.get_aliases(), vec!["Turing"]);
+        let frontmatter: &Frontmatter = md_file.get_frontmatter().unwrap();
+        assert_eq!(
+            &contents[frontmatter.start..frontmatter.end],
+            "\r\naliases: [Turing]\r\n"
+        );
+        let diagnostic: &ParseDiagnostic = &md_file.get_unparsed_blocks()[0].diagnostic;
+        assert_eq!((diagnostic.line, diagnostic.column), (9, 15));
+        assert_eq!(diagnostic.excerpt, "second é *line");
+
+        let link: &WikiLink = md_file.get_wikilinks()[0];
+        assert_eq!(&contents[link.target.start..link.target.end], "link");
+        let code_block: &CodeBlock = md_file
+            .blocks
+            .iter()
+            .find_map(|block| match block {
+                Block::Code(code_block) => Some(code_block),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(code_block.code.text(contents), "\r\nx = 1\r\n");
+        assert!(contents[code_block.start..].starts_with("```py"));
+        assert!(contents[..code_block.end].ends_with("\r\n```"));
+        // no text ends between a \r and its \n
+        for node in md_file.get_nodes() {
+            if let NodeEnum::Text = &node.node {
+                let text: &str = node.text(&md_file.source);
+                assert!(
+                    !text.ends_with('\r') && !text.starts_with('\n'),
+                    "{:?}",
+                    text
+                );
+            }
+        }
+        assert_eq!(md_file.block_spans.last().unwrap().1, contents.len() + 1);
+    }
+
+    #[test]
+    fn span_test() {
+        let contents: &str =
+            "# Grüße 🎉\r\n> quote 📚 [[note]]\r\n> > nested\r\n\r\n- a\r\n  - b ^id\r\n| x |\n| - |\n";
+        let md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("spans.md")).unwrap();
+
+        let spans: Vec<(usize, usize)> = md_file
+            .blocks
+            .iter()
+            .map(|block| (block.span().start, block.span().end))
+            .collect();
+        assert_eq!(spans, md_file.block_spans);
+        let texts: Vec<&str> = md_file
+            .blocks
+            .iter()
+            .flat_map(|block| block.get_lines())
+            .map(|line| &contents[line.span().start..line.span().end])
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "# Grüße 🎉",
+                " quote 📚 [[note]]",
+                " nested",
+                "",
+                "- a",
+                "  - b ^id"
+            ]
+        );
+        let nested: &Block = match &md_file.blocks[1] {
+            Block::BlockQuote(block_quote) => &block_quote.inner_blocks[1],
+            _ => panic!("expected a block quote"),
+        };
+        assert_eq!(
+            &contents[nested.span().start..nested.span().end],
+            " > nested"
+        );
+
+        // line and column count characters, utf16 counts what JavaScript does
+        let link: &Node = md_file
+            .get_nodes()
+            .into_iter()
+            .find(|node| matches!(node.node, NodeEnum::MDLink(_)))
+            .unwrap();
+        let location: Location = md_file.location(link.span());
+        assert_eq!((location.start.line, location.start.column), (2, 11));
+        assert_eq!((location.end.line, location.end.column), (2, 19));
+        let utf16: Vec<u16> = contents.encode_utf16().collect();
+        assert_eq!(
+            String::from_utf16(&utf16[location.start.utf16..location.end.utf16]).unwrap(),
+            "[[note]]"
+        );
+        assert_eq!(md_file.position(contents.len()).line, 9);
+
+        // every character boundary survives the trip through UTF-16
+        for (byte, _) in contents.char_indices() {
+            let position: Position = md_file.position(byte);
+            assert_eq!(
+                md_file.line_index.byte_offset(contents, position.utf16),
+                byte
+            );
+        }
+        // an offset inside a character is read as its start
+        let party: usize = contents.find('🎉').unwrap();
+        assert_eq!(md_file.position(party + 2), md_file.position(party));
+
+        // an ascii file keeps no UTF-16 line starts, offsets are the same
+        let ascii: &str = "# a\n\n- [[b]]\n";
+        let index: LineIndex = LineIndex::new(ascii);
+        for byte in 0..=ascii.len() {
+            assert_eq!(index.position(ascii, byte).utf16, byte);
+            assert_eq!(index.byte_offset(ascii, byte), byte);
+        }
+        assert_eq!(index.position(ascii, 8).line, 3);
+    }
+
+    #[test]
+    fn visitor_test() {
+        struct Texts<'s>(&'s str, Vec<(String, Vec<u32>, usize, Vec<Emphasis>)>);
+        impl<'ast> Visitor<'ast> for Texts<'_> {
+            fn visit_text(&mut self, text: Span, context: &mut VisitContext) {
+                let levels: Vec<u32> = context
+                    .headings()
+                    .iter()
+                    .map(|heading| heading.level)
+                    .collect();
+                self.1.push((
+                    self.0[text.start..text.end].trim().to_string(),
+                    levels,
+                    context.list_depth(),
+                    context.emphasis().to_vec(),
+                ));
+            }
+        }
+        struct Underscores;
+        impl VisitorMut for Underscores {
+            // delimiters are generated from the tree, **bold** is written as __bold__
+            fn visit_node_mut(&mut self, node: &mut Node, context: &mut VisitContext) {
+                if let NodeEnum::Bold(_, delimiter) = &mut node.node {
+                    *delimiter = '_';
+                }
+                visit::walk_node_mut(self, node, context);
+            }
+        }
+
+        let contents: &str = "# Top\nintro **bold ==both==**\n## Sub\n- one\n    - two ==mark== [[a]]\n- three\n>## quoted\n> - in quote\nafter\n";
+        let mut md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("visit.md")).unwrap();
+        let mut texts: Texts = Texts(contents, Vec::new());
+        texts.visit_file(&md_file, &mut VisitContext::new());
+        let texts: Vec<(&str, Vec<u32>, usize, Vec<Emphasis>)> = texts
+            .1
+            .iter()
+            .map(|(text, levels, depth, emphasis)| {
+                (text.as_str(), levels.clone(), *depth, emphasis.clone())
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("Top", vec![1], 0, vec![]),
+                ("intro", vec![1], 0, vec![]),
+                ("bold", vec![1], 0, vec![Emphasis::Bold]),
+                (
+                    "both",
+                    vec![1],
+                    0,
+                    vec![Emphasis::Bold, Emphasis::Highlight]
+                ),
+                ("Sub", vec![1, 2], 0, vec![]),
+                ("one", vec![1, 2], 1, vec![]),
+                ("two", vec![1, 2], 2, vec![]),
+                ("mark", vec![1, 2], 2, vec![Emphasis::Highlight]),
+                ("", vec![1, 2], 2, vec![]),
+                ("three", vec![1, 2], 1, vec![]),
+                ("quoted", vec![1, 2], 0, vec![]),
+                ("in quote", vec![1, 2], 1, vec![]),
+                ("after", vec![1, 2], 0, vec![]),
+            ]
+        );
+
+        Underscores.visit_file_mut(&mut md_file, &mut VisitContext::new());
+        assert_eq!(
+            md_file.render(),
+            contents.replace("**bold ==both==**", "__bold ==both==__")
+        );
+    }
+
+    #[test]
+    fn block_quote_test() {
+        let contents: &str =
+            "> Alan Turing\n> > Turing **Machine**\n>> nested\n> back out\nafter\n";
+        let md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("quote.md")).unwrap();
+
+        let block_quote: &BlockQuote = match &md_file.blocks[0] {
+            Block::BlockQuote(block_quote) => block_quote,
+            block => panic!("expected block quote, got {:?}", block),
+        };
+        assert_eq!(block_quote.inner_blocks.len(), 3);
+        assert!(matches!(block_quote.inner_blocks[0], Block::String(_)));
+        assert!(matches!(block_quote.inner_blocks[1], Block::BlockQuote(_)));
+        assert!(matches!(block_quote.inner_blocks[2], Block::String(_)));
+
+        let texts: Vec<&str> = md_file
+            .get_string_nodes(&Settings::default())
+            .iter()
+            .map(|node| &contents[node.start..node.end])
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                " Alan Turing",
+                " Turing ",
+                "Machine",
+                " nested",
+                " back out",
+                "after"
+            ]
+        );
+    }
+
+    #[test]
+    fn callout_test() {
+        let contents: &str =
+            "> [!warning]- Alan Turing\n> body text\n> > nested\n> [!NOTE]\n> second\n";
+        let md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("callout.md")).unwrap();
+        assert_eq!(md_file.blocks.len(), 2);
+
+        let callout: &Callout = match &md_file.blocks[0] {
+            Block::Callout(callout) => callout,
+            block => panic!("expected callout, got {:?}", block),
+        };
+        assert_eq!(callout.kind.text(contents), "warning");
+        assert!(callout.foldable && callout.collapsed);
+        assert_eq!(callout.inner_blocks.len(), 2);
+
+        let mut settings: Settings = Settings::default();
+        let texts: Vec<&str> = md_file
+            .get_string_nodes(&settings)
+            .iter()
+            .map(|node| &contents[node.start..node.end])
+            .collect();
+        assert_eq!(
+            texts,
+            vec![" Alan Turing", " body text", " nested", " second"]
+        );
+
+        settings.excluded_callouts = vec!["note".to_string()];
+        let texts: Vec<&str> = md_file
+            .get_string_nodes(&settings)
+            .iter()
+            .map(|node| &contents[node.start..node.end])
+            .collect();
+        assert_eq!(texts, vec![" Alan Turing", " body text", " nested"]);
+    }
+
+    #[test]
+    fn table_test() {
+        let contents: &str = "intro\n| a | **b** |\n|:-:|--:|\n| x \\| y | [[z]] |\nno pipes\n";
+        let md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("table.md")).unwrap();
+        assert_eq!(md_file.blocks.len(), 3);
+
+        let table: &Table = match &md_file.blocks[1] {
+            Block::Table(table) => table,
+            block => panic!("expected table, got {:?}", block),
+        };
+        assert_eq!(table.alignments, vec![Alignment::Center, Alignment::Right]);
+        assert_eq!(table.header.len(), 2);
+        assert_eq!(table.rows.len(), 1);
+        let cells: Vec<&str> = table
+            .get_cells()
+            .iter()
+            .map(|cell| &contents[cell.start..cell.end])
+            .collect();
+        assert_eq!(cells, vec![" a ", " **b** ", " x \\| y ", " [[z]] "]);
+        assert!(matches!(
+            table.rows[0][1].nodes[1].node,
+            NodeEnum::MDLink(_)
+        ));
+    }
+
+    #[test]
+    fn quoted_table_test() {
+        let contents: &str =
+            "> [!note] Table\n> before\n> | a | b |\n> | - | - |\n> | Alan Turing | x |\n> after\n";
+        let mut md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("table.md")).unwrap();
+        let callout: &Callout = match &md_file.blocks[0] {
+            Block::Callout(callout) => callout,
+            block => panic!("expected callout, got {:?}", block),
+        };
+        assert!(matches!(
+            callout.inner_blocks.as_slice(),
+            [Block::String(_), Block::Table(_), Block::String(_)]
+        ));
+        let cells: Vec<&str> = md_file
+            .get_table_cells()
+            .iter()
+            .map(|cell| &contents[cell.start..cell.end])
+            .collect();
+        assert_eq!(cells, vec![" a ", " b ", " Alan Turing ", " x "]);
+        assert_eq!(md_file.render(), contents);
+
+        let start: usize = contents.find("Alan Turing").unwrap();
+        md_file
+            .wrap_in_link(start, start + "Alan Turing".len(), "turing")
+            .unwrap();
+        assert_eq!(
+            md_file.source,
+            contents.replace("Alan Turing", "[[turing\\|Alan Turing]]")
+        );
+    }
+
+    #[test]
+    fn code_fence_test() {
+        let contents: &str = "- item\n    ```python\n    x = [[a]]\n    ```\n~~~\n```\nnot closed here\n~~~\n````md\n```c\nint x;\n```\n````\ntext ```inline``` more\n\\begin{align}\na &= [[b]] \\\\\n\\end{align}\nand \\(x [[c]]\\) or\n\\[y [[d]]\\]\n```\nopen to the end [[e]]\n";
+        let path: PathBuf = PathBuf::from("fences.md");
+        let md_file = parse_md_file_wrapper(contents.to_string(), path).unwrap();
+        assert!(md_file.get_wikilinks().is_empty());
+        assert_eq!(md_file.render(), contents);
+
+        let code_blocks: Vec<(&str, Option<&str>, &str)> = md_file
+            .blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Code(code_block) => Some((
+                    code_block.fence.text(contents),
+                    code_block
+                        .code_type
+                        .map(|code_type| code_type.text(contents)),
+                    code_block.closing_fence.text(contents),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            code_blocks,
+            vec![
+                ("```", Some("python"), "    ```"),
+                ("~~~", None, "~~~"),
+                ("````", Some("md"), "````"),
+                ("```", None, "")
+            ]
+        );
+        let nested: &CodeBlock = md_file
+            .blocks
+            .iter()
+            .find_map(|block| match block {
+                Block::Code(code_block) if code_block.fence.text(contents) == "````" => {
+                    Some(code_block)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(nested.code.text(contents), "\n```c\nint x;\n```\n");
+
+        let latex: Vec<&str> = md_file
+            .get_nodes()
+            .into_iter()
+            .filter_map(|node| match &node.node {
+                NodeEnum::InlineLatex | NodeEnum::InlineLatexBlock | NodeEnum::InlineCodeBlock => {
+                    Some(node.text(contents))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            latex,
+            vec![
+                "```inline```",
+                "\\begin{align}\na &= [[b]] \\\\\n\\end{align}",
+                "\\(x [[c]]\\)",
+                "\\[y [[d]]\\]"
+            ]
+        );
+
+        // \[ and \( do not reach past a blank line, and an escaped bracket in a line is text
+        let contents: &str = "a \\[ b\nc [[Link]] d\n\ne \\] f \\(g\n\nh [[Other]] \\)\nsee \\[1\\] [[Alan Turing]]\n```\n~~~\ncode [[x]]\n```\n";
+        let md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("escaped.md")).unwrap();
+        let targets: Vec<&str> = md_file
+            .get_wikilinks()
+            .iter()
+            .map(|link| link.target.text(contents))
+            .collect();
+        assert_eq!(targets, vec!["Link", "Other", "Alan Turing"]);
+        assert!(!md_file.get_nodes().iter().any(|node| matches!(
+            node.node,
+            NodeEnum::InlineLatex | NodeEnum::InlineLatexBlock
+        )));
+        assert!(md_file.blocks.iter().any(
+            |block| matches!(block, Block::Code(code_block) if code_block.code.text(contents) == "\n~~~\ncode [[x]]\n")
+        ));
+    }
+
+    #[test]
+    fn task_test() {
+        let contents: &str =
+            "- [ ] buy milk\n  - [x] done\n> 1. [/] in progress\n- [-]\n+ [X] Done\n- [>] later\n- [link](url)\n";
+        let md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("tasks.md")).unwrap();
+
+        let tasks: Vec<&Task> = md_file.get_tasks();
+        let states: Vec<(&str, char, bool)> = tasks
+            .iter()
+            .map(|task| (task.indent.text(contents), task.status_char, task.checked))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("", ' ', false),
+                ("  ", 'x', true),
+                (" ", '/', false),
+                ("", '-', false),
+                ("", 'X', true),
+                ("", '>', false)
+            ]
+        );
+
+        let node: &Node = &tasks[0].nodes[0];
+        assert_eq!(&contents[node.start..node.end], " buy milk");
+        assert!(tasks[3].nodes.is_empty());
+    }
+
+    #[test]
+    fn embed_test() {
+        let contents: &str =
+            "See ![[Note]] and ![[image.png|300x200]] or ![alt text|120](pic.png)! Wow![x]\n";
+        let md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("embed.md")).unwrap();
+
+        let embeds: Vec<&Embed> = md_file.get_embeds();
+        assert_eq!(embeds.len(), 2);
+        assert_eq!(embeds[0].target.text(contents), "Note");
+        assert_eq!(
+            embeds[1].size,
+            Some(EmbedSize {
+                width: 300,
+                height: Some(200)
+            })
+        );
+
+        let images: Vec<&Image> = md_file.get_images();
+        assert_eq!(images[0].target.text(contents), "pic.png");
+        assert_eq!(images[0].alt.text(contents), "alt text");
+        assert_eq!(images[0].size.map(|size| size.width), Some(120));
+
+        let texts: Vec<&str> = md_file
+            .get_string_nodes(&Settings::default())
+            .iter()
+            .map(|node| &contents[node.start..node.end])
+            .collect();
+        assert_eq!(texts, vec!["See ", " and ", " or ", "! Wow!"]);
+    }
+
+    #[test]
+    fn wikilink_test() {
+        let contents: &str =
+            "[[Note#Heading#Sub|shown text]] [[#^blockid]] [[Other]]\n| [[a\\|b]] |\n|---|\n[[Note|see #todo]]\n";
+        let md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("links.md")).unwrap();
+        let links: Vec<&WikiLink> = md_file.get_wikilinks();
+        assert_eq!(links.len(), 5);
+
+        let part = |part: &LinkPart| &contents[part.start..part.end];
+        assert_eq!(part(&links[0].target), "Note");
+        let headings: Vec<&str> = links[0].heading_path.iter().map(part).collect();
+        assert_eq!(headings, vec!["Heading", "Sub"]);
+        assert_eq!(part(links[0].display.as_ref().unwrap()), "shown text");
+        assert_eq!(
+            links[0].get_subpath(contents),
+            Some("Heading#Sub".to_string())
+        );
+
+        assert_eq!(part(&links[1].target), "");
+        assert_eq!(part(links[1].block_id.as_ref().unwrap()), "blockid");
+        assert_eq!(links[1].get_subpath(contents), Some("^blockid".to_string()));
+
+        assert_eq!(part(&links[2].target), "Other");
+        assert!(links[2].display.is_none() && links[2].get_subpath(contents).is_none());
+
+        assert_eq!(part(&links[3].target), "a");
+        assert_eq!(part(links[3].display.as_ref().unwrap()), "b");
+
+        assert_eq!(part(links[4].display.as_ref().unwrap()), "see #todo");
+        assert!(md_file.get_tags().is_empty());
+    }
+
+    #[test]
+    fn tag_test() {
+        let contents: &str = "---\ntags: [meta, \"#area/home\"]\n---\n# Heading #h1\nsee #project/alpha and C# or a#b\n#123 is not a tag but #y2024 is\n> quoted #inner-tag\n";
+        let path: PathBuf = PathBuf::from("tags.md");
+
+        let md_file = parse_md_file_wrapper(contents.to_string(), path).unwrap();
+        let tags: Vec<String> = md_file.get_tags().iter().map(|tag| tag.name()).collect();
+        assert_eq!(
+            tags,
+            vec![
+                "h1",
+                "project/alpha",
+                "y2024",
+                "inner-tag",
+                "meta",
+                "area/home"
+            ]
+        );
+
+        let tag: &Node = md_file
+            .get_nodes()
+            .into_iter()
+            .find(|node| node.tag(contents).is_some_and(|tag| tag.path.len() == 2))
+            .unwrap();
+        assert_eq!(tag.text(contents), "#project/alpha");
+
+        let alpha: Tag = Tag::parse("#project/alpha").unwrap();
+        assert!(alpha.matches("Project") && alpha.matches("#project/alpha"));
+        assert!(!alpha.matches("project/beta") && !alpha.matches("proj"));
+    }
+
+    #[test]
+    fn long_word_test() {
+        // urls and emails are only tried where a word starts, a long word has to stay linear
+        let word: String = "a1".repeat(100_000);
+        let contents: String = f!("{} see https://example.com or {}@example.com\n", word, word);
+        let path: PathBuf = PathBuf::from("long.md");
+
+        let md_file = parse_md_file_wrapper(contents.clone(), path).unwrap();
+        let urls: Vec<&str> = md_file
+            .get_urls()
+            .iter()
+            .map(|url| url.text(&contents))
+            .collect();
+        assert_eq!(urls.len(), 2);
+        assert_eq!(urls[0], "https://example.com");
+        assert!(urls[1] == f!("{}@example.com", word));
+    }
+
+    #[test]
+    fn footnote_block_id_test() {
+        let contents: &str = "Turing wrote it[^1] in 1936 ^origin\n- a list item ^item-2\nx^2 is not an id\n\n[^1]: On Computable Numbers\n    by Alan Turing\n";
+        let path: PathBuf = PathBuf::from("footnotes.md");
+
+        let md_file = parse_md_file_wrapper(contents.to_string(), path).unwrap();
+        let refs: Vec<&str> = md_file
+            .get_nodes()
+            .into_iter()
+            .filter_map(|node| match &node.node {
+                NodeEnum::FootnoteRef => node.footnote_label(contents),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(refs, vec!["1"]);
+
+        let ids: Vec<&str> = md_file
+            .get_block_ids()
+            .iter()
+            .map(|id| &contents[id.start..id.end])
+            .collect();
+        assert_eq!(ids, vec!["origin", "item-2"]);
+        assert!(matches!(
+            md_file.get_block("item-2"),
+            Some(Line::BulletedList(_))
+        ));
+        assert!(md_file.get_block("2").is_none());
+
+        let footnotes: Vec<&FootnoteDefinition> = md_file.get_footnote_definitions();
+        assert_eq!(footnotes.len(), 1);
+        assert_eq!(footnotes[0].label.text(contents), "1");
+        assert_eq!(footnotes[0].lines.len(), 2);
+
+        // the block id is not text, the footnote body is
+        let text: Vec<String> = md_file
+            .get_string_nodes(&Settings::default())
+            .iter()
+            .map(|node| contents[node.start..node.end].to_string())
+            .collect();
+        assert!(!text.iter().any(|text| text.contains("origin")));
+        assert!(text
+            .iter()
+            .any(|text| text.contains("On Computable Numbers")));
+    }
+
+    #[test]
+    fn inline_field_test() {
+        let contents: &str = "---\nstatus: draft\n---\nstatus:: done [[Alan Turing]]\nrated [rating:: 5] and (hidden:: [[Note]]) [not a field]\n- due:: tomorrow ^id\n```dataview\nlist\n```\n```dataviewjs\ndv.list()\n```\n";
+        let path: PathBuf = PathBuf::from("fields.md");
+        let md_file = parse_md_file_wrapper(contents.to_string(), path).unwrap();
+
+        assert_eq!(
+            md_file.get_inline_fields(),
+            vec![
+                ("status", "done [[Alan Turing]]"),
+                ("rating", "5"),
+                ("hidden", "[[Note]]"),
+                ("due", "tomorrow")
+            ]
+        );
+        let targets: Vec<&str> = md_file
+            .get_wikilinks()
+            .iter()
+            .map(|link| link.target.text(contents))
+            .collect();
+        assert_eq!(targets, vec!["Alan Turing", "Note"]);
+        assert!(md_file.get_nodes().iter().any(
+            |node| matches!(&node.node, NodeEnum::SquareBracket if node.text(contents) == "[not a field]")
+        ));
+        assert_eq!(md_file.get_block_ids()[0].text(contents), "id");
+
+        let properties: serde_yaml::Mapping = md_file.get_properties();
+        assert_eq!(
+            properties.get("status"),
+            Some(&serde_yaml::Value::Sequence(vec![
+                "draft".into(),
+                "done [[Alan Turing]]".into()
+            ]))
+        );
+        assert_eq!(properties.get("rating"), Some(&"5".into()));
+
+        // keys are only linkable when the setting allows it
+        let text = |settings: &Settings| -> Vec<String> {
+            md_file
+                .get_string_nodes(settings)
+                .iter()
+                .map(|node| contents[node.start..node.end].to_string())
+                .collect()
+        };
+        let settings: Settings = Settings::default();
+        assert!(!text(&settings).iter().any(|text| text.contains("status")));
+        assert!(text(&settings).contains(&" 5".to_string()));
+        let settings: Settings = Settings {
+            link_only_field_values: false,
+            ..Settings::default()
+        };
+        assert!(text(&settings).contains(&"status".to_string()));
+
+        let queries: Vec<&str> = md_file
+            .get_dataview_queries()
+            .iter()
+            .filter_map(|query| query.code_type)
+            .map(|code_type| code_type.text(contents))
+            .collect();
+        assert_eq!(queries, vec!["dataview", "dataviewjs"]);
+
+        // a :: in prose, code or a sentence is not a field
+        let contents: &str = "Call Vec::new to build Alan Turing lists\nNote: see:: this\nWait. then:: that\nends with::\n";
+        let md_file =
+            parse_md_file_wrapper(contents.to_string(), PathBuf::from("prose.md")).unwrap();
+        assert_eq!(md_file.get_inline_fields(), vec![("ends with", "")]);
+    }
+
+    #[test]
+    fn html_comment_test() {
+        let contents: &str = "text %% hidden [[link]] %% and <!-- gone -->\n%%\nblock comment\n%%\na <b>bold *x*</b> b<br>c < d\n<div align=\"center\">\n  <img src=\"a.png\">\n</div>\n\nafter\n";
+        let path: PathBuf = PathBuf::from("html.md");
+
+        let md_file = parse_md_file_wrapper(contents.to_string(), path).unwrap();
+        assert!(md_file.get_wikilinks().is_empty());
+
+        let comments: Vec<&str> = md_file
+            .get_nodes()
+            .into_iter()
+            .filter_map(|node| match &node.node {
+                NodeEnum::Comment => Some(node.text(contents)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            comments,
+            vec![
+                "%% hidden [[link]] %%",
+                "<!-- gone -->",
+                "%%\nblock comment\n%%"
+            ]
+        );
+
+        let html: Vec<(&str, &str, usize)> = md_file
+            .get_nodes()
+            .into_iter()
+            .filter_map(|node| match &node.node {
+                NodeEnum::Html(html) => Some((
+                    html.name.text(contents),
+                    html.tag.text(contents),
+                    html.nodes.len(),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            html,
+            vec![
+                ("b", "<b>", 1),
+                ("br", "<br>", 0),
+                ("div", "<div align=\"center\">", 0),
+                ("img", "<img src=\"a.png\">", 0),
+                ("div", "</div>", 0),
+            ]
+        );
+        assert!(matches!(
+            md_file.blocks[md_file.blocks.len() - 2],
+            Block::Html(_)
+        ));
+
+        let text: Vec<&str> = md_file
+            .get_string_nodes(&Settings::default())
+            .iter()
+            .map(|node| &contents[node.start..node.end])
+            .collect();
+        assert_eq!(text, vec!["text ", " and ", "a ", " b", "c < d", "after"]);
+    }
+
+    #[test]
+    fn emphasis_test() {
+        let contents: &str =
+            "==marked **bold**== ~~gone~~ _lean_ __heavy__ snake_case_name a == b ~x~\n";
+        let path: PathBuf = PathBuf::from("emphasis.md");
+
+        let md_file = parse_md_file_wrapper(contents.to_string(), path).unwrap();
+        let kinds: Vec<(&str, &str)> = md_file
+            .get_nodes()
+            .into_iter()
+            .map(|node| {
+                let kind: &str = match &node.node {
+                    NodeEnum::Highlight(_) => "highlight",
+                    NodeEnum::Strikethrough(_) => "strikethrough",
+                    NodeEnum::Bold(_, _) => "bold",
+                    NodeEnum::Italic(_, _) => "italic",
+                    NodeEnum::Text => "text",
+                    _ => "other",
+                };
+                (kind, &contents[node.start..node.end])
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("highlight", "==marked **bold**=="),
+                ("text", "marked "),
+                ("bold", "**bold**"),
+                ("text", "bold"),
+                ("text", " "),
+                ("strikethrough", "~~gone~~"),
+                ("text", "gone"),
+                ("text", " "),
+                ("italic", "_lean_"),
+                ("text", "lean"),
+                ("text", " "),
+                ("bold", "__heavy__"),
+                ("text", "heavy"),
+                ("text", " snake_case_name a == b ~x~"),
+            ]
+        );
+    }
+
+    #[test]
+    fn emphasis_markup_test() {
+        // emphasis and table cells take the same markup as a line
+        let contents: &str = "*a `b` c* **d `e` f[^1]**\n\n| *g `h`* [^1] |\n| - |\n";
+        let path: PathBuf = PathBuf::from("emphasis.md");
+
+        let md_file = parse_md_file_wrapper(contents.to_string(), path).unwrap();
+        let kinds: Vec<(&str, &str)> = md_file
+            .get_nodes()
+            .into_iter()
+            .filter(|node| !matches!(node.node, NodeEnum::Text))
+            .map(|node| (node.node.kind(), &contents[node.start..node.end]))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("Italic", "*a `b` c*"),
+                ("InlineCode", "`b`"),
+                ("Bold", "**d `e` f[^1]**"),
+                ("InlineCode", "`e`"),
+                ("FootnoteRef", "[^1]"),
+                ("Italic", "*g `h`*"),
+                ("InlineCode", "`h`"),
+                ("FootnoteRef", "[^1]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn outline_test() {
+        let contents: &str = "intro\n# A\n- one\n  - one.a\n    1. deep\n- two\n## B\n3. three\n\t4. four ^id\n# C\ntext\n";
+        let path: PathBuf = PathBuf::from("outline.md");
+
+        let md_file = parse_md_file_wrapper(contents.to_string(), path).unwrap();
+        let numbered: Vec<(&str, u32)> = md_file
+            .blocks
+            .iter()
+            .flat_map(|block| block.get_lines())
+            .filter_map(|line| match line {
+                Line::NumberedList(numbered) => {
+                    Some((numbered.indent.text(contents), numbered.number))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(numbered, vec![("    ", 1), ("", 3), ("\t", 4)]);
+
+        let outline: Outline = md_file.get_outline();
+        let sections: Vec<(u32, usize)> = outline
+            .get_sections()
+            .iter()
+            .map(|section| (section.heading.unwrap().level, section.children.len()))
+            .collect();
+        assert_eq!(sections, vec![(1, 1), (2, 0), (1, 0)]);
+        assert_eq!(outline.root.items.len(), 1);
+
+        let section_a: &Section = &outline.root.children[0];
+        assert_eq!(section_a.lists.len(), 2);
+        assert_eq!(section_a.lists[0].children[0].children.len(), 1);
+        assert_eq!(section_a.children[0].lists[0].children.len(), 1);
+
+        let deep: &Node = md_file
+            .get_nodes()
+            .into_iter()
+            .find(|node| &contents[node.start..node.end] == "deep")
+            .unwrap();
+        let headings: Vec<&str> = outline
+            .heading_path(deep)
+            .iter()
+            .map(|heading| heading.nodes[0].get_inner_string(contents).unwrap())
+            .collect();
+        assert_eq!(headings, vec!["A"]);
+        let parents: Vec<Option<&str>> = outline
+            .list_path(deep)
+            .iter()
+            .map(|line| line.get_indent().map(|indent| indent.text(contents)))
+            .collect();
+        assert_eq!(parents, vec![Some(""), Some("  "), Some("    ")]);
+
+        let four: &Node = md_file
+            .get_nodes()
+            .into_iter()
+            .find(|node| &contents[node.start..node.end] == "four ")
+            .unwrap();
+        assert_eq!(outline.heading_path(four).len(), 2);
+        assert_eq!(outline.list_path(four).len(), 2);
+    }
+
+    #[test]
+    fn frontmatter_test() {
+        let contents: &str = "---\n# kept\naliases: turing\nalias: [Alan, 1936]\ntags: a, b/c\ncssclasses:\n  - wide\nstatus: draft\n---\nbody\n";
+        let path: PathBuf = PathBuf::from("frontmatter.md");
+        let md_file = parse_md_file_wrapper(contents.to_string(), path.clone()).unwrap();
+        let frontmatter: &Frontmatter = md_file.get_frontmatter().unwrap();
+        assert_eq!(md_file.get_aliases(), vec!["Alan", "1936", "turing"]);
+        let tags: Vec<String> = frontmatter.tags.iter().map(|tag| tag.name()).collect();
+        assert_eq!(tags, vec!["a", "b/c"]);
+        assert_eq!(frontmatter.cssclasses, vec!["wide"]);
+        assert_eq!(
+            frontmatter.get("status").and_then(|v| v.as_str()),
+            Some("draft")
+        );
+        assert_eq!(frontmatter.get_list("aliases"), vec!["turing"]);
+        assert_eq!(
+            &contents[frontmatter.start..frontmatter.end],
+            &contents[3..contents.len() - 9]
+        );
+        assert!(frontmatter.diagnostic.is_none());
+
+        let contents: &str = "---\naliases: [turing\nstatus: x\n---\nbody\n";
+        let md_file = parse_md_file_wrapper(contents.to_string(), path.clone()).unwrap();
+        let frontmatter: &Frontmatter = md_file.get_frontmatter().unwrap();
+        assert!(md_file.get_aliases().is_empty());
+        let diagnostic: &ParseDiagnostic = frontmatter.diagnostic.as_ref().unwrap();
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(diagnostic.category(), "yaml_inner");
+        assert!(diagnostic.line > 1);
+        assert_eq!(md_file.get_nodes().len(), 1);
+
+        let contents: &str = "---\njust text\n---\nbody\n";
+        let md_file = parse_md_file_recovering(contents.to_string(), path).unwrap();
+        assert!(md_file.get_frontmatter().unwrap().diagnostic.is_some());
+    }
+
+    const ROUND_TRIP: &str = "---\ntags: [a]\n---\n# Title ^top\n\nSome **bold _x_** and ==hi== ~~no~~ [[a#b|c]] ![[img.png|100]] [x](https://e.com)\nstatus:: [[done]] [due:: today] (by:: me)\n- [ ] task #tag/sub\n\t1. item <b>html</b> %%c%% `code` $x$\n> [!note] Title\n> quote [^1]\n\n| a | b |\n| --- | --- |\n| [[x\\|y]] | www.e.com |\n\n```rust\nfn main() {}\n```\n$$\nx^2\n$$\n<div>\nblock\n</div>\n[^1]: note\n    more\ntrailing";
+
+    #[test]
+    fn render_round_trip_test() {
+        let path: PathBuf = PathBuf::from("round.md");
+        let md_file = parse_md_file_wrapper(ROUND_TRIP.to_string(), path.clone()).unwrap();
+        assert_eq!(md_file.render(), ROUND_TRIP);
+
+        let contents: String = f!("stray * here\n{}", ROUND_TRIP);
+        let md_file = parse_md_file_recovering(contents.clone(), path).unwrap();
+        assert_eq!(md_file.get_unparsed_blocks().len(), 1);
+        assert_eq!(md_file.render(), contents);
+    }
+
+    // the spans the renderer reads from the source, everything else it writes from the tree
+    struct ReadFromSource(Vec<Span>);
+
+    impl<'ast> Visitor<'ast> for ReadFromSource {
+        fn visit_callout(&mut self, callout: &'ast Callout, context: &mut VisitContext) {
+            self.0.push(callout.kind);
+            visit::walk_callout(self, callout, context);
+        }
+
+        fn visit_latex_block(&mut self, latex_block: &'ast LatexBlock, _: &mut VisitContext) {
+            self.0.push(Span::new(latex_block.start, latex_block.end));
+        }
+
+        fn visit_code_block(&mut self, code_block: &'ast CodeBlock, _: &mut VisitContext) {
+            self.0.push(Span::new(code_block.start, code_block.end));
+        }
+
+        fn visit_unparsed_block(&mut self, unparsed: &'ast UnparsedBlock, _: &mut VisitContext) {
+            self.0.push(Span::new(unparsed.start, unparsed.end));
+        }
+
+        fn visit_table(&mut self, table: &'ast Table, context: &mut VisitContext) {
+            self.0.push(table.delimiter_row);
+            visit::walk_table(self, table, context);
+        }
+
+        fn visit_footnote_definition(
+            &mut self,
+            footnote: &'ast FootnoteDefinition,
+            context: &mut VisitContext,
+        ) {
+            self.0.push(footnote.label);
+            visit::walk_lines(self, &footnote.lines, context);
+        }
+
+        fn visit_line(&mut self, line: &'ast Line, context: &mut VisitContext) {
+            self.0.extend(line.get_block_id().map(LinkPart::span));
+            visit::walk_line(self, line, context);
+        }
+
+        fn visit_task(&mut self, task: &'ast Task, context: &mut VisitContext) {
+            self.0.push(task.marker);
+            visit::walk_nodes(self, &task.nodes, context);
+        }
+
+        fn visit_node(&mut self, node: &'ast Node, context: &mut VisitContext) {
+            if matches!(
+                node.node,
+                NodeEnum::Text
+                    | NodeEnum::Tag
+                    | NodeEnum::SquareBracket
+                    | NodeEnum::InlineCode
+                    | NodeEnum::InlineLatex
+                    | NodeEnum::InlineCodeBlock
+                    | NodeEnum::InlineLatexBlock
+                    | NodeEnum::FootnoteRef
+                    | NodeEnum::Comment
+            ) {
+                self.0.push(node.span());
+            }
+            visit::walk_node(self, node, context);
+        }
+
+        fn visit_wikilink(&mut self, link: &'ast WikiLink, _: &mut VisitContext) {
+            self.0.push(link.target.span());
+            self.0.extend(link.heading_path.iter().map(LinkPart::span));
+            self.0.extend(link.block_id.iter().map(LinkPart::span));
+            self.0.extend(link.display.iter().map(LinkPart::span));
+        }
+
+        fn visit_web_link(&mut self, web_link: &'ast WebLink, _: &mut VisitContext) {
+            self.0.extend([web_link.name, web_link.link]);
+        }
+
+        fn visit_embed(&mut self, embed: &'ast Embed, _: &mut VisitContext) {
+            self.0.push(embed.target);
+            self.0.extend(embed.alt);
+        }
+
+        fn visit_image(&mut self, image: &'ast Image, _: &mut VisitContext) {
+            self.0.extend([image.alt, image.target]);
+        }
+
+        fn visit_url(&mut self, url: &'ast Url, _: &mut VisitContext) {
+            self.0.push(url.url);
+        }
+
+        fn visit_html(&mut self, html: &'ast Html, context: &mut VisitContext) {
+            self.0.push(html.tag);
+            visit::walk_nodes(self, &html.nodes, context);
+        }
+
+        fn visit_inline_field(
+            &mut self,
+            key: &'ast LinkPart,
+            value: &'ast [Node],
+            context: &mut VisitContext,
+        ) {
+            self.0.push(key.span());
+            visit::walk_nodes(self, value, context);
+        }
+    }
+
+    // Overwrites everything in the source the renderer has to generate from
+    // the tree. Only the spans it reads, the frontmatter and whitespace are
+    // left as they were.
+    fn scramble(md_file: &mut ParsedMDFile) {
+        let mut kept: ReadFromSource = ReadFromSource(Vec::new());
+        kept.visit_file(md_file, &mut VisitContext::new());
+        let mut kept: Vec<(usize, usize)> =
+            kept.0.iter().map(|span| (span.start, span.end)).collect();
+        kept.extend(
+            md_file
+                .get_frontmatter()
+                .map(|frontmatter| (frontmatter.start, frontmatter.end)),
+        );
+        md_file.source = md_file
+            .source
+            .char_indices()
+            .map(|(at, c)| {
+                if c.is_whitespace()
+                    || c == '\u{feff}'
+                    || kept.iter().any(|(start, end)| *start <= at && at < *end)
+                {
+                    c.to_string()
+                } else {
+                    "X".repeat(c.len_utf8())
+                }
+            })
+            .collect();
+    }
+
+    #[test]
+    fn render_from_tree_test() {
+        let path: PathBuf = PathBuf::from("round.md");
+        let mut md_file = parse_md_file_wrapper(ROUND_TRIP.to_string(), path).unwrap();
+        scramble(&mut md_file);
+        assert_ne!(md_file.source, ROUND_TRIP);
+        assert_eq!(md_file.render(), ROUND_TRIP);
+    }
+
+    #[test]
+    fn wrap_in_link_test() {
+        let contents: &str = "# Head\n**bold Alan Turing here**\n| Alan Turing | b |\n| --- | --- |\n| c | d |\nAlan Turing\n";
+        let path: PathBuf = PathBuf::from("wrap.md");
+        let mut md_file = parse_md_file_wrapper(contents.to_string(), path).unwrap();
+
+        let start: usize = contents.find("Alan").unwrap();
+        let edits = md_file.wrap_in_link(start, start + 11, "Turing").unwrap();
+        assert_eq!(edits.len(), 1);
+        let start: usize = md_file.source.find("| Alan").unwrap() + 2;
+        md_file.wrap_in_link(start, start + 4, "Alan").unwrap();
+        let start: usize = md_file.source.rfind("Alan").unwrap();
+        md_file.wrap_in_link(start, start + 11, "Turing").unwrap();
+
+        let expected: &str = "# Head\n**bold [[Turing|Alan Turing]] here**\n| [[Alan\\|Alan]] Turing | b |\n| --- | --- |\n| c | d |\n[[Turing|Alan Turing]]\n";
+        assert_eq!(md_file.source, expected);
+        assert_eq!(md_file.render(), expected);
+        let targets: Vec<&str> = md_file
+            .get_wikilinks()
+            .iter()
+            .map(|link| &expected[link.target.start..link.target.end])
+            .collect();
+        assert_eq!(targets, vec!["Turing", "Alan", "Turing"]);
+
+        // the edited tree matches a fresh parse of the new source
+        let reparsed = parse_md_file_wrapper(expected.to_string(), md_file.path.clone()).unwrap();
+        assert_eq!(
+            f!("{:?}", reparsed.get_nodes()),
+            f!("{:?}", md_file.get_nodes())
+        );
+
+        let start: usize = md_file.source.find("here").unwrap();
+        assert!(md_file.wrap_in_link(start, start + 4, "a|b").is_err());
+        assert!(md_file.wrap_in_link(0, 1, "x").is_err());
+    }
+
+    #[test]
+    fn incremental_parse_test() {
+        let contents: &str = "---\ntags: a\n---\n# Title\nAlan Turing\n\nmore text\n| a | b |\n| --- | --- |\n| c | d |\nstray ` here\nlast line\n";
+        let path: PathBuf = PathBuf::from("incremental.md");
+        let previous = parse_md_file_recovering(contents.to_string(), path.clone()).unwrap();
+        let edits: Vec<TextEdit> = vec![
+            // inside the string block, after its first lines
+            TextEdit::insert(contents.find("more").unwrap(), "even "),
+            // a new table delimiter row turns the line above into a header
+            TextEdit::new(
+                contents.find("more").unwrap(),
+                contents.find("more").unwrap() + 9,
+                "|x|\n|-|".to_string(),
+            ),
+            // closes the stray backtick, so the unparsed line parses
+            TextEdit::insert(contents.find("last").unwrap(), "`"),
+            TextEdit::delete(
+                contents.find("| c").unwrap(),
+                contents.find("stray").unwrap(),
+            ),
+            TextEdit::insert(contents.len(), "appended"),
+            TextEdit::new(4, 11, "tags: b, c\n".to_string()),
+        ];
+        for edit in edits {
+            let edited: String =
+                crate::utils::apply_edits(contents, std::slice::from_ref(&edit)).unwrap();
+            let reparsed = previous.reparse(&edit).unwrap();
+            let full = parse_md_file_recovering(edited.clone(), path.clone()).unwrap();
+            assert_eq!(reparsed.source, edited);
+            assert_eq!(reparsed.block_spans, full.block_spans);
+            assert_eq!(f!("{:?}", reparsed.blocks), f!("{:?}", full.blocks));
+            assert_eq!(
+                f!("{:?}", reparsed.get_frontmatter_tags()),
+                f!("{:?}", full.get_frontmatter_tags())
+            );
+        }
+    }
+
+    mod proptests {
+        use super::*;
+        use proptest::prelude::*;
+
+        const FRAGMENTS: &[&str] = &[
+            "word",
+            " ",
+            "  ",
+            "\t",
+            "\n",
+            "\n\n",
+            "\r\n",
+            "\r",
+            "\u{feff}",
+            "# ",
+            "## ",
+            "- ",
+            "  - ",
+            "1. ",
+            "- [ ] ",
+            "> ",
+            "> [!tip] ",
+            "**b**",
+            "*i*",
+            "_u_",
+            "__uu__",
+            "==h==",
+            "~~s~~",
+            "[[a]]",
+            "[[a#b|c]]",
+            "![[e|20]]",
+            "[t](u)",
+            "`c`",
+            "$m$",
+            "#tag",
+            "<i>x</i>",
+            "%%c%%",
+            "http://e.com/x",
+            "[^n]",
+            "\n```\ncode\n```\n",
+            "$",
+            "$$",
+            "`",
+            "[",
+            "]",
+            ")",
+            "%%",
+            "<!--",
+            "-->",
+            "---",
+            "\n| a | b |\n| - | - |\n",
+            "\n[^n]: note\n",
+            "\n<div>\n",
+            "\nkey:: ",
+            "[k:: v]",
+            "(k:: [[a]])",
+            "\n~~~\n",
+            "\n  ````\n",
+            "\\(",
+            "\\)",
+            "\\[",
+            "\\]",
+            "\\begin{a}",
+            "\\end{a}",
+            "\n\n",
+        ];
+
+        fn note() -> impl Strategy<Value = String> {
+            prop::collection::vec(prop::sample::select(FRAGMENTS), 0..24)
+                .prop_map(|fragments| fragments.concat())
+        }
+
+        proptest! {
+            #[test]
+            fn render_parse_is_identity(contents in note()) {
+                let path: PathBuf = PathBuf::from("prop.md");
+                if let Ok(md_file) = parse_md_file_wrapper(contents.clone(), path.clone()) {
+                    prop_assert_eq!(md_file.render(), contents.clone());
+                }
+                let md_file = parse_md_file_recovering(contents.clone(), path).unwrap();
+                prop_assert_eq!(md_file.render(), contents);
+            }
+
+            #[test]
+            fn render_reads_only_spans_from_source(contents in note()) {
+                let path: PathBuf = PathBuf::from("prop.md");
+                let mut md_file = parse_md_file_recovering(contents.clone(), path).unwrap();
+                scramble(&mut md_file);
+                prop_assert_eq!(md_file.render(), contents);
+            }
+
+            #[test]
+            fn crlf_parses_like_lf(contents in note()) {
+                let path: PathBuf = PathBuf::from("prop.md");
+                let contents: String = contents.replace('\r', "").trim_start_matches('\u{feff}').to_string();
+                let lf = parse_md_file_recovering(contents.clone(), path.clone()).unwrap();
+                let crlf_contents: String = f!("\u{feff}{}", contents.replace('\n', "\r\n"));
+                let crlf = parse_md_file_recovering(crlf_contents.clone(), path).unwrap();
+                prop_assert_eq!(crlf.render(), crlf_contents.clone());
+                prop_assert_eq!(crlf.blocks.len(), lf.blocks.len());
+                prop_assert_eq!(crlf.get_nodes().len(), lf.get_nodes().len());
+                for (crlf_node, lf_node) in crlf.get_nodes().into_iter().zip(lf.get_nodes()) {
+                    prop_assert_eq!(std::mem::discriminant(&crlf_node.node), std::mem::discriminant(&lf_node.node));
+                    if let (NodeEnum::Text, NodeEnum::Text) = (&crlf_node.node, &lf_node.node) {
+                        prop_assert_eq!(&normalize::normalize_line_endings(crlf_node.text(&crlf.source)), lf_node.text(&lf.source));
+                    }
+                }
+            }
+
+            #[test]
+            fn reparse_matches_full_parse(
+                contents in note(),
+                start in 0.0..1.0f64,
+                removed in 0usize..12,
+                inserted in prop::sample::select(FRAGMENTS),
+                empty in any::<bool>(),
+            ) {
+                let path: PathBuf = PathBuf::from("prop.md");
+                let mut start: usize = (contents.len() as f64 * start) as usize;
+                let mut end: usize = (start + removed).min(contents.len());
+                // the byte order mark is the only fragment longer than a byte
+                while !contents.is_char_boundary(start) {
+                    start -= 1;
+                }
+                while !contents.is_char_boundary(end) {
+                    end += 1;
+                }
+                let edit: TextEdit = TextEdit::new(start, end, if empty { "" } else { inserted }.to_string());
+                let edited: String = crate::utils::apply_edits(&contents, std::slice::from_ref(&edit)).unwrap();
+
+                let previous = parse_md_file_recovering(contents, path.clone()).unwrap();
+                let reparsed = previous.reparse(&edit).unwrap();
+                let full = parse_md_file_recovering(edited.clone(), path).unwrap();
+                prop_assert_eq!(&reparsed.source, &edited);
+                prop_assert_eq!(&reparsed.block_spans, &full.block_spans);
+                prop_assert_eq!(f!("{:?}", reparsed.blocks), f!("{:?}", full.blocks));
+            }
+        }
+    }
+}
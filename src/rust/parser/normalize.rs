@@ -0,0 +1,103 @@
+use super::{Block, ParseDiagnostic, ParsedMDFile, Shift};
+
+const BOM: char = '\u{feff}';
+
+// The text the grammar sees: no byte order mark and \n line endings, a lone \r
+// is read as \n. Offsets into it are moved back onto the original bytes once
+// parsing is done, so everything reported points into the text as given.
+pub(crate) struct Normalized {
+    pub(crate) text: String,
+    bom: usize,
+    // offsets in text before which the \r of a \r\n was dropped
+    dropped: Vec<usize>,
+}
+
+impl Normalized {
+    pub(crate) fn new(source: &str) -> Self {
+        let body: &str = source.strip_prefix(BOM).unwrap_or(source);
+        let (text, dropped): (String, Vec<usize>) = line_endings(body);
+        Normalized {
+            text,
+            bom: source.len() - body.len(),
+            dropped,
+        }
+    }
+
+    // whether offsets into text are already offsets into the source
+    pub(crate) fn same_offsets(&self) -> bool {
+        self.bom == 0 && self.dropped.is_empty()
+    }
+
+    pub(crate) fn shift(&self) -> Shift<'_> {
+        Shift::restore(self.bom, &self.dropped)
+    }
+
+    // moves the offsets of a file parsed from text onto its source
+    pub(crate) fn restore(&self, file: &mut ParsedMDFile) {
+        if self.same_offsets() {
+            return;
+        }
+        let shift: Shift = self.shift();
+        for block in &mut file.blocks {
+            block.shift(shift);
+            if let Block::Unparsed(unparsed) = block {
+                unparsed.diagnostic = self.restore_diagnostic(&file.source, &unparsed.diagnostic);
+            }
+        }
+        for (start, end) in &mut file.block_spans {
+            shift.start(start);
+            shift.end(end);
+        }
+        if let Some(frontmatter) = &mut file.frontmatter {
+            shift.start(&mut frontmatter.start);
+            shift.end(&mut frontmatter.end);
+            if let Some(diagnostic) = &frontmatter.diagnostic {
+                frontmatter.diagnostic = Some(self.restore_diagnostic(&file.source, diagnostic));
+            }
+        }
+    }
+
+    // the diagnostic for the same position in source
+    pub(crate) fn restore_diagnostic(
+        &self,
+        source: &str,
+        diagnostic: &ParseDiagnostic,
+    ) -> ParseDiagnostic {
+        let mut offset: usize = diagnostic.byte_offset;
+        self.shift().start(&mut offset);
+        ParseDiagnostic::new(
+            source,
+            offset,
+            diagnostic.expected.clone(),
+            diagnostic.message.clone(),
+            diagnostic.severity,
+        )
+    }
+}
+
+// whether the grammar sees text any different from how it is written
+pub(crate) fn needs_normalizing(text: &str) -> bool {
+    text.starts_with(BOM) || text.contains('\r')
+}
+
+// text with \r\n and a lone \r read as \n
+pub(crate) fn normalize_line_endings(text: &str) -> String {
+    line_endings(text).0
+}
+
+fn line_endings(text: &str) -> (String, Vec<usize>) {
+    let mut normalized: String = String::with_capacity(text.len());
+    let mut dropped: Vec<usize> = Vec::new();
+    let mut rest: &str = text;
+    while let Some(i) = rest.find('\r') {
+        normalized.push_str(&rest[..i]);
+        if rest[i + 1..].starts_with('\n') {
+            dropped.push(normalized.len());
+        } else {
+            normalized.push('\n');
+        }
+        rest = &rest[i + 1..];
+    }
+    normalized.push_str(rest);
+    (normalized, dropped)
+}
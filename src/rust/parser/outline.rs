@@ -0,0 +1,255 @@
+use super::visit::walk_block;
+use super::{Block, Heading, Line, Node, ParsedMDFile, VisitContext, Visitor};
+
+// a tab counts as this many spaces when comparing list indents
+const TAB_WIDTH: usize = 4;
+
+// what a section holds in document order, lines of string blocks are listed one by one
+#[derive(Debug, Clone, Copy)]
+pub enum SectionItem<'a> {
+    Line(&'a Line),
+    Block(&'a Block),
+}
+
+impl<'a> SectionItem<'a> {
+    pub fn get_nodes(&self) -> Vec<&'a Node> {
+        match self {
+            SectionItem::Line(line) => line.get_nodes(),
+            SectionItem::Block(block) => block.get_nodes(),
+        }
+    }
+}
+
+// a list line and the lines indented below it
+#[derive(Debug, Clone)]
+pub struct ListItem<'a> {
+    pub line: &'a Line,
+    pub children: Vec<ListItem<'a>>,
+}
+
+impl<'a> ListItem<'a> {
+    // the list lines from this item down to the one holding node
+    fn path_to(&self, node: &Node) -> Option<Vec<&'a Line>> {
+        if contains(self.line.get_nodes(), node) {
+            return Some(vec![self.line]);
+        }
+        self.children.iter().find_map(|child| {
+            let mut path: Vec<&'a Line> = child.path_to(node)?;
+            path.insert(0, self.line);
+            Some(path)
+        })
+    }
+}
+
+// the root section has no heading and level 0, it holds everything before the first heading
+#[derive(Debug, Clone)]
+pub struct Section<'a> {
+    pub heading: Option<&'a Heading>,
+    pub level: u32,
+    pub items: Vec<SectionItem<'a>>,
+    pub lists: Vec<ListItem<'a>>,
+    pub children: Vec<Section<'a>>,
+}
+
+impl<'a> Section<'a> {
+    fn new(heading: Option<&'a Heading>, level: u32) -> Self {
+        Section {
+            heading,
+            level,
+            items: Vec::new(),
+            lists: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    // nodes of the heading and items, not those of subsections
+    pub fn get_nodes(&self) -> Vec<&'a Node> {
+        let heading_nodes = self
+            .heading
+            .into_iter()
+            .flat_map(|heading| heading.nodes.iter().flat_map(|node| node.get_nodes()));
+        heading_nodes
+            .chain(self.items.iter().flat_map(|item| item.get_nodes()))
+            .collect()
+    }
+
+    fn heading_path(&self, node: &Node) -> Option<Vec<&'a Heading>> {
+        if contains(self.get_nodes(), node) {
+            return Some(self.heading.into_iter().collect());
+        }
+        self.children.iter().find_map(|child| {
+            let mut path: Vec<&'a Heading> = child.heading_path(node)?;
+            if let Some(heading) = self.heading {
+                path.insert(0, heading);
+            }
+            Some(path)
+        })
+    }
+
+    fn list_path(&self, node: &Node) -> Option<Vec<&'a Line>> {
+        self.lists
+            .iter()
+            .find_map(|item| item.path_to(node))
+            .or_else(|| self.children.iter().find_map(|child| child.list_path(node)))
+    }
+
+    // nests the list lines of items by indent, any other non blank item ends a list
+    fn build_lists(&mut self) {
+        let mut roots: Vec<ListItem<'a>> = Vec::new();
+        // open items with their indent width, outermost first
+        let mut stack: Vec<(usize, ListItem<'a>)> = Vec::new();
+        for item in &self.items {
+            let line: &'a Line = match item {
+                SectionItem::Line(line) if is_blank(line) => continue,
+                SectionItem::Line(line) => line,
+                SectionItem::Block(_) => {
+                    close_items(&mut stack, &mut roots, 0);
+                    continue;
+                }
+            };
+            let indent: usize = match line.get_indent() {
+                Some(indent) => indent.width,
+                None => {
+                    close_items(&mut stack, &mut roots, 0);
+                    continue;
+                }
+            };
+            close_items(&mut stack, &mut roots, indent);
+            stack.push((
+                indent,
+                ListItem {
+                    line,
+                    children: Vec::new(),
+                },
+            ));
+        }
+        close_items(&mut stack, &mut roots, 0);
+        self.lists = roots;
+    }
+}
+
+// pops items indented at least min_indent, attaching each to its parent or to roots
+fn close_items<'a>(
+    stack: &mut Vec<(usize, ListItem<'a>)>,
+    roots: &mut Vec<ListItem<'a>>,
+    min_indent: usize,
+) {
+    while stack
+        .last()
+        .is_some_and(|(indent, _)| *indent >= min_indent)
+    {
+        let (_, item) = stack.pop().expect("checked above");
+        match stack.last_mut() {
+            Some((_, parent)) => parent.children.push(item),
+            None => roots.push(item),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Outline<'a> {
+    pub root: Section<'a>,
+}
+
+impl<'a> Outline<'a> {
+    pub fn new(file: &'a ParsedMDFile) -> Self {
+        let mut sections: OpenSections<'a> = OpenSections {
+            stack: vec![Section::new(None, 0)],
+        };
+        sections.visit_file(file, &mut VisitContext::new());
+        let mut stack: Vec<Section<'a>> = sections.stack;
+        close_sections(&mut stack, 1);
+        let mut root: Section<'a> = stack.pop().expect("the root section is never closed");
+        build_all_lists(&mut root);
+        Outline { root }
+    }
+
+    // headings the node sits under, outermost first, including the one it is part of
+    pub fn heading_path(&self, node: &Node) -> Vec<&'a Heading> {
+        self.root.heading_path(node).unwrap_or_default()
+    }
+
+    // list lines the node sits under, outermost first, ending with the line holding it
+    pub fn list_path(&self, node: &Node) -> Vec<&'a Line> {
+        self.root.list_path(node).unwrap_or_default()
+    }
+
+    // every section below the root in document order
+    pub fn get_sections(&self) -> Vec<&Section<'a>> {
+        let mut sections: Vec<&Section<'a>> = Vec::new();
+        let mut pending: Vec<&Section<'a>> = self.root.children.iter().rev().collect();
+        while let Some(section) = pending.pop() {
+            sections.push(section);
+            pending.extend(section.children.iter().rev());
+        }
+        sections
+    }
+}
+
+// sorts the top level blocks and the lines of string blocks into sections by heading
+struct OpenSections<'a> {
+    // the innermost open section is last
+    stack: Vec<Section<'a>>,
+}
+
+impl<'a> OpenSections<'a> {
+    fn push_item(&mut self, item: SectionItem<'a>) {
+        if let Some(section) = self.stack.last_mut() {
+            section.items.push(item);
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for OpenSections<'a> {
+    // only the lines of string blocks are items of their own, other blocks are not entered
+    fn visit_block(&mut self, block: &'a Block, context: &mut VisitContext) {
+        match block {
+            Block::String(_) => walk_block(self, block, context),
+            _ => self.push_item(SectionItem::Block(block)),
+        }
+    }
+
+    fn visit_line(&mut self, line: &'a Line, _context: &mut VisitContext) {
+        match line {
+            Line::Heading(heading) => {
+                close_sections(&mut self.stack, heading.level);
+                self.stack.push(Section::new(Some(heading), heading.level));
+            }
+            _ => self.push_item(SectionItem::Line(line)),
+        }
+    }
+}
+
+// closes sections of level or deeper, the root is never closed
+fn close_sections(stack: &mut Vec<Section<'_>>, level: u32) {
+    while stack.len() > 1 && stack.last().is_some_and(|section| section.level >= level) {
+        let section = stack.pop().expect("checked above");
+        if let Some(parent) = stack.last_mut() {
+            parent.children.push(section);
+        }
+    }
+}
+
+fn build_all_lists(section: &mut Section<'_>) {
+    section.build_lists();
+    for child in &mut section.children {
+        build_all_lists(child);
+    }
+}
+
+fn contains(nodes: Vec<&Node>, node: &Node) -> bool {
+    nodes
+        .into_iter()
+        .any(|candidate| std::ptr::eq(candidate, node))
+}
+
+fn is_blank(line: &Line) -> bool {
+    matches!(line, Line::StringLine(string_line) if string_line.nodes.is_empty())
+}
+
+pub(super) fn indent_width(indent: &str) -> usize {
+    indent
+        .chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
@@ -0,0 +1,339 @@
+use super::visit::{
+    walk_block_quote, walk_callout, walk_file, walk_line, walk_lines, walk_node, walk_nodes,
+};
+use super::{
+    BlockQuote, BulletedList, Callout, CodeBlock, Embed, FootnoteDefinition, Heading, Html, Image,
+    LatexBlock, Line, LinkPart, Node, NodeEnum, NumberedList, ParsedMDFile, Span, Table, TableCell,
+    Task, UnparsedBlock, Url, VisitContext, Visitor, WebLink, WikiLink,
+};
+use crate::prelude::*;
+
+// Writes a parsed file back as markdown. Markers, delimiters and the other
+// syntax are generated from the tree, the text of leaf nodes and link parts,
+// code, the frontmatter and the whitespace between elements are read from the
+// source by their spans. An unchanged file renders to exactly its source, an
+// edited tree renders to the markdown of the edit.
+pub fn render(file: &ParsedMDFile) -> String {
+    let mut renderer: Renderer = Renderer {
+        source: &file.source,
+        output: String::with_capacity(file.source.len()),
+        pos: 0,
+        quote_depth: 0,
+    };
+    renderer.visit_file(file, &mut VisitContext::new());
+    renderer.output
+}
+
+struct Renderer<'a> {
+    source: &'a str,
+    output: String,
+    // everything in source before pos has been written
+    pos: usize,
+    // how many > open each line
+    quote_depth: usize,
+}
+
+impl Renderer<'_> {
+    // Copies the source up to end. Between elements this is whitespace, any
+    // syntax the tree does not account for is kept as it was.
+    fn copy_to(&mut self, end: usize) {
+        let mut end: usize = end.min(self.source.len());
+        while !self.source.is_char_boundary(end) {
+            end += 1;
+        }
+        if end > self.pos {
+            self.output.push_str(&self.source[self.pos..end]);
+            self.pos = end;
+        }
+    }
+
+    // a token that starts at start, such as an opening delimiter
+    fn token_at(&mut self, start: usize, token: &str) {
+        self.copy_to(start);
+        self.output.push_str(token);
+        self.pos = self.pos.max(start + token.len());
+    }
+
+    // a token that ends at end, such as a closing delimiter
+    fn token_before(&mut self, end: usize, token: &str) {
+        self.copy_to(end.saturating_sub(token.len()));
+        self.output.push_str(token);
+        self.pos = self.pos.max(end);
+    }
+
+    // markers separated by whitespace, such as the > of a quote or the - [ ] of a task
+    fn markers<'t>(&mut self, markers: impl IntoIterator<Item = &'t str>) {
+        for marker in markers {
+            let rest: &str = self.source.get(self.pos..).unwrap_or_default();
+            let skipped: usize = rest
+                .find(|c: char| !c.is_whitespace() && c != '\u{feff}')
+                .unwrap_or(rest.len());
+            self.copy_to(self.pos + skipped);
+            self.token_at(self.pos, marker);
+        }
+    }
+
+    // text the tree points at, such as a link part
+    fn part(&mut self, part: Span) {
+        self.copy_to(part.start);
+        self.output.push_str(part.text(self.source));
+        self.pos = self.pos.max(part.end);
+    }
+
+    fn quote_markers(&mut self) {
+        self.markers(std::iter::repeat_n(">", self.quote_depth));
+    }
+
+    // the separator in front of to, a longer gap is made up with # as in [[a##b]]
+    fn fill(&mut self, to: usize, separator: &str) {
+        let gap: usize = to.saturating_sub(self.pos);
+        self.output
+            .push_str(&"#".repeat(gap.saturating_sub(separator.len())));
+        self.output.push_str(separator);
+        self.pos = self.pos.max(to);
+    }
+}
+
+// the opening and closing syntax around the children of a node
+fn delimiters(node: &NodeEnum, source: &str) -> Option<(String, String)> {
+    let (open, close): (String, String) = match node {
+        NodeEnum::BoldItalic(_) => ("***".to_string(), "***".to_string()),
+        NodeEnum::Bold(_, delimiter) => (f!("{0}{0}", delimiter), f!("{0}{0}", delimiter)),
+        NodeEnum::Italic(_, delimiter) => (delimiter.to_string(), delimiter.to_string()),
+        NodeEnum::Highlight(_) => ("==".to_string(), "==".to_string()),
+        NodeEnum::Strikethrough(_) => ("~~".to_string(), "~~".to_string()),
+        // the end tag repeats the name the way the opening tag writes it
+        NodeEnum::Html(html) if html.element => (
+            html.tag.text(source).to_string(),
+            f!("</{}>", html.name.text(source)),
+        ),
+        NodeEnum::InlineField(field) => match field.bracket {
+            Some(bracket) => (
+                bracket.to_string(),
+                if bracket == '(' { ")" } else { "]" }.to_string(),
+            ),
+            None => return None,
+        },
+        _ => return None,
+    };
+    Some((open, close))
+}
+
+impl<'a> Visitor<'a> for Renderer<'a> {
+    fn visit_file(&mut self, file: &'a ParsedMDFile, context: &mut VisitContext) {
+        if let Some(frontmatter) = &file.frontmatter {
+            self.markers(["---"]);
+            self.copy_to(frontmatter.end);
+            self.markers(["---"]);
+        }
+        walk_file(self, file, context);
+        self.copy_to(self.source.len());
+    }
+
+    fn visit_callout(&mut self, callout: &'a Callout, context: &mut VisitContext) {
+        let fold: &str = match (callout.foldable, callout.collapsed) {
+            (false, _) => "",
+            (true, false) => "+",
+            (true, true) => "-",
+        };
+        self.markers([">", &f!("[!{}]{}", callout.kind.text(self.source), fold)]);
+        self.quote_depth += 1;
+        walk_callout(self, callout, context);
+        self.quote_depth -= 1;
+    }
+
+    fn visit_block_quote(&mut self, block_quote: &'a BlockQuote, context: &mut VisitContext) {
+        self.quote_depth += 1;
+        walk_block_quote(self, block_quote, context);
+        self.quote_depth -= 1;
+    }
+
+    fn visit_latex_block(&mut self, latex_block: &'a LatexBlock, _context: &mut VisitContext) {
+        self.part(Span::new(latex_block.start, latex_block.end));
+    }
+
+    fn visit_code_block(&mut self, code_block: &'a CodeBlock, _context: &mut VisitContext) {
+        self.part(code_block.fence);
+        if let Some(code_type) = code_block.code_type {
+            self.part(code_type);
+        }
+        self.part(code_block.code);
+        self.part(code_block.closing_fence);
+    }
+
+    fn visit_table(&mut self, table: &'a Table, context: &mut VisitContext) {
+        let rows = std::iter::once(&table.header).chain(table.rows.iter());
+        for (index, row) in rows.enumerate() {
+            let (leading, trailing): (bool, bool) =
+                table.pipes.get(index).copied().unwrap_or((true, true));
+            self.quote_markers();
+            if leading {
+                self.markers(["|"]);
+            }
+            for (column, cell) in row.iter().enumerate() {
+                if column > 0 {
+                    self.markers(["|"]);
+                }
+                self.visit_table_cell(cell, context);
+            }
+            if trailing {
+                self.markers(["|"]);
+            }
+            if index == 0 {
+                self.quote_markers();
+                self.markers([table.delimiter_row.text(self.source)]);
+            }
+        }
+    }
+
+    fn visit_table_cell(&mut self, cell: &'a TableCell, context: &mut VisitContext) {
+        walk_nodes(self, &cell.nodes, context);
+        self.copy_to(cell.end);
+    }
+
+    fn visit_footnote_definition(
+        &mut self,
+        footnote: &'a FootnoteDefinition,
+        context: &mut VisitContext,
+    ) {
+        self.markers([f!("[^{}]:", footnote.label.text(self.source)).as_str()]);
+        walk_lines(self, &footnote.lines, context);
+    }
+
+    fn visit_unparsed_block(&mut self, unparsed: &'a UnparsedBlock, _context: &mut VisitContext) {
+        self.part(Span::new(unparsed.start, unparsed.end));
+    }
+
+    fn visit_line(&mut self, line: &'a Line, context: &mut VisitContext) {
+        self.quote_markers();
+        walk_line(self, line, context);
+        if let Some(block_id) = line.get_block_id() {
+            self.token_before(block_id.start, "^");
+            self.part(block_id.span());
+        }
+    }
+
+    fn visit_task(&mut self, task: &'a Task, context: &mut VisitContext) {
+        self.markers([task.marker.text(self.source), &f!("[{}]", task.status_char)]);
+        walk_nodes(self, &task.nodes, context);
+    }
+
+    fn visit_numbered_list(&mut self, numbered_list: &'a NumberedList, context: &mut VisitContext) {
+        self.markers([f!(
+            "{:0digits$}.",
+            numbered_list.number,
+            digits = numbered_list.digits
+        )
+        .as_str()]);
+        walk_nodes(self, &numbered_list.nodes, context);
+    }
+
+    fn visit_bulleted_list(&mut self, bulleted_list: &'a BulletedList, context: &mut VisitContext) {
+        self.markers(["-"]);
+        walk_nodes(self, &bulleted_list.nodes, context);
+    }
+
+    fn visit_heading(&mut self, heading: &'a Heading, context: &mut VisitContext) {
+        self.markers(["#".repeat(heading.level as usize).as_str()]);
+        walk_nodes(self, &heading.nodes, context);
+    }
+
+    fn visit_node(&mut self, node: &'a Node, context: &mut VisitContext) {
+        match delimiters(&node.node, self.source) {
+            Some((open, close)) => {
+                self.token_at(node.start, &open);
+                walk_node(self, node, context);
+                self.token_before(node.end, &close);
+            }
+            None => walk_node(self, node, context),
+        }
+        // text leaves are their source
+        self.copy_to(node.end);
+    }
+
+    // The separators between the parts are filled in from their spans, a
+    // link added to the tree has parts of no width and gets one of each.
+    fn visit_wikilink(&mut self, link: &'a WikiLink, context: &mut VisitContext) {
+        let (start, end): (usize, usize) = context.span().unwrap_or((self.pos, self.pos));
+        self.copy_to(start);
+        let pipe: &str = if link.escaped_pipe { "\\|" } else { "|" };
+        let mut parts: Vec<(&LinkPart, &str)> = vec![(&link.target, "[[")];
+        parts.extend(link.heading_path.iter().map(|heading| (heading, "#")));
+        parts.extend(link.block_id.iter().map(|block_id| (block_id, "#^")));
+        parts.extend(link.display.iter().map(|display| (display, pipe)));
+        parts.sort_by_key(|(part, _)| part.start);
+        for (part, separator) in parts {
+            self.fill(part.start, separator);
+            self.part(part.span());
+        }
+        self.fill(end, "]]");
+    }
+
+    fn visit_web_link(&mut self, web_link: &'a WebLink, context: &mut VisitContext) {
+        if let Some((start, end)) = context.span() {
+            self.token_at(start, "[");
+            self.part(web_link.name);
+            self.token_at(self.pos, "](");
+            self.part(web_link.link);
+            self.token_before(end, ")");
+        }
+    }
+
+    fn visit_embed(&mut self, embed: &'a Embed, context: &mut VisitContext) {
+        if let Some((start, end)) = context.span() {
+            self.token_at(start, "![[");
+            self.part(embed.target);
+            match (embed.size, embed.alt) {
+                (Some(size), _) => self.token_at(self.pos, &f!("|{}", size)),
+                (None, Some(alt)) => {
+                    self.token_at(self.pos, "|");
+                    self.part(alt);
+                }
+                (None, None) => {}
+            }
+            self.token_before(end, "]]");
+        }
+    }
+
+    fn visit_image(&mut self, image: &'a Image, context: &mut VisitContext) {
+        if let Some((start, end)) = context.span() {
+            self.token_at(start, "![");
+            self.part(image.alt);
+            if let Some(size) = image.size {
+                self.token_at(self.pos, &f!("|{}", size));
+            }
+            self.token_at(self.pos, "](");
+            self.part(image.target);
+            self.token_before(end, ")");
+        }
+    }
+
+    fn visit_url(&mut self, url: &'a Url, context: &mut VisitContext) {
+        match (url.autolink, context.span()) {
+            (true, Some((start, end))) => {
+                self.token_at(start, "<");
+                self.part(url.url);
+                self.token_before(end, ">");
+            }
+            _ => self.part(url.url),
+        }
+    }
+
+    fn visit_html(&mut self, html: &'a Html, context: &mut VisitContext) {
+        match html.element {
+            false => self.part(html.tag),
+            true => walk_nodes(self, &html.nodes, context),
+        }
+    }
+
+    fn visit_inline_field(
+        &mut self,
+        key: &'a LinkPart,
+        value: &'a [Node],
+        context: &mut VisitContext,
+    ) {
+        self.part(key.span());
+        self.markers(["::"]);
+        walk_nodes(self, value, context);
+    }
+}
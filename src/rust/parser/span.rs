@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+use super::Shift;
+
+// byte offsets into the source, end exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    // what the span covers in the source it was parsed from, the newline the
+    // parser adds to a file without one is not part of it
+    pub fn text<'s>(&self, source: &'s str) -> &'s str {
+        let end: usize = self.end.min(source.len());
+        source.get(self.start.min(end)..end).unwrap_or_default()
+    }
+
+    pub(crate) fn shift(&mut self, shift: Shift) {
+        shift.span(&mut self.start, &mut self.end);
+    }
+}
+
+// Where a byte offset falls in the source. line and column count from 1 like a
+// ParseDiagnostic, the column in characters. utf16 is the offset in UTF-16 code
+// units, what JavaScript strings and CodeMirror positions count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub byte: usize,
+    pub line: usize,
+    pub column: usize,
+    pub utf16: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub start: Position,
+    pub end: Position,
+}
+
+// Where every line of a source starts, in bytes and in UTF-16 code units, so a
+// position is found without scanning the text before it. Lines end at \n, a
+// \r before it belongs to the line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    // empty for an ascii source, where both are the same
+    utf16_line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts: Vec<usize> = vec![0];
+        let mut utf16_line_starts: Vec<usize> = vec![0];
+        let mut utf16: usize = 0;
+        for (i, _) in source.match_indices('\n') {
+            let start: usize = i + 1;
+            utf16 += utf16_len(&source[line_starts[line_starts.len() - 1]..start]);
+            line_starts.push(start);
+            utf16_line_starts.push(utf16);
+        }
+        if source.is_ascii() {
+            utf16_line_starts = Vec::new();
+        }
+        line_starts.shrink_to_fit();
+        utf16_line_starts.shrink_to_fit();
+        LineIndex {
+            line_starts,
+            utf16_line_starts,
+        }
+    }
+
+    // offsets past the end are read as the end, inside a character as its start
+    pub fn position(&self, source: &str, byte: usize) -> Position {
+        let mut byte: usize = byte.min(source.len());
+        while !source.is_char_boundary(byte) {
+            byte -= 1;
+        }
+        let line: usize = self.line_starts.partition_point(|start| *start <= byte) - 1;
+        let before: &str = &source[self.line_starts[line]..byte];
+        Position {
+            byte,
+            line: line + 1,
+            column: before.chars().count() + 1,
+            utf16: self.utf16_start(line) + utf16_len(before),
+        }
+    }
+
+    fn utf16_start(&self, line: usize) -> usize {
+        match self.utf16_line_starts.get(line) {
+            Some(start) => *start,
+            None => self.line_starts[line],
+        }
+    }
+
+    pub fn location(&self, source: &str, span: Span) -> Location {
+        Location {
+            start: self.position(source, span.start),
+            end: self.position(source, span.end),
+        }
+    }
+
+    // the byte offset of a UTF-16 offset, one inside a surrogate pair is read as the pair
+    pub fn byte_offset(&self, source: &str, utf16: usize) -> usize {
+        if self.utf16_line_starts.is_empty() {
+            return utf16.min(source.len());
+        }
+        let line: usize = self
+            .utf16_line_starts
+            .partition_point(|start| *start <= utf16)
+            - 1;
+        let start: usize = self.line_starts[line];
+        let mut units: usize = self.utf16_line_starts[line];
+        for (i, c) in source[start..].char_indices() {
+            units += c.len_utf16();
+            if units > utf16 {
+                return start + i;
+            }
+        }
+        source.len()
+    }
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
@@ -0,0 +1,911 @@
+use super::{
+    Block, BlockQuote, BulletedList, Callout, CodeBlock, Embed, FootnoteDefinition, Heading, Html,
+    HtmlBlock, Image, LatexBlock, Line, LinkPart, Node, NodeEnum, NumberedList, ParsedMDFile,
+    Shift, Span, StringBlock, StringLine, Table, TableCell, Task, UnparsedBlock, Url, WebLink,
+    WikiLink,
+};
+use crate::settings::Settings;
+
+// the node variants that wrap other nodes in emphasis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emphasis {
+    BoldItalic,
+    Bold,
+    Italic,
+    Highlight,
+    Strikethrough,
+}
+
+// a heading the element being visited sits under, the span is its whole line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenHeading {
+    pub level: u32,
+    pub span: Span,
+}
+
+// Where the element being visited sits in the note. The walk functions keep it
+// up to date, visitors only read it.
+#[derive(Debug, Clone, Default)]
+pub struct VisitContext {
+    // headings of the top level sections, outermost first
+    headings: Vec<OpenHeading>,
+    // indent widths of the open list lines in the current block
+    list_indents: Vec<usize>,
+    list_depth: usize,
+    emphasis: Vec<Emphasis>,
+    span: Option<(usize, usize)>,
+    // blocks entered, 1 inside a top level block
+    block_depth: usize,
+}
+
+impl VisitContext {
+    pub fn new() -> Self {
+        VisitContext::default()
+    }
+
+    // the headings the element sits under, outermost first, a heading's own
+    // nodes sit under it. Headings inside quotes and callouts start no section.
+    pub fn headings(&self) -> &[OpenHeading] {
+        &self.headings
+    }
+
+    pub fn heading(&self) -> Option<&OpenHeading> {
+        self.headings.last()
+    }
+
+    // 1 on a list line that is not nested in another, 0 outside lists
+    pub fn list_depth(&self) -> usize {
+        self.list_depth
+    }
+
+    // the emphasis around the node being visited, innermost last
+    pub fn emphasis(&self) -> &[Emphasis] {
+        &self.emphasis
+    }
+
+    // start and end of the node being visited, None outside nodes
+    pub fn span(&self) -> Option<(usize, usize)> {
+        self.span
+    }
+
+    fn enter_line(&mut self, line: &Line) {
+        if let Line::Heading(heading) = line {
+            if self.block_depth == 1 {
+                while self
+                    .headings
+                    .last()
+                    .is_some_and(|open| open.level >= heading.level)
+                {
+                    self.headings.pop();
+                }
+                self.headings.push(OpenHeading {
+                    level: heading.level,
+                    span: line.span(),
+                });
+            }
+        }
+        match line.get_indent() {
+            Some(indent) => {
+                let width: usize = indent.width;
+                while self.list_indents.last().is_some_and(|open| *open >= width) {
+                    self.list_indents.pop();
+                }
+                self.list_indents.push(width);
+                self.list_depth = self.list_indents.len();
+            }
+            // a blank line does not end a list
+            None if matches!(line, Line::StringLine(string_line) if string_line.nodes.is_empty()) => {
+                self.list_depth = 0
+            }
+            None => {
+                self.list_indents.clear();
+                self.list_depth = 0;
+            }
+        }
+    }
+
+    // lists do not run on into or out of a nested block
+    fn enter_block(&mut self) -> (Vec<usize>, usize) {
+        self.block_depth += 1;
+        let list_depth: usize = std::mem::take(&mut self.list_depth);
+        (std::mem::take(&mut self.list_indents), list_depth)
+    }
+
+    fn exit_block(&mut self, (list_indents, list_depth): (Vec<usize>, usize)) {
+        self.block_depth -= 1;
+        self.list_indents = list_indents;
+        self.list_depth = list_depth;
+    }
+}
+
+// Walks a parsed note. Every method visits the children of its element by
+// calling the matching walk function, an override that does not call it skips
+// them. Leaves have nothing to walk and do nothing by default, text leaves are
+// given where their text is in the note's source.
+pub trait Visitor<'ast> {
+    fn visit_file(&mut self, file: &'ast ParsedMDFile, context: &mut VisitContext) {
+        walk_file(self, file, context)
+    }
+
+    fn visit_block(&mut self, block: &'ast Block, context: &mut VisitContext) {
+        walk_block(self, block, context)
+    }
+
+    fn visit_callout(&mut self, callout: &'ast Callout, context: &mut VisitContext) {
+        walk_callout(self, callout, context)
+    }
+
+    fn visit_block_quote(&mut self, block_quote: &'ast BlockQuote, context: &mut VisitContext) {
+        walk_block_quote(self, block_quote, context)
+    }
+
+    fn visit_latex_block(&mut self, _latex_block: &'ast LatexBlock, _context: &mut VisitContext) {}
+
+    fn visit_code_block(&mut self, _code_block: &'ast CodeBlock, _context: &mut VisitContext) {}
+
+    fn visit_table(&mut self, table: &'ast Table, context: &mut VisitContext) {
+        walk_table(self, table, context)
+    }
+
+    fn visit_table_cell(&mut self, cell: &'ast TableCell, context: &mut VisitContext) {
+        walk_nodes(self, &cell.nodes, context)
+    }
+
+    fn visit_footnote_definition(
+        &mut self,
+        footnote: &'ast FootnoteDefinition,
+        context: &mut VisitContext,
+    ) {
+        walk_lines(self, &footnote.lines, context)
+    }
+
+    fn visit_html_block(&mut self, html_block: &'ast HtmlBlock, context: &mut VisitContext) {
+        walk_nodes(self, &html_block.nodes, context)
+    }
+
+    fn visit_string_block(&mut self, string_block: &'ast StringBlock, context: &mut VisitContext) {
+        walk_lines(self, &string_block.lines, context)
+    }
+
+    fn visit_unparsed_block(
+        &mut self,
+        _unparsed: &'ast UnparsedBlock,
+        _context: &mut VisitContext,
+    ) {
+    }
+
+    fn visit_line(&mut self, line: &'ast Line, context: &mut VisitContext) {
+        walk_line(self, line, context)
+    }
+
+    fn visit_task(&mut self, task: &'ast Task, context: &mut VisitContext) {
+        walk_nodes(self, &task.nodes, context)
+    }
+
+    fn visit_numbered_list(
+        &mut self,
+        numbered_list: &'ast NumberedList,
+        context: &mut VisitContext,
+    ) {
+        walk_nodes(self, &numbered_list.nodes, context)
+    }
+
+    fn visit_bulleted_list(
+        &mut self,
+        bulleted_list: &'ast BulletedList,
+        context: &mut VisitContext,
+    ) {
+        walk_nodes(self, &bulleted_list.nodes, context)
+    }
+
+    fn visit_heading(&mut self, heading: &'ast Heading, context: &mut VisitContext) {
+        walk_nodes(self, &heading.nodes, context)
+    }
+
+    fn visit_string_line(&mut self, string_line: &'ast StringLine, context: &mut VisitContext) {
+        walk_nodes(self, &string_line.nodes, context)
+    }
+
+    fn visit_node(&mut self, node: &'ast Node, context: &mut VisitContext) {
+        walk_node(self, node, context)
+    }
+
+    fn visit_text(&mut self, _text: Span, _context: &mut VisitContext) {}
+
+    fn visit_emphasis(
+        &mut self,
+        emphasis: Emphasis,
+        nodes: &'ast [Node],
+        context: &mut VisitContext,
+    ) {
+        walk_emphasis(self, emphasis, nodes, context)
+    }
+
+    // [[target]] and [[target|display]]
+    fn visit_wikilink(&mut self, _link: &'ast WikiLink, _context: &mut VisitContext) {}
+
+    fn visit_web_link(&mut self, _web_link: &'ast WebLink, _context: &mut VisitContext) {}
+
+    fn visit_square_bracket(&mut self, _text: Span, _context: &mut VisitContext) {}
+
+    fn visit_inline_code(&mut self, _code: Span, _context: &mut VisitContext) {}
+
+    fn visit_inline_latex(&mut self, _latex: Span, _context: &mut VisitContext) {}
+
+    fn visit_inline_code_block(&mut self, _code: Span, _context: &mut VisitContext) {}
+
+    fn visit_inline_latex_block(&mut self, _latex: Span, _context: &mut VisitContext) {}
+
+    fn visit_embed(&mut self, _embed: &'ast Embed, _context: &mut VisitContext) {}
+
+    fn visit_image(&mut self, _image: &'ast Image, _context: &mut VisitContext) {}
+
+    fn visit_tag(&mut self, _tag: Span, _context: &mut VisitContext) {}
+
+    fn visit_footnote_ref(&mut self, _label: Span, _context: &mut VisitContext) {}
+
+    fn visit_url(&mut self, _url: &'ast Url, _context: &mut VisitContext) {}
+
+    fn visit_comment(&mut self, _text: Span, _context: &mut VisitContext) {}
+
+    fn visit_html(&mut self, html: &'ast Html, context: &mut VisitContext) {
+        walk_nodes(self, &html.nodes, context)
+    }
+
+    fn visit_inline_field(
+        &mut self,
+        _key: &'ast LinkPart,
+        value: &'ast [Node],
+        context: &mut VisitContext,
+    ) {
+        walk_nodes(self, value, context)
+    }
+}
+
+pub fn walk_file<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    file: &'ast ParsedMDFile,
+    context: &mut VisitContext,
+) {
+    for block in &file.blocks {
+        visitor.visit_block(block, context);
+    }
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    block: &'ast Block,
+    context: &mut VisitContext,
+) {
+    let outer = context.enter_block();
+    match block {
+        Block::Callout(callout) => visitor.visit_callout(callout, context),
+        Block::BlockQuote(block_quote) => visitor.visit_block_quote(block_quote, context),
+        Block::Latex(latex_block) => visitor.visit_latex_block(latex_block, context),
+        Block::Code(code_block) => visitor.visit_code_block(code_block, context),
+        Block::Table(table) => visitor.visit_table(table, context),
+        Block::FootnoteDefinition(footnote) => visitor.visit_footnote_definition(footnote, context),
+        Block::Html(html_block) => visitor.visit_html_block(html_block, context),
+        Block::String(string_block) => visitor.visit_string_block(string_block, context),
+        Block::Unparsed(unparsed) => visitor.visit_unparsed_block(unparsed, context),
+    }
+    context.exit_block(outer);
+}
+
+pub fn walk_callout<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    callout: &'ast Callout,
+    context: &mut VisitContext,
+) {
+    walk_nodes(visitor, &callout.title, context);
+    for block in &callout.inner_blocks {
+        visitor.visit_block(block, context);
+    }
+}
+
+pub fn walk_block_quote<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    block_quote: &'ast BlockQuote,
+    context: &mut VisitContext,
+) {
+    for block in &block_quote.inner_blocks {
+        visitor.visit_block(block, context);
+    }
+}
+
+pub fn walk_table<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    table: &'ast Table,
+    context: &mut VisitContext,
+) {
+    for cell in table.get_cells() {
+        visitor.visit_table_cell(cell, context);
+    }
+}
+
+pub fn walk_lines<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    lines: &'ast [Line],
+    context: &mut VisitContext,
+) {
+    for line in lines {
+        context.enter_line(line);
+        visitor.visit_line(line, context);
+    }
+}
+
+pub fn walk_line<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    line: &'ast Line,
+    context: &mut VisitContext,
+) {
+    match line {
+        Line::Task(task) => visitor.visit_task(task, context),
+        Line::NumberedList(numbered_list) => visitor.visit_numbered_list(numbered_list, context),
+        Line::BulletedList(bulleted_list) => visitor.visit_bulleted_list(bulleted_list, context),
+        Line::Heading(heading) => visitor.visit_heading(heading, context),
+        Line::StringLine(string_line) => visitor.visit_string_line(string_line, context),
+    }
+}
+
+pub fn walk_nodes<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    nodes: &'ast [Node],
+    context: &mut VisitContext,
+) {
+    for node in nodes {
+        visitor.visit_node(node, context);
+    }
+}
+
+pub fn walk_node<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    node: &'ast Node,
+    context: &mut VisitContext,
+) {
+    let outer: Option<(usize, usize)> = context.span.replace((node.start, node.end));
+    match &node.node {
+        NodeEnum::Text => visitor.visit_text(node.span(), context),
+        NodeEnum::BoldItalic(nodes) => visitor.visit_emphasis(Emphasis::BoldItalic, nodes, context),
+        NodeEnum::Bold(nodes, _) => visitor.visit_emphasis(Emphasis::Bold, nodes, context),
+        NodeEnum::Italic(nodes, _) => visitor.visit_emphasis(Emphasis::Italic, nodes, context),
+        NodeEnum::Highlight(nodes) => visitor.visit_emphasis(Emphasis::Highlight, nodes, context),
+        NodeEnum::Strikethrough(nodes) => {
+            visitor.visit_emphasis(Emphasis::Strikethrough, nodes, context)
+        }
+        NodeEnum::MDLink(link) | NodeEnum::NamedMDLink(link) => {
+            visitor.visit_wikilink(link, context)
+        }
+        NodeEnum::WebLink(web_link) => visitor.visit_web_link(web_link, context),
+        NodeEnum::SquareBracket => visitor.visit_square_bracket(node.span(), context),
+        NodeEnum::InlineCode => visitor.visit_inline_code(node.span(), context),
+        NodeEnum::InlineLatex => visitor.visit_inline_latex(node.span(), context),
+        NodeEnum::InlineCodeBlock => visitor.visit_inline_code_block(node.span(), context),
+        NodeEnum::InlineLatexBlock => visitor.visit_inline_latex_block(node.span(), context),
+        NodeEnum::Embed(embed) => visitor.visit_embed(embed, context),
+        NodeEnum::Image(image) => visitor.visit_image(image, context),
+        NodeEnum::Tag => visitor.visit_tag(node.span(), context),
+        // inside the [^ and ]
+        NodeEnum::FootnoteRef => {
+            visitor.visit_footnote_ref(Span::new(node.start + 2, node.end - 1), context)
+        }
+        NodeEnum::Url(url) => visitor.visit_url(url, context),
+        NodeEnum::Comment => visitor.visit_comment(node.span(), context),
+        NodeEnum::Html(html) => visitor.visit_html(html, context),
+        NodeEnum::InlineField(field) => {
+            visitor.visit_inline_field(&field.key, &field.value, context)
+        }
+    }
+    context.span = outer;
+}
+
+pub fn walk_emphasis<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    emphasis: Emphasis,
+    nodes: &'ast [Node],
+    context: &mut VisitContext,
+) {
+    context.emphasis.push(emphasis);
+    walk_nodes(visitor, nodes, context);
+    context.emphasis.pop();
+}
+
+// The same walk over a note that may be changed on the way. Offsets are not
+// moved, an edit that changes the length of the text has to keep them in step.
+pub trait VisitorMut {
+    fn visit_file_mut(&mut self, file: &mut ParsedMDFile, context: &mut VisitContext) {
+        walk_file_mut(self, file, context)
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block, context: &mut VisitContext) {
+        walk_block_mut(self, block, context)
+    }
+
+    fn visit_callout_mut(&mut self, callout: &mut Callout, context: &mut VisitContext) {
+        walk_callout_mut(self, callout, context)
+    }
+
+    fn visit_block_quote_mut(&mut self, block_quote: &mut BlockQuote, context: &mut VisitContext) {
+        walk_block_quote_mut(self, block_quote, context)
+    }
+
+    fn visit_latex_block_mut(
+        &mut self,
+        _latex_block: &mut LatexBlock,
+        _context: &mut VisitContext,
+    ) {
+    }
+
+    fn visit_code_block_mut(&mut self, _code_block: &mut CodeBlock, _context: &mut VisitContext) {}
+
+    fn visit_table_mut(&mut self, table: &mut Table, context: &mut VisitContext) {
+        walk_table_mut(self, table, context)
+    }
+
+    fn visit_table_cell_mut(&mut self, cell: &mut TableCell, context: &mut VisitContext) {
+        self.visit_nodes_mut(&mut cell.nodes, context)
+    }
+
+    fn visit_footnote_definition_mut(
+        &mut self,
+        footnote: &mut FootnoteDefinition,
+        context: &mut VisitContext,
+    ) {
+        walk_lines_mut(self, &mut footnote.lines, context)
+    }
+
+    fn visit_html_block_mut(&mut self, html_block: &mut HtmlBlock, context: &mut VisitContext) {
+        self.visit_nodes_mut(&mut html_block.nodes, context)
+    }
+
+    fn visit_string_block_mut(
+        &mut self,
+        string_block: &mut StringBlock,
+        context: &mut VisitContext,
+    ) {
+        walk_lines_mut(self, &mut string_block.lines, context)
+    }
+
+    fn visit_unparsed_block_mut(
+        &mut self,
+        _unparsed: &mut UnparsedBlock,
+        _context: &mut VisitContext,
+    ) {
+    }
+
+    fn visit_line_mut(&mut self, line: &mut Line, context: &mut VisitContext) {
+        walk_line_mut(self, line, context)
+    }
+
+    fn visit_task_mut(&mut self, task: &mut Task, context: &mut VisitContext) {
+        self.visit_nodes_mut(&mut task.nodes, context)
+    }
+
+    fn visit_numbered_list_mut(
+        &mut self,
+        numbered_list: &mut NumberedList,
+        context: &mut VisitContext,
+    ) {
+        self.visit_nodes_mut(&mut numbered_list.nodes, context)
+    }
+
+    fn visit_bulleted_list_mut(
+        &mut self,
+        bulleted_list: &mut BulletedList,
+        context: &mut VisitContext,
+    ) {
+        self.visit_nodes_mut(&mut bulleted_list.nodes, context)
+    }
+
+    fn visit_heading_mut(&mut self, heading: &mut Heading, context: &mut VisitContext) {
+        self.visit_nodes_mut(&mut heading.nodes, context)
+    }
+
+    fn visit_string_line_mut(&mut self, string_line: &mut StringLine, context: &mut VisitContext) {
+        self.visit_nodes_mut(&mut string_line.nodes, context)
+    }
+
+    // a list of sibling nodes, an override may add or remove nodes
+    fn visit_nodes_mut(&mut self, nodes: &mut Vec<Node>, context: &mut VisitContext) {
+        walk_nodes_mut(self, nodes, context)
+    }
+
+    // may replace the node, walk_node_mut visits what it was replaced with
+    fn visit_node_mut(&mut self, node: &mut Node, context: &mut VisitContext) {
+        walk_node_mut(self, node, context)
+    }
+
+    fn visit_emphasis_mut(
+        &mut self,
+        emphasis: Emphasis,
+        nodes: &mut Vec<Node>,
+        context: &mut VisitContext,
+    ) {
+        walk_emphasis_mut(self, emphasis, nodes, context)
+    }
+
+    fn visit_wikilink_mut(&mut self, _link: &mut WikiLink, _context: &mut VisitContext) {}
+
+    fn visit_web_link_mut(&mut self, _web_link: &mut WebLink, _context: &mut VisitContext) {}
+
+    fn visit_embed_mut(&mut self, _embed: &mut Embed, _context: &mut VisitContext) {}
+
+    fn visit_image_mut(&mut self, _image: &mut Image, _context: &mut VisitContext) {}
+
+    fn visit_url_mut(&mut self, _url: &mut Url, _context: &mut VisitContext) {}
+
+    fn visit_html_mut(&mut self, html: &mut Html, context: &mut VisitContext) {
+        self.visit_nodes_mut(&mut html.nodes, context)
+    }
+
+    fn visit_inline_field_mut(
+        &mut self,
+        _key: &mut LinkPart,
+        value: &mut Vec<Node>,
+        context: &mut VisitContext,
+    ) {
+        self.visit_nodes_mut(value, context)
+    }
+}
+
+pub fn walk_file_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    file: &mut ParsedMDFile,
+    context: &mut VisitContext,
+) {
+    for block in &mut file.blocks {
+        visitor.visit_block_mut(block, context);
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    block: &mut Block,
+    context: &mut VisitContext,
+) {
+    let outer = context.enter_block();
+    match block {
+        Block::Callout(callout) => visitor.visit_callout_mut(callout, context),
+        Block::BlockQuote(block_quote) => visitor.visit_block_quote_mut(block_quote, context),
+        Block::Latex(latex_block) => visitor.visit_latex_block_mut(latex_block, context),
+        Block::Code(code_block) => visitor.visit_code_block_mut(code_block, context),
+        Block::Table(table) => visitor.visit_table_mut(table, context),
+        Block::FootnoteDefinition(footnote) => {
+            visitor.visit_footnote_definition_mut(footnote, context)
+        }
+        Block::Html(html_block) => visitor.visit_html_block_mut(html_block, context),
+        Block::String(string_block) => visitor.visit_string_block_mut(string_block, context),
+        Block::Unparsed(unparsed) => visitor.visit_unparsed_block_mut(unparsed, context),
+    }
+    context.exit_block(outer);
+}
+
+pub fn walk_callout_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    callout: &mut Callout,
+    context: &mut VisitContext,
+) {
+    visitor.visit_nodes_mut(&mut callout.title, context);
+    for block in &mut callout.inner_blocks {
+        visitor.visit_block_mut(block, context);
+    }
+}
+
+pub fn walk_block_quote_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    block_quote: &mut BlockQuote,
+    context: &mut VisitContext,
+) {
+    for block in &mut block_quote.inner_blocks {
+        visitor.visit_block_mut(block, context);
+    }
+}
+
+pub fn walk_table_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    table: &mut Table,
+    context: &mut VisitContext,
+) {
+    for cell in std::iter::once(&mut table.header)
+        .chain(table.rows.iter_mut())
+        .flatten()
+    {
+        visitor.visit_table_cell_mut(cell, context);
+    }
+}
+
+pub fn walk_lines_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    lines: &mut [Line],
+    context: &mut VisitContext,
+) {
+    for line in lines {
+        context.enter_line(line);
+        visitor.visit_line_mut(line, context);
+    }
+}
+
+pub fn walk_line_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    line: &mut Line,
+    context: &mut VisitContext,
+) {
+    match line {
+        Line::Task(task) => visitor.visit_task_mut(task, context),
+        Line::NumberedList(numbered_list) => {
+            visitor.visit_numbered_list_mut(numbered_list, context)
+        }
+        Line::BulletedList(bulleted_list) => {
+            visitor.visit_bulleted_list_mut(bulleted_list, context)
+        }
+        Line::Heading(heading) => visitor.visit_heading_mut(heading, context),
+        Line::StringLine(string_line) => visitor.visit_string_line_mut(string_line, context),
+    }
+}
+
+pub fn walk_nodes_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    nodes: &mut [Node],
+    context: &mut VisitContext,
+) {
+    for node in nodes {
+        visitor.visit_node_mut(node, context);
+    }
+}
+
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    node: &mut Node,
+    context: &mut VisitContext,
+) {
+    let outer: Option<(usize, usize)> = context.span.replace((node.start, node.end));
+    match &mut node.node {
+        NodeEnum::BoldItalic(nodes) => {
+            visitor.visit_emphasis_mut(Emphasis::BoldItalic, nodes, context)
+        }
+        NodeEnum::Bold(nodes, _) => visitor.visit_emphasis_mut(Emphasis::Bold, nodes, context),
+        NodeEnum::Italic(nodes, _) => visitor.visit_emphasis_mut(Emphasis::Italic, nodes, context),
+        NodeEnum::Highlight(nodes) => {
+            visitor.visit_emphasis_mut(Emphasis::Highlight, nodes, context)
+        }
+        NodeEnum::Strikethrough(nodes) => {
+            visitor.visit_emphasis_mut(Emphasis::Strikethrough, nodes, context)
+        }
+        NodeEnum::MDLink(link) | NodeEnum::NamedMDLink(link) => {
+            visitor.visit_wikilink_mut(link, context)
+        }
+        NodeEnum::WebLink(web_link) => visitor.visit_web_link_mut(web_link, context),
+        NodeEnum::Embed(embed) => visitor.visit_embed_mut(embed, context),
+        NodeEnum::Image(image) => visitor.visit_image_mut(image, context),
+        NodeEnum::Url(url) => visitor.visit_url_mut(url, context),
+        NodeEnum::Html(html) => visitor.visit_html_mut(html, context),
+        NodeEnum::InlineField(field) => {
+            visitor.visit_inline_field_mut(&mut field.key, &mut field.value, context)
+        }
+        // text leaves are read from the source, changing them means editing it
+        NodeEnum::Text
+        | NodeEnum::Tag
+        | NodeEnum::SquareBracket
+        | NodeEnum::InlineCode
+        | NodeEnum::InlineLatex
+        | NodeEnum::InlineCodeBlock
+        | NodeEnum::InlineLatexBlock
+        | NodeEnum::FootnoteRef
+        | NodeEnum::Comment => {}
+    }
+    context.span = outer;
+}
+
+pub fn walk_emphasis_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    emphasis: Emphasis,
+    nodes: &mut Vec<Node>,
+    context: &mut VisitContext,
+) {
+    context.emphasis.push(emphasis);
+    visitor.visit_nodes_mut(nodes, context);
+    context.emphasis.pop();
+}
+
+// moves every byte offset in what it visits, see Shift
+pub(crate) struct ShiftOffsets<'s> {
+    pub(crate) shift: Shift<'s>,
+}
+
+impl VisitorMut for ShiftOffsets<'_> {
+    fn visit_block_mut(&mut self, block: &mut Block, context: &mut VisitContext) {
+        let (start, end): (&mut usize, &mut usize) = block.span_mut();
+        self.shift.span(start, end);
+        walk_block_mut(self, block, context);
+    }
+
+    fn visit_table_cell_mut(&mut self, cell: &mut TableCell, context: &mut VisitContext) {
+        self.shift.start(&mut cell.start);
+        self.shift.end(&mut cell.end);
+        self.visit_nodes_mut(&mut cell.nodes, context);
+    }
+
+    fn visit_callout_mut(&mut self, callout: &mut Callout, context: &mut VisitContext) {
+        callout.kind.shift(self.shift);
+        walk_callout_mut(self, callout, context);
+    }
+
+    fn visit_code_block_mut(&mut self, code_block: &mut CodeBlock, _context: &mut VisitContext) {
+        if let Some(code_type) = &mut code_block.code_type {
+            code_type.shift(self.shift);
+        }
+        code_block.code.shift(self.shift);
+        code_block.fence.shift(self.shift);
+        code_block.closing_fence.shift(self.shift);
+    }
+
+    fn visit_table_mut(&mut self, table: &mut Table, context: &mut VisitContext) {
+        table.delimiter_row.shift(self.shift);
+        walk_table_mut(self, table, context);
+    }
+
+    fn visit_footnote_definition_mut(
+        &mut self,
+        footnote: &mut FootnoteDefinition,
+        context: &mut VisitContext,
+    ) {
+        footnote.label.shift(self.shift);
+        walk_lines_mut(self, &mut footnote.lines, context);
+    }
+
+    fn visit_line_mut(&mut self, line: &mut Line, context: &mut VisitContext) {
+        let (block_id, start, end) = line.offsets_mut();
+        self.shift.span(start, end);
+        if let Some(block_id) = block_id {
+            block_id.shift(self.shift);
+        }
+        walk_line_mut(self, line, context);
+    }
+
+    fn visit_task_mut(&mut self, task: &mut Task, context: &mut VisitContext) {
+        task.indent.span.shift(self.shift);
+        task.marker.shift(self.shift);
+        self.visit_nodes_mut(&mut task.nodes, context);
+    }
+
+    fn visit_numbered_list_mut(
+        &mut self,
+        numbered_list: &mut NumberedList,
+        context: &mut VisitContext,
+    ) {
+        numbered_list.indent.span.shift(self.shift);
+        self.visit_nodes_mut(&mut numbered_list.nodes, context);
+    }
+
+    fn visit_bulleted_list_mut(
+        &mut self,
+        bulleted_list: &mut BulletedList,
+        context: &mut VisitContext,
+    ) {
+        bulleted_list.indent.span.shift(self.shift);
+        self.visit_nodes_mut(&mut bulleted_list.nodes, context);
+    }
+
+    fn visit_node_mut(&mut self, node: &mut Node, context: &mut VisitContext) {
+        self.shift.start(&mut node.start);
+        self.shift.end(&mut node.end);
+        walk_node_mut(self, node, context);
+    }
+
+    fn visit_wikilink_mut(&mut self, link: &mut WikiLink, _context: &mut VisitContext) {
+        link.shift(self.shift);
+    }
+
+    fn visit_web_link_mut(&mut self, web_link: &mut WebLink, _context: &mut VisitContext) {
+        web_link.name.shift(self.shift);
+        web_link.link.shift(self.shift);
+    }
+
+    fn visit_embed_mut(&mut self, embed: &mut Embed, _context: &mut VisitContext) {
+        embed.target.shift(self.shift);
+        if let Some(alt) = &mut embed.alt {
+            alt.shift(self.shift);
+        }
+    }
+
+    fn visit_image_mut(&mut self, image: &mut Image, _context: &mut VisitContext) {
+        image.target.shift(self.shift);
+        image.alt.shift(self.shift);
+    }
+
+    fn visit_url_mut(&mut self, url: &mut Url, _context: &mut VisitContext) {
+        url.url.shift(self.shift);
+    }
+
+    fn visit_html_mut(&mut self, html: &mut Html, context: &mut VisitContext) {
+        html.name.shift(self.shift);
+        html.tag.shift(self.shift);
+        self.visit_nodes_mut(&mut html.nodes, context);
+    }
+
+    fn visit_inline_field_mut(
+        &mut self,
+        key: &mut LinkPart,
+        value: &mut Vec<Node>,
+        context: &mut VisitContext,
+    ) {
+        key.shift(self.shift);
+        self.visit_nodes_mut(value, context);
+    }
+}
+
+// every node, each followed by the nodes nested inside it
+pub(crate) struct AllNodes<'ast> {
+    pub(crate) nodes: Vec<&'ast Node>,
+}
+
+impl<'ast> Visitor<'ast> for AllNodes<'ast> {
+    fn visit_node(&mut self, node: &'ast Node, context: &mut VisitContext) {
+        self.nodes.push(node);
+        walk_node(self, node, context);
+    }
+}
+
+pub(crate) struct TableCells<'ast> {
+    pub(crate) cells: Vec<&'ast TableCell>,
+}
+
+impl<'ast> Visitor<'ast> for TableCells<'ast> {
+    fn visit_table_cell(&mut self, cell: &'ast TableCell, _context: &mut VisitContext) {
+        self.cells.push(cell);
+    }
+}
+
+// the text nodes links may be added to, what counts depends on the settings
+pub(crate) struct StringNodes<'s> {
+    pub(crate) settings: &'s Settings,
+    pub(crate) source: &'s str,
+    pub(crate) nodes: Vec<Node>,
+}
+
+impl<'ast> Visitor<'ast> for StringNodes<'_> {
+    fn visit_callout(&mut self, callout: &'ast Callout, context: &mut VisitContext) {
+        if !self
+            .settings
+            .is_callout_excluded(callout.kind.text(self.source))
+        {
+            walk_callout(self, callout, context);
+        }
+    }
+
+    fn visit_html_block(&mut self, html_block: &'ast HtmlBlock, context: &mut VisitContext) {
+        if self.settings.link_html_text {
+            walk_nodes(self, &html_block.nodes, context);
+        }
+    }
+
+    fn visit_html(&mut self, html: &'ast Html, context: &mut VisitContext) {
+        if self.settings.link_html_text {
+            walk_nodes(self, &html.nodes, context);
+        }
+    }
+
+    fn visit_text(&mut self, text: Span, _context: &mut VisitContext) {
+        self.nodes.push(Node {
+            node: NodeEnum::Text,
+            start: text.start,
+            end: text.end,
+        });
+    }
+
+    fn visit_inline_field(
+        &mut self,
+        key: &'ast LinkPart,
+        value: &'ast [Node],
+        context: &mut VisitContext,
+    ) {
+        if !self.settings.link_only_field_values {
+            self.nodes.push(Node {
+                node: NodeEnum::Text,
+                start: key.start,
+                end: key.end,
+            });
+        }
+        walk_nodes(self, value, context);
+    }
+}
@@ -1,7 +1,13 @@
-#[derive(Debug)]
-pub(crate) struct Settings {
+#[derive(Debug, Clone)]
+pub struct Settings {
     pub case_insensitive: bool,
     pub color: String,
+    // callout kinds (e.g. "quote") whose title and body are never linked, compared case insensitively
+    pub excluded_callouts: Vec<String>,
+    // text between html tags may be linked, the tags and their attributes never are
+    pub link_html_text: bool,
+    // dataview field keys are left alone, only their values may be linked
+    pub link_only_field_values: bool,
 }
 
 impl Settings {
@@ -9,6 +15,7 @@ impl Settings {
         Settings {
             case_insensitive,
             color,
+            ..Settings::default()
         }
     }
 
@@ -16,6 +23,15 @@ impl Settings {
         Settings {
             case_insensitive: true,
             color: String::from("red"),
+            excluded_callouts: Vec::new(),
+            link_html_text: false,
+            link_only_field_values: true,
         }
     }
+
+    pub fn is_callout_excluded(&self, kind: &str) -> bool {
+        self.excluded_callouts
+            .iter()
+            .any(|excluded| excluded.eq_ignore_ascii_case(kind))
+    }
 }
@@ -1 +1,87 @@
+use serde::{Deserialize, Serialize};
+
 use crate::prelude::*;
+
+// replaces the bytes start..end of a text, an insertion has start == end
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    pub fn new(start: usize, end: usize, replacement: String) -> Self {
+        TextEdit {
+            start,
+            end,
+            replacement,
+        }
+    }
+
+    pub fn insert(at: usize, text: &str) -> Self {
+        TextEdit::new(at, at, text.to_string())
+    }
+
+    pub fn delete(start: usize, end: usize) -> Self {
+        TextEdit::new(start, end, String::new())
+    }
+
+    // the single edit turning old into new, None when they are the same
+    pub fn between(old: &str, new: &str) -> Option<Self> {
+        if old == new {
+            return None;
+        }
+        let prefix: usize = old
+            .char_indices()
+            .zip(new.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(old.len().min(new.len()), |((i, _), _)| i);
+        let suffix: usize = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        Some(TextEdit::new(
+            prefix,
+            old.len() - suffix,
+            new[prefix..new.len() - suffix].to_string(),
+        ))
+    }
+
+    // change in length of the text after applying this edit
+    pub fn delta(&self) -> isize {
+        self.replacement.len() as isize - (self.end - self.start) as isize
+    }
+}
+
+// edits are given in offsets of the original text and must not overlap
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> Result<String> {
+    let mut edits: Vec<&TextEdit> = edits.iter().collect();
+    edits.sort_by_key(|edit| (edit.start, edit.end));
+
+    let mut result: String = String::with_capacity(text.len());
+    let mut pos: usize = 0;
+    for edit in edits {
+        if edit.start < pos
+            || edit.start > edit.end
+            || edit.end > text.len()
+            || !text.is_char_boundary(edit.start)
+            || !text.is_char_boundary(edit.end)
+        {
+            return Err(Error::Generic(f!(
+                "Invalid edit {}..{} for text of length {}",
+                edit.start,
+                edit.end,
+                text.len()
+            )));
+        }
+        result.push_str(&text[pos..edit.start]);
+        result.push_str(&edit.replacement);
+        pos = edit.end;
+    }
+    result.push_str(&text[pos..]);
+    Ok(result)
+}
@@ -0,0 +1,433 @@
+use super::File;
+use crate::prelude::*;
+use crate::utils::TextEdit;
+
+// indent used for lists this module writes, the same as Obsidian
+const LIST_INDENT: &str = "  ";
+
+// Edits to the frontmatter are made line by line on the original text so that
+// key order, comments and quoting survive. Each call returns the smallest
+// edits that make the change, in offsets of File::original, and nothing when
+// the frontmatter already says what was asked.
+impl File {
+    // appends value to the list under key, a scalar becomes a flow list
+    pub fn add_frontmatter_item(&self, key: &str, value: &str) -> Result<Vec<TextEdit>> {
+        let yaml: YamlText = match self.yaml_text()? {
+            Some(yaml) => yaml,
+            None => return Ok(vec![self.create_frontmatter(key, &list_value(value))]),
+        };
+        let entry: Entry = match yaml.entry(key) {
+            Some(entry) => entry,
+            None => return Ok(vec![yaml.append(key, &list_value(value))]),
+        };
+        let inline: &str = yaml.slice(entry.value_start, entry.value_end);
+
+        if inline.starts_with('[') {
+            let items: Vec<(usize, usize)> = yaml.flow_items(&entry)?;
+            if items
+                .iter()
+                .any(|(start, end)| unquote(yaml.slice(*start, *end)) == value)
+            {
+                return Ok(Vec::new());
+            }
+            let style: Option<char> = items
+                .first()
+                .and_then(|(start, _)| quote_style(yaml.slice(*start, *start + 1)));
+            let item: String = quote(value, style, true);
+            return Ok(vec![match items.last() {
+                Some((_, end)) => TextEdit::insert(*end, &f!(", {}", item)),
+                None => TextEdit::insert(entry.value_start + 1, &item),
+            }]);
+        }
+        if !inline.is_empty() {
+            if !is_scalar(inline) {
+                return Err(unsupported(key, &self.path));
+            }
+            if unquote(inline) == value {
+                return Ok(Vec::new());
+            }
+            let item: String = quote(value, quote_style(inline), true);
+            return Ok(vec![
+                TextEdit::insert(entry.value_start, "["),
+                TextEdit::insert(entry.value_end, &f!(", {}]", item)),
+            ]);
+        }
+
+        let items: Vec<(usize, &str)> = yaml.block_items(&entry);
+        if items.is_empty() && entry.last != entry.line {
+            return Err(unsupported(key, &self.path));
+        }
+        if items.iter().any(|(_, item)| unquote(item) == value) {
+            return Ok(Vec::new());
+        }
+        let (indent, style): (&str, Option<char>) = match items.first() {
+            Some((line, item)) => (yaml.indent(*line), quote_style(item)),
+            None => (LIST_INDENT, None),
+        };
+        let at: usize = yaml.lines[entry.last].1;
+        Ok(vec![TextEdit::insert(
+            at,
+            &f!("{}{}- {}", yaml.newline, indent, quote(value, style, false)),
+        )])
+    }
+
+    // removes every item equal to value from the list under key
+    pub fn remove_frontmatter_item(&self, key: &str, value: &str) -> Result<Vec<TextEdit>> {
+        let yaml: YamlText = match self.yaml_text()? {
+            Some(yaml) => yaml,
+            None => return Ok(Vec::new()),
+        };
+        let entry: Entry = match yaml.entry(key) {
+            Some(entry) => entry,
+            None => return Ok(Vec::new()),
+        };
+        let inline: &str = yaml.slice(entry.value_start, entry.value_end);
+
+        if inline.starts_with('[') {
+            let items: Vec<(usize, usize)> = yaml.flow_items(&entry)?;
+            let keep: Vec<&(usize, usize)> = items
+                .iter()
+                .filter(|(start, end)| unquote(yaml.slice(*start, *end)) != value)
+                .collect();
+            if keep.len() == items.len() {
+                return Ok(Vec::new());
+            }
+            let joined: Vec<&str> = keep
+                .iter()
+                .map(|(start, end)| yaml.slice(*start, *end))
+                .collect();
+            let (first, last): (usize, usize) = (items[0].0, items[items.len() - 1].1);
+            return Ok(vec![TextEdit::new(first, last, joined.join(", "))]);
+        }
+        if !inline.is_empty() {
+            if is_scalar(inline) && unquote(inline) == value {
+                return Ok(vec![TextEdit::delete(entry.colon + 1, entry.value_end)]);
+            }
+            return Ok(Vec::new());
+        }
+        Ok(yaml
+            .block_items(&entry)
+            .into_iter()
+            .filter(|(_, item)| unquote(item) == value)
+            .map(|(line, _)| TextEdit::delete(yaml.lines[line].0, yaml.line_end_with_newline(line)))
+            .collect())
+    }
+
+    // replaces whatever is under key with a single value
+    pub fn set_frontmatter_value(&self, key: &str, value: &str) -> Result<Vec<TextEdit>> {
+        let yaml: YamlText = match self.yaml_text()? {
+            Some(yaml) => yaml,
+            None => {
+                return Ok(vec![
+                    self.create_frontmatter(key, &f!(" {}", quote(value, None, false)))
+                ])
+            }
+        };
+        let entry: Entry = match yaml.entry(key) {
+            Some(entry) => entry,
+            None => return Ok(vec![yaml.append(key, &f!(" {}", quote(value, None, false)))]),
+        };
+        let inline: &str = yaml.slice(entry.value_start, entry.value_end);
+        if entry.last == entry.line && is_scalar(inline) && !inline.is_empty() {
+            if unquote(inline) == value {
+                return Ok(Vec::new());
+            }
+            return Ok(vec![TextEdit::new(
+                entry.value_start,
+                entry.value_end,
+                quote(value, quote_style(inline), false),
+            )]);
+        }
+        Ok(vec![TextEdit::new(
+            entry.colon + 1,
+            yaml.lines[entry.last].1,
+            f!(" {}", quote(value, None, false)),
+        )])
+    }
+
+    // a note without frontmatter gets a block at the very start
+    fn create_frontmatter(&self, key: &str, value: &str) -> TextEdit {
+        let newline: &str = if self.original().contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let text: String = f!("---\n{}:{}\n---\n", key, value);
+        TextEdit::insert(0, &text.replace('\n', newline))
+    }
+
+    fn yaml_text(&self) -> Result<Option<YamlText<'_>>> {
+        let frontmatter = match self.contents.get_frontmatter() {
+            Some(frontmatter) => frontmatter,
+            None => return Ok(None),
+        };
+        if frontmatter.diagnostic.is_some() {
+            return Err(Error::Generic(f!(
+                "Frontmatter of {} is not valid yaml",
+                self.path.display()
+            )));
+        }
+        Ok(Some(YamlText::new(
+            self.original(),
+            frontmatter.start,
+            frontmatter.end.min(self.original().len()),
+        )))
+    }
+}
+
+// the lines between the --- markers with absolute offsets, ends exclude the line break
+struct YamlText<'a> {
+    text: &'a str,
+    end: usize,
+    lines: Vec<(usize, usize)>,
+    newline: &'static str,
+}
+
+// a top level key, its value on the key line and the lines that continue it
+struct Entry {
+    line: usize,
+    colon: usize,
+    value_start: usize,
+    value_end: usize,
+    last: usize,
+}
+
+impl<'a> YamlText<'a> {
+    fn new(text: &'a str, start: usize, end: usize) -> Self {
+        let mut lines: Vec<(usize, usize)> = Vec::new();
+        let mut pos: usize = start;
+        // the text starts with the break after the opening ---
+        if let Some(offset) = text[start..end].find('\n') {
+            pos = start + offset + 1;
+        }
+        while pos < end {
+            let line_end: usize = text[pos..end].find('\n').map_or(end, |offset| pos + offset);
+            let content_end: usize = if text[..line_end].ends_with('\r') {
+                line_end - 1
+            } else {
+                line_end
+            };
+            lines.push((pos, content_end));
+            pos = line_end + 1;
+        }
+        let newline: &'static str = if text[start..end].contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        YamlText {
+            text,
+            end,
+            lines,
+            newline,
+        }
+    }
+
+    fn slice(&self, start: usize, end: usize) -> &'a str {
+        &self.text[start..end]
+    }
+
+    fn line(&self, index: usize) -> &'a str {
+        self.slice(self.lines[index].0, self.lines[index].1)
+    }
+
+    fn indent(&self, index: usize) -> &'a str {
+        let line: &str = self.line(index);
+        &line[..line.len() - line.trim_start().len()]
+    }
+
+    fn line_end_with_newline(&self, index: usize) -> usize {
+        match self.lines.get(index + 1) {
+            Some((start, _)) => *start,
+            None => self.end,
+        }
+    }
+
+    fn entry(&self, key: &str) -> Option<Entry> {
+        let line: usize = (0..self.lines.len()).find(|index| {
+            let text: &str = self.line(*index);
+            [f!("{}:", key), f!("\"{}\":", key), f!("'{}':", key)]
+                .iter()
+                .any(|prefix| text.starts_with(prefix.as_str()))
+        })?;
+        let (line_start, line_end): (usize, usize) = self.lines[line];
+        let colon: usize = line_start + key_length(self.line(line)).unwrap_or(0);
+        let value: &str = strip_comment(self.slice(colon + 1, line_end));
+        let value_start: usize = colon + 1 + (value.len() - value.trim_start().len());
+        let value_end: usize = colon + 1 + value.trim_end().len();
+
+        // indented lines, list items and comments continue the value until the next key
+        let mut last: usize = line;
+        for index in line + 1..self.lines.len() {
+            let text: &str = self.line(index);
+            let trimmed: &str = text.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if text.len() == trimmed.len() && !(trimmed == "-" || trimmed.starts_with("- ")) {
+                break;
+            }
+            last = index;
+        }
+        Some(Entry {
+            line,
+            colon,
+            value_start,
+            value_end: value_end.max(value_start),
+            last,
+        })
+    }
+
+    // line index and text of each "- item" below the key
+    fn block_items(&self, entry: &Entry) -> Vec<(usize, &'a str)> {
+        (entry.line + 1..=entry.last)
+            .filter_map(|index| {
+                let trimmed: &str = self.line(index).trim_start();
+                let item: &str = trimmed.strip_prefix('-')?;
+                if !(item.is_empty() || item.starts_with(' ')) {
+                    return None;
+                }
+                Some((index, strip_comment(item).trim()))
+            })
+            .collect()
+    }
+
+    // spans of the items of a one line [a, "b"] list
+    fn flow_items(&self, entry: &Entry) -> Result<Vec<(usize, usize)>> {
+        let inline: &str = self.slice(entry.value_start, entry.value_end);
+        if !inline.ends_with(']') || entry.last != entry.line {
+            return Err(Error::Generic(f!(
+                "Cannot edit the list at line {} of the frontmatter",
+                entry.line + 1
+            )));
+        }
+        let mut items: Vec<(usize, usize)> = Vec::new();
+        let mut quote: Option<char> = None;
+        let mut item_start: usize = 1;
+        for (index, c) in inline.char_indices().skip(1) {
+            match (quote, c) {
+                (Some(open), c) if c == open => quote = None,
+                (Some(_), _) => {}
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (None, ',') | (None, ']') => {
+                    let item: &str = &inline[item_start..index];
+                    let leading: usize = item.len() - item.trim_start().len();
+                    if !item.trim().is_empty() {
+                        let start: usize = entry.value_start + item_start + leading;
+                        items.push((start, start + item.trim().len()));
+                    }
+                    item_start = index + 1;
+                }
+                _ => {}
+            }
+        }
+        Ok(items)
+    }
+
+    // a new key goes after the last line, before the closing ---
+    fn append(&self, key: &str, value: &str) -> TextEdit {
+        let body: &str = self.slice(self.lines.first().map_or(self.end, |line| line.0), self.end);
+        let separator: &str = if body.is_empty() || body.ends_with('\n') {
+            ""
+        } else {
+            self.newline
+        };
+        TextEdit::insert(
+            self.end,
+            &f!(
+                "{}{}:{}{}",
+                separator,
+                key,
+                value.replace('\n', self.newline),
+                self.newline
+            ),
+        )
+    }
+}
+
+fn list_value(value: &str) -> String {
+    f!("\n{}- {}", LIST_INDENT, quote(value, None, false))
+}
+
+fn unsupported(key: &str, path: &std::path::Path) -> Error {
+    Error::Generic(f!(
+        "Cannot edit the value of {} in the frontmatter of {}",
+        key,
+        path.display()
+    ))
+}
+
+fn key_length(line: &str) -> Option<usize> {
+    match line.chars().next()? {
+        quote @ ('"' | '\'') => line[1..].find(quote).map(|end| end + 2),
+        _ => line.find(':'),
+    }
+}
+
+// cuts a trailing # comment that is outside quotes
+fn strip_comment(text: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut previous: char = ' ';
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') if previous.is_whitespace() => quote = Some(c),
+            (None, '#') if previous.is_whitespace() => return &text[..index],
+            _ => {}
+        }
+        previous = c;
+    }
+    text
+}
+
+// block scalars, anchors and maps are left alone
+fn is_scalar(text: &str) -> bool {
+    !text.starts_with(['|', '>', '{', '[', '&', '*', '!'])
+}
+
+fn quote_style(text: &str) -> Option<char> {
+    text.chars().next().filter(|c| *c == '"' || *c == '\'')
+}
+
+fn unquote(text: &str) -> String {
+    let text: &str = text.trim();
+    if text.len() >= 2 && text.starts_with('\'') && text.ends_with('\'') {
+        return text[1..text.len() - 1].replace("''", "'");
+    }
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        return text[1..text.len() - 1]
+            .replace("\\\"", "\"")
+            .replace("\\\\", "\\");
+    }
+    text.to_string()
+}
+
+// keeps the quotes used around the other values, plain only when yaml reads it back unchanged
+fn quote(value: &str, style: Option<char>, flow: bool) -> String {
+    match style {
+        Some('\'') => f!("'{}'", value.replace('\'', "''")),
+        Some(_) => f!(
+            "\"{}\"",
+            value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        ),
+        None if is_plain(value, flow) => value.to_string(),
+        None => quote(value, Some('"'), flow),
+    }
+}
+
+fn is_plain(value: &str, flow: bool) -> bool {
+    if value.contains(['\n', '\r'])
+        || value.contains(" #")
+        || (flow && value.contains([',', '[', ']', '{', '}']))
+    {
+        return false;
+    }
+    matches!(
+        serde_yaml::from_str::<serde_yaml::Value>(value),
+        Ok(serde_yaml::Value::String(read)) if read == value
+    )
+}
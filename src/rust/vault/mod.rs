@@ -2,40 +2,58 @@ use std::{collections::HashMap, path::PathBuf};
 
 use pest::error;
 use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
 
 use crate::parser::ParsedMDFile;
 use crate::prelude::*;
 
+mod frontmatter;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct File {
     pub path: PathBuf,
     pub contents: ParsedMDFile,
-    pub original: String,
 }
 
 impl File {
     pub(crate) fn new(path: PathBuf, contents: String) -> Result<Self> {
-        let parsed_mdfile = ParsedMDFile::new(path.clone(), contents.clone())?;
+        let parsed_mdfile = ParsedMDFile::new_recovering(path.clone(), contents)?;
         Ok(File {
             path,
             contents: parsed_mdfile,
-            original: contents,
         })
     }
 
-    pub(crate) fn get_aliases(&self) -> Vec<&str> {
+    // the text as it was read, kept once by the parsed file
+    pub(crate) fn original(&self) -> &str {
+        &self.contents.source
+    }
+
+    // in NFC, file names from macOS are decomposed while typed text usually is not
+    pub(crate) fn get_aliases(&self) -> Vec<String> {
         let title: &str = self.contents.get_title();
-        let file_aliases: Result<Vec<&str>> = self.contents.get_aliases();
-        let mut aliases: Vec<&str> = vec![title];
-        match file_aliases {
-            Ok(file_aliases) => {
-                for alias in file_aliases {
-                    aliases.push(alias);
-                }
-            }
-            Err(_) => {}
-        }
-        aliases
+        std::iter::once(title)
+            .chain(self.contents.get_aliases())
+            .map(|alias| alias.nfc().collect())
+            .collect()
+    }
+
+    // one warning for malformed frontmatter and one per region the parser had to skip
+    pub(crate) fn get_warnings(&self) -> Vec<Error> {
+        let frontmatter = self
+            .contents
+            .get_frontmatter()
+            .and_then(|frontmatter| frontmatter.diagnostic.as_ref());
+        let unparsed = self
+            .contents
+            .get_unparsed_blocks()
+            .into_iter()
+            .map(|unparsed| &unparsed.diagnostic);
+        frontmatter
+            .into_iter()
+            .chain(unparsed)
+            .map(|diagnostic| Error::Diagnostic(self.path.clone(), Box::new(diagnostic.clone())))
+            .collect()
     }
 
     pub(crate) fn get_path(&self) -> &PathBuf {
@@ -49,8 +67,7 @@ impl Default for File {
         let path: PathBuf = PathBuf::new();
         File {
             path: path.clone(),
-            contents: ParsedMDFile::new(path, contents.clone()).unwrap(),
-            original: contents,
+            contents: ParsedMDFile::new(path, contents).unwrap(),
         }
     }
 }
@@ -19,11 +19,17 @@ class RustPluginSettings {
   color: string;
   includePaths: string;
   excludePaths: string;
+  excludedCallouts: string;
+  linkHtmlText: boolean;
+  linkOnlyFieldValues: boolean;
 
   constructor(caseInsensitive: boolean, color: string) {
     this.caseInsensitive = caseInsensitive;
     this.color = color;
     this.includePaths = "";
+    this.excludedCallouts = "";
+    this.linkHtmlText = false;
+    this.linkOnlyFieldValues = true;
   }
   set_case_insensitive(caseSensitive: boolean) {
     this.caseInsensitive = caseSensitive;
@@ -40,7 +46,6 @@ const DEFAULT_SETTINGS: RustPluginSettings = new RustPluginSettings(
 
 interface FileChange {
   file_path: string;
-  new_content: string;
   colored_content: string;
 }
 
@@ -50,6 +55,9 @@ export default class RustPlugin extends Plugin {
     this.manifest.dir +
     "/cache.json";
   cache_obj: { [key: string]: any } = {};
+  // kept between runs of "Link current note", only the notes changed since are parsed again
+  linker_vault: plugin.JsVault | null = null;
+  linker_mtimes: { [key: string]: number } = {};
 
   async onload() {
     await this.loadSettings();
@@ -122,15 +130,10 @@ export default class RustPlugin extends Plugin {
   async run_linker() {
     await this.save_active_file();
     let tfilemap: { [key: string]: TFile } = await this.get_filtered_filemap();
-    let file_paths: string[] = Object.keys(tfilemap);
     let wasm_vault: plugin.JsVault = await this.create_wasm_vault(tfilemap);
     let alias_map: { [key: string]: string[] } =
       await this.get_alias_map(tfilemap);
 
-    let files: plugin.JsFile[] = [];
-    for (let file_path of file_paths) {
-      files.push(wasm_vault.get_file(file_path));
-    }
     // let settings_obj = new plugin.JsSettings(this.settings.caseInsensitive, link_to_self, this.settings.color);
     let valid_file_paths: string[] = wasm_vault.get_valid_file_paths();
     let valid_files: plugin.JsFile[] = valid_file_paths.map((path) =>
@@ -141,6 +144,7 @@ export default class RustPlugin extends Plugin {
       valid_files,
       this.settings.caseInsensitive,
     );
+    this.configure_link_finder(link_finder);
 
     let valid_files_len = valid_files.length;
     let valid_index = 1;
@@ -166,15 +170,10 @@ export default class RustPlugin extends Plugin {
     await this.save_active_file();
 
     let tfilemap: { [key: string]: TFile } = await this.get_filemap();
-    let file_paths: string[] = Object.keys(tfilemap);
-    let wasm_vault: plugin.JsVault = await this.create_wasm_vault(tfilemap);
+    let wasm_vault: plugin.JsVault = await this.update_linker_vault(tfilemap);
     let alias_map: { [key: string]: string[] } =
       await this.get_alias_map(tfilemap);
 
-    let files: plugin.JsFile[] = [];
-    for (let file_path of file_paths) {
-      files.push(wasm_vault.get_file(file_path));
-    }
     let valid_file_paths: string[] = wasm_vault.get_valid_file_paths();
     let valid_files: plugin.JsFile[] = valid_file_paths.map((path) =>
       wasm_vault.get_file(path),
@@ -184,6 +183,7 @@ export default class RustPlugin extends Plugin {
       valid_files,
       this.settings.caseInsensitive,
     );
+    this.configure_link_finder(link_finder);
 
     this.validate_cache(valid_file_paths, tfilemap);
 
@@ -202,15 +202,10 @@ export default class RustPlugin extends Plugin {
   async scan_vault() {
     await this.save_active_file();
     let tfilemap: { [key: string]: TFile } = await this.get_filtered_filemap();
-    let file_paths: string[] = Object.keys(tfilemap);
     let wasm_vault: plugin.JsVault = await this.create_wasm_vault(tfilemap);
     let alias_map: { [key: string]: string[] } =
       await this.get_alias_map(tfilemap);
 
-    let files: plugin.JsFile[] = [];
-    for (let file_path of file_paths) {
-      files.push(wasm_vault.get_file(file_path));
-    }
     let valid_file_paths: string[] = wasm_vault.get_valid_file_paths();
     let valid_files: plugin.JsFile[] = valid_file_paths.map((path) =>
       wasm_vault.get_file(path),
@@ -220,6 +215,7 @@ export default class RustPlugin extends Plugin {
       valid_files,
       this.settings.caseInsensitive,
     );
+    this.configure_link_finder(link_finder);
 
     let valid_files_len = valid_files.length;
     let valid_index = 1;
@@ -247,13 +243,32 @@ export default class RustPlugin extends Plugin {
     let file_paths: string[] = Object.keys(tfilemap);
     let wasm_vault: plugin.JsVault = await this.create_wasm_vault(tfilemap);
 
-    let invalid_files: plugin.JsFileError[] = wasm_vault.get_invalid_files();
-    let paths: string[] = invalid_files.map((file) => file.get_path());
+    let invalid_files: plugin.JsFileError[] = wasm_vault
+      .get_invalid_files()
+      .concat(wasm_vault.get_warnings());
+    // group failures of the same kind together
+    invalid_files.sort((a, b) =>
+      a.get_category().localeCompare(b.get_category()),
+    );
+    let paths: string[] = invalid_files.map((file) =>
+      file.has_diagnostic()
+        ? `${file.get_path()}:${file.get_line()}:${file.get_column()}`
+        : file.get_path(),
+    );
     let errors: string[] = invalid_files.map((file) => file.get_error());
-    let modal = new ParseErrorModal(this, paths, errors);
+    // searching for fixes reparses the note many times, it is only done when asked
+    let fixable: string[] = invalid_files.map((file) =>
+      file.is_fixable() ? file.get_path() : "",
+    );
+    let modal = new ParseErrorModal(this, paths, errors, fixable, tfilemap, wasm_vault);
     modal.open();
   }
 
+  onunload() {
+    this.linker_vault?.free();
+    this.linker_vault = null;
+  }
+
   async loadSettings() {
     this.settings = Object.assign({}, DEFAULT_SETTINGS, await this.loadData());
   }
@@ -357,6 +372,10 @@ export default class RustPlugin extends Plugin {
         file_links = file_links_serialized.map(
           (link_serialized) => new plugin.JsLink(link_serialized),
         );
+        // links cached by older versions only have byte offsets
+        if (file_links.some((link) => link.get_utf16_start() === undefined)) {
+          file_links = link_finder.find_links(file);
+        }
       } else {
         file_links = link_finder.find_links(file);
       }
@@ -464,6 +483,16 @@ export default class RustPlugin extends Plugin {
     }
     return alias_map;
   }
+  configure_link_finder(link_finder: plugin.JsLinkFinder) {
+    let excluded_callouts: string[] = this.settings.excludedCallouts
+      .split(",")
+      .map((kind) => kind.trim())
+      .filter((kind) => kind.length > 0);
+    link_finder.set_excluded_callouts(excluded_callouts);
+    link_finder.set_link_html_text(this.settings.linkHtmlText);
+    link_finder.set_link_only_field_values(this.settings.linkOnlyFieldValues);
+  }
+
   async create_wasm_vault(filemap: {
     [key: string]: TFile;
   }): Promise<plugin.JsVault> {
@@ -475,6 +504,33 @@ export default class RustPlugin extends Plugin {
     }
     return wasm_vault;
   }
+  // the vault of the last run with the notes changed since updated, it is built again
+  // when notes were added, removed or renamed
+  async update_linker_vault(filemap: {
+    [key: string]: TFile;
+  }): Promise<plugin.JsVault> {
+    let paths: string[] = Object.keys(filemap);
+    let same_files: boolean =
+      paths.length == Object.keys(this.linker_mtimes).length &&
+      paths.every((path) => path in this.linker_mtimes);
+    if (this.linker_vault == null || !same_files) {
+      this.linker_vault?.free();
+      this.linker_vault = await this.create_wasm_vault(filemap);
+      this.linker_mtimes = {};
+      for (let path of paths) {
+        this.linker_mtimes[path] = filemap[path].stat.mtime;
+      }
+      return this.linker_vault;
+    }
+    for (let path of paths) {
+      let tfile: TFile = filemap[path];
+      if (tfile.stat.mtime != this.linker_mtimes[path]) {
+        this.linker_vault.update_file(path, await this.app.vault.cachedRead(tfile));
+        this.linker_mtimes[path] = tfile.stat.mtime;
+      }
+    }
+    return this.linker_vault;
+  }
   async get_parsed_file(
     path: string,
     vault: plugin.JsVault,
@@ -508,7 +564,6 @@ export default class RustPlugin extends Plugin {
       valid_index++;
       return;
     }
-    let byte_increament: number = 0;
     let file: plugin.JsFile = wasm_vault.get_file(file_path);
     let file_links: plugin.JsLink[] = await this.get_links(
       tfilemap[file_path],
@@ -536,35 +591,29 @@ export default class RustPlugin extends Plugin {
     if (view && file_open) {
       file_content = view.editor.getValue();
     }
-
-    let current_file_text = file_content;
-    // bad_links:
-    // - Aliasing.md
-    // - Antisymmetric Relation.md
-    // get array of links: [Aliasing.md, Antisymmetric Relation.md]
-
-    let regex = /bad_links:\n(\s+- [^\n]*)+/g;
-    let bad_links = current_file_text.match(regex);
-    let captured_links: string[] = [];
-    let capture_regex = / - ([^\n]*)/g;
-    // if badlinks is found store the capture group 1 in captured_links
-    if (bad_links) {
-      for (let bad_link of bad_links) {
-        let match: RegExpExecArray | null;
-        while ((match = capture_regex.exec(bad_link))) {
-          captured_links.push(match[1]);
-        }
-      }
+    // links are found in and added to the vault's copy, it has to match the note
+    if (wasm_vault.get_contents(file_path).toString() != file_content) {
+      wasm_vault.update_file(file_path, file_content);
+      file.free();
+      file = wasm_vault.get_file(file_path);
+      file_links = link_finder.find_links(file);
     }
+    // the handle is not collected by the garbage collector
+    file.free();
+
+    // targets the note should not be linked to, declined links are added to it
+    let captured_links: string[] = wasm_vault
+      .get_frontmatter_list(file_path, "bad_links")
+      .map((bad_link) => bad_link.toString());
 
     // console.log("captured_links: ", captured_links);
 
     let accept_all: { [key: string]: boolean } = {};
     let decline_all: { [key: string]: boolean } = {};
 
+    // how many bytes the links added so far moved the text after them
+    let shift: number = 0;
     for (let link of file_links) {
-      let slice_start = byte_increament + link.get_start();
-      let slice_end = byte_increament + link.get_end();
       let source = link.get_source();
       let target = link.get_target();
       let break_loop = false;
@@ -582,32 +631,22 @@ export default class RustPlugin extends Plugin {
         continue;
       }
 
-      let encoder = new TextEncoder();
-      let decoder = new TextDecoder();
-      let byteArray = encoder.encode(file_content);
-
-      let content_as_bytes = encoder.encode(file_content);
-
-      let slicedArray = byteArray.slice(slice_start, slice_end);
-      let slice_str = decoder.decode(slicedArray);
-      let replace_str = `[[${target}|${slice_str}]]`;
-
-      let replaced_as_bytes = encoder.encode(replace_str);
-      let increment = replaced_as_bytes.length - (slice_end - slice_start);
-
+      // the link as the vault would write it, nothing is changed yet
+      let edit = wasm_vault.preview_link(link, shift);
+      if (!edit) {
+        remaining_links.push(link.serialize());
+        continue;
+      }
       let color = this.settings.color;
+      let escaped = edit.get_replacement().replace(/[\[\]|]/g, "\\$&");
       let colored_content =
-        decoder.decode(content_as_bytes.slice(0, slice_start)) +
-        `<span style="color:${color}">\\[\\[${target}\\|${slice_str}\\]\\]</span>` +
-        decoder.decode(content_as_bytes.slice(slice_end));
-      let new_content: string =
-        decoder.decode(content_as_bytes.slice(0, slice_start)) +
-        `[[${target}|${slice_str}]]` +
-        decoder.decode(content_as_bytes.slice(slice_end));
+        file_content.slice(0, edit.get_utf16_start()) +
+        `<span style="color:${color}">${escaped}</span>` +
+        file_content.slice(edit.get_utf16_end());
+      edit.free();
 
       let file_change: FileChange = {
         file_path: source,
-        new_content: new_content,
         colored_content: colored_content,
       };
       if (perform_link) {
@@ -618,11 +657,8 @@ export default class RustPlugin extends Plugin {
           decline_all[source] = false;
         }
         if (accept_all[source]) {
-          let tfile: TFile = tfilemap[source];
-          file_content = new_content;
-
-          await this.app.vault.modify(tfile, new_content);
-          byte_increament += increment;
+          shift += await this.write_link(wasm_vault, link, shift, tfilemap[source]);
+          file_content = wasm_vault.get_contents(source).toString();
           continue;
         }
         if (decline_all[source]) {
@@ -647,17 +683,19 @@ export default class RustPlugin extends Plugin {
         }
 
         if (modal.accepted) {
-          let tfile: TFile = tfilemap[source];
-          file_content = new_content;
-
-          await this.app.vault.modify(tfile, new_content);
-          byte_increament += increment;
+          shift += await this.write_link(wasm_vault, link, shift, tfilemap[source]);
+          file_content = wasm_vault.get_contents(source).toString();
         }
 
         if (modal.declined) {
           let json_link_serialized = link.serialize();
           remaining_links.push(json_link_serialized);
         }
+        if (modal.declined && !modal.all_declined) {
+          shift += await this.decline_link(wasm_vault, target, tfilemap[source]);
+          file_content = wasm_vault.get_contents(source).toString();
+          captured_links.push(target);
+        }
       } else {
         let json_link_serialized = link.serialize();
         remaining_links.push(json_link_serialized);
@@ -670,6 +708,48 @@ export default class RustPlugin extends Plugin {
     };
     await this.write_cache();
   }
+  // adds the link to the vault's copy of the note and writes the note from that copy,
+  // returns how many bytes longer the note got
+  async write_link(
+    wasm_vault: plugin.JsVault,
+    link: plugin.JsLink,
+    shift: number,
+    tfile: TFile,
+  ): Promise<number> {
+    let edit = wasm_vault.add_link(link, shift);
+    if (!edit) {
+      return 0;
+    }
+    let delta: number = edit.get_delta();
+    edit.free();
+    await this.app.vault.modify(tfile, wasm_vault.get_contents(tfile.path).toString());
+    return delta;
+  }
+  // adds the target to the note's bad_links so it is not suggested again, in the vault's
+  // copy and in the note, returns how many bytes longer the note got
+  async decline_link(
+    wasm_vault: plugin.JsVault,
+    target: string,
+    tfile: TFile,
+  ): Promise<number> {
+    let delta: number = 0;
+    let edits = wasm_vault.add_frontmatter_item(tfile.path, "bad_links", target);
+    // the edits are in offsets of the note before any of them, the last is made first
+    for (let edit of edits.reverse()) {
+      wasm_vault.edit_file(
+        tfile.path,
+        edit.get_start(),
+        edit.get_end(),
+        edit.get_replacement(),
+      );
+      delta += edit.get_delta();
+      edit.free();
+    }
+    if (delta != 0) {
+      await this.app.vault.modify(tfile, wasm_vault.get_contents(tfile.path).toString());
+    }
+    return delta;
+  }
   async write_cache() {
     if (!this.app.vault.adapter.exists(this.cache_path)) {
       await this.app.vault.adapter.write(this.cache_path, "{}");
@@ -774,12 +854,26 @@ class ParseErrorModal extends Modal {
   plugin: RustPlugin;
   paths: string[];
   errors: string[];
-
-  constructor(plugin: RustPlugin, paths: string[], errors: string[]) {
+  // vault path of each entry that may be fixed automatically, "" otherwise
+  fixable: string[];
+  tfilemap: { [key: string]: TFile };
+  wasm_vault: plugin.JsVault;
+
+  constructor(
+    plugin: RustPlugin,
+    paths: string[],
+    errors: string[],
+    fixable: string[],
+    tfilemap: { [key: string]: TFile },
+    wasm_vault: plugin.JsVault,
+  ) {
     super(plugin.app);
     this.plugin = plugin;
     this.paths = paths;
     this.errors = errors;
+    this.fixable = fixable;
+    this.tfilemap = tfilemap;
+    this.wasm_vault = wasm_vault;
   }
 
   async onOpen() {
@@ -787,9 +881,35 @@ class ParseErrorModal extends Modal {
     for (let index = 0; index < this.paths.length; index++) {
       let path = this.paths[index];
       let error = this.errors[index];
+      let fixable_path = this.fixable[index];
       contentEl.createEl("h2", { text: path });
       contentEl.createEl("p", { text: error });
+      if (fixable_path) {
+        let button = contentEl.createEl("button", { text: "Fix and re-scan" });
+        button.onclick = async () => {
+          if (!(await this.fix_file(fixable_path))) {
+            button.disabled = true;
+            return;
+          }
+          this.close();
+          await this.plugin.get_invalid_notes();
+        };
+      }
+    }
+  }
+
+  // false when no fix was found
+  async fix_file(path: string): Promise<boolean> {
+    let tfile = this.tfilemap[path];
+    let fixes = this.wasm_vault.get_fixes(path);
+    if (fixes.length == 0) {
+      new Notice(`No automatic fix found for ${path}`);
+      return false;
     }
+    let explanations = fixes.map((fix) => fix.get_explanation());
+    await this.plugin.app.vault.modify(tfile, this.wasm_vault.fix_file(path, fixes));
+    new Notice(explanations.join("\n"));
+    return true;
   }
 
   onClose() {
@@ -832,6 +952,45 @@ class RustPluginSettingTab extends PluginSettingTab {
           await this.plugin.saveSettings();
         }),
       );
+    new Setting(containerEl)
+      .setName("Excluded callouts")
+      .setDesc(
+        'Comma separated callout types that are never linked, e.g. "quote, cite"',
+      )
+      .addText((text) =>
+        text
+          .setValue(this.plugin.settings.excludedCallouts)
+          .onChange(async (value) => {
+            this.plugin.settings.excludedCallouts = value;
+            await this.plugin.saveSettings();
+          }),
+      );
+    new Setting(containerEl)
+      .setName("Link inside HTML")
+      .setDesc(
+        "Allow links in the text between HTML tags, tags, attributes and comments are never linked",
+      )
+      .addToggle((toggle) =>
+        toggle
+          .setValue(this.plugin.settings.linkHtmlText)
+          .onChange(async (value) => {
+            this.plugin.settings.linkHtmlText = value;
+            await this.plugin.saveSettings();
+          }),
+      );
+    new Setting(containerEl)
+      .setName("Link only field values")
+      .setDesc(
+        "In Dataview fields like status:: done, link the value and never the key",
+      )
+      .addToggle((toggle) =>
+        toggle
+          .setValue(this.plugin.settings.linkOnlyFieldValues)
+          .onChange(async (value) => {
+            this.plugin.settings.linkOnlyFieldValues = value;
+            await this.plugin.saveSettings();
+          }),
+      );
     new Setting(containerEl)
       .setName("Include paths")
       .setDesc("Paths to include in linking, default is all files in the vault")